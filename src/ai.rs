@@ -0,0 +1,481 @@
+use std::collections::HashSet;
+use cgmath::{Point2, Vector2, InnerSpace};
+use rand::Rng;
+
+use entities::{Beetle, Beetles, Entity, Id};
+use game::{Game, Command};
+use formation::{FormationShape, FormationState};
+use colony::{GenomeSource, ProductionOrder};
+use food_ecology::FoodEcology;
+use team::PLAYER_TEAM_ID;
+use game_match::VictoryCondition;
+use utils::{Color, Positioned, seconds_to_ticks};
+
+// retreating beetles go back to work once they've healed this much
+const HEALED_FRACTION: f32 = 0.9;
+// close enough to the base to count as home
+const BASE_RANGE_UNITS: f32 = 64.0;
+// the army gathers this far from its base, on the way to the enemy, before
+// attacking
+const STAGING_DISTANCE_UNITS: f32 = 150.0;
+// formations that can't all get to the staging point don't hold the attack
+// up forever
+const MAX_MUSTER_SECONDS: f32 = 20.0;
+// attackers are only sent somewhere new once the enemy has moved this far
+const RETARGET_DISTANCE_UNITS: f32 = 100.0;
+// where the bases go in a match, as fractions of the map
+const PLAYER_BASE_POSITION: (f32, f32) = (0.15, 0.5);
+const COMPUTER_BASE_POSITION: (f32, f32) = (0.85, 0.5);
+// the computer's army starts this close to its base
+const ARMY_SPREAD_UNITS: f32 = 100.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+// Everything a difficulty level changes about how the computer plays
+struct Settings {
+    // time between decisions
+    think_seconds: f32,
+    // share of the team sent to gather food, taken from the worst fighters
+    harvester_fraction: f32,
+    // beetles below this fraction of their health go home to heal. Zero
+    // never retreats.
+    retreat_health_fraction: f32,
+    // how much stronger than the enemy the army has to be before it attacks
+    attack_strength_ratio: f32,
+    // gather into a formation before attacking rather than trickling in
+    formations: bool,
+    genome_source: GenomeSource,
+}
+
+impl Difficulty {
+    fn settings(&self) -> Settings {
+        match *self {
+            Difficulty::Easy => Settings {
+                think_seconds: 2.0,
+                harvester_fraction: 0.5,
+                retreat_health_fraction: 0.0,
+                attack_strength_ratio: 1.5,
+                formations: false,
+                genome_source: GenomeSource::BreedingPool,
+            },
+            Difficulty::Normal => Settings {
+                think_seconds: 1.0,
+                harvester_fraction: 0.4,
+                retreat_health_fraction: 0.25,
+                attack_strength_ratio: 1.2,
+                formations: true,
+                genome_source: GenomeSource::BreedingPool,
+            },
+            Difficulty::Hard => Settings {
+                think_seconds: 0.25,
+                harvester_fraction: 0.3,
+                retreat_health_fraction: 0.4,
+                attack_strength_ratio: 1.0,
+                formations: true,
+                genome_source: GenomeSource::Crossover,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Plan {
+    // guard the base and wait to get strong enough
+    Defend,
+    // marching to the staging point in formation
+    Muster {
+        group_id: Id,
+        target: Point2<f32>,
+        ticks: u32,
+    },
+    Attack {
+        target: Point2<f32>,
+    },
+}
+
+// A computer player in charge of one team. Runs once a tick, but only makes
+// decisions as often as its difficulty allows.
+pub struct AiPlayer {
+    pub team_id: Id,
+    pub difficulty: Difficulty,
+    ticks_until_think: u32,
+    // beetles on their way home to heal, which nothing else gets to order
+    // about
+    retreating: HashSet<Id>,
+    plan: Plan,
+}
+
+impl AiPlayer {
+    pub fn new(team_id: Id, difficulty: Difficulty) -> AiPlayer {
+        AiPlayer {
+            team_id,
+            difficulty,
+            ticks_until_think: 0,
+            retreating: HashSet::new(),
+            plan: Plan::Defend,
+        }
+    }
+
+    pub fn update(&mut self, game: &mut Game) {
+        if self.ticks_until_think > 0 {
+            self.ticks_until_think -= 1;
+            return;
+        }
+
+        let settings = self.difficulty.settings();
+        self.ticks_until_think = seconds_to_ticks(settings.think_seconds);
+
+        let base = self.home_base(game);
+
+        self.keep_hatching(game, &settings);
+        self.retreat_damaged(game, &settings, base);
+        let army = self.assign_harvesters(game, &settings);
+        self.command_army(game, &settings, &army, base);
+    }
+
+    // The first base the team still owns
+    fn home_base(&self, game: &Game) -> Option<(Id, Point2<f32>)> {
+        let field_state = &game.field_state;
+
+        field_state.teams.get(self.team_id)
+            .and_then(|team| {
+                team.home_base_ids.iter()
+                    .filter_map(|id| field_state.get_home_bases().get(id))
+                    .next()
+            })
+            .map(|home_base| (home_base.get_id(), home_base.get_position()))
+    }
+
+    // Every base spends whatever food it has on more beetles
+    fn keep_hatching(&self, game: &mut Game, settings: &Settings) {
+        let home_base_ids = match game.field_state.teams.get(self.team_id) {
+            Some(team) => team.home_base_ids.clone(),
+            None => return,
+        };
+
+        for id in home_base_ids {
+            let idle = game.field_state.get_home_bases().get(&id)
+                .map(|home_base| home_base.production.queue.is_empty())
+                .unwrap_or(false);
+
+            if idle {
+                game.set_base_production(id, Some(ProductionOrder {
+                    genome_source: settings.genome_source,
+                    remaining: None,
+                }), false);
+            }
+        }
+    }
+
+    fn retreat_damaged(
+            &mut self, game: &mut Game, settings: &Settings,
+            base: Option<(Id, Point2<f32>)>) {
+
+        let beetles = &mut game.field_state.beetles;
+
+        // anyone who died on the way doesn't need to be tracked anymore
+        self.retreating.retain(|id| beetles.contains_key(id));
+
+        let base_position = match base {
+            Some((_, position)) => position,
+            // nowhere to heal
+            None => {
+                self.retreating.clear();
+                return;
+            },
+        };
+
+        let team_id = self.team_id;
+        for beetle in beetles.values_mut().filter(|beetle| beetle.team_id == team_id) {
+            let health = beetle.health as f32 / beetle.max_health() as f32;
+
+            if self.retreating.contains(&beetle.id) {
+                if health >= HEALED_FRACTION {
+                    self.retreating.remove(&beetle.id);
+                }
+                else if beetle.current_command != Command::ReturnToBase &&
+                        (beetle.position - base_position).magnitude() > BASE_RANGE_UNITS {
+                    beetle.set_command(Command::ReturnToBase);
+                }
+            }
+            else if health < settings.retreat_health_fraction {
+                self.retreating.insert(beetle.id);
+                beetle.set_command(Command::ReturnToBase);
+            }
+        }
+    }
+
+    // Sends the worst fighters off to forage and returns the ids of
+    // everyone left to fight
+    fn assign_harvesters(&self, game: &mut Game, settings: &Settings) -> Vec<Id> {
+        let no_food = game.field_state.no_food_left() &&
+            !game.field_state.food_ecology.spawns_food();
+
+        let beetles = &mut game.field_state.beetles;
+
+        let mut team: Vec<&mut Beetle> = beetles.values_mut()
+            .filter(|beetle| {
+                beetle.team_id == self.team_id && !self.retreating.contains(&beetle.id)
+            })
+            .collect();
+
+        // ranked on what they're built for rather than how hurt they are, so
+        // a fighter taking a few hits doesn't get swapped for a harvester
+        team.sort_by(|a, b| {
+            potential(a).partial_cmp(&potential(b)).unwrap()
+                .then(a.id.cmp(&b.id))
+        });
+
+        let num_harvesters = if no_food {
+            0
+        }
+        else {
+            (team.len() as f32 * settings.harvester_fraction).round() as usize
+        };
+
+        let mut army = Vec::new();
+        for (index, beetle) in team.into_iter().enumerate() {
+            if index < num_harvesters {
+                if beetle.current_command != Command::Forage {
+                    beetle.set_command(Command::Forage);
+                }
+            }
+            else {
+                army.push(beetle.id);
+            }
+        }
+
+        army
+    }
+
+    fn command_army(
+            &mut self, game: &mut Game, settings: &Settings, army: &[Id],
+            base: Option<(Id, Point2<f32>)>) {
+
+        let enemy_strength: f32 = game.field_state.beetles.values()
+            .filter(|beetle| game.field_state.teams.are_hostile(self.team_id, beetle.team_id))
+            .map(strength)
+            .sum();
+
+        let our_strength: f32 = army.iter()
+            .filter_map(|id| game.field_state.beetles.get(id))
+            .map(strength)
+            .sum();
+
+        let target = self.find_target(game, army);
+
+        let strong_enough = target.is_some() && our_strength > 0.0 &&
+            our_strength >= enemy_strength * settings.attack_strength_ratio;
+
+        // outmatched, so fall back and wait for reinforcements. An army
+        // that's already fighting sees it through unless it's lost half of
+        // what it needs.
+        let keep_attacking = target.is_some() &&
+            our_strength * 2.0 >= enemy_strength * settings.attack_strength_ratio;
+
+        self.plan = match (self.plan.clone(), target) {
+            (Plan::Defend, Some(target)) if strong_enough => {
+                match base {
+                    Some((_, base_position)) if settings.formations => {
+                        let staging = staging_point(base_position, target);
+                        let group_id = game.create_formation_for(
+                            army, FormationShape::Wedge, None, None);
+                        game.move_formation(group_id, staging);
+
+                        Plan::Muster { group_id, target, ticks: 0 }
+                    },
+                    _ => Plan::Attack { target },
+                }
+            },
+            (Plan::Muster { group_id, target: _, ticks }, Some(target)) if keep_attacking => {
+                let ticks = ticks + seconds_to_ticks(settings.think_seconds).max(1);
+
+                let arrived = match game.field_state.formation_groups.get(&group_id) {
+                    Some(group) => {
+                        group.target.is_none() && group.state == FormationState::Holding
+                    },
+                    None => true,
+                };
+
+                if arrived || ticks > seconds_to_ticks(MAX_MUSTER_SECONDS) {
+                    Plan::Attack { target }
+                }
+                else {
+                    Plan::Muster { group_id, target, ticks }
+                }
+            },
+            (Plan::Attack { target: old_target }, Some(target)) if keep_attacking => {
+                if (target - old_target).magnitude() > RETARGET_DISTANCE_UNITS {
+                    Plan::Attack { target }
+                }
+                else {
+                    Plan::Attack { target: old_target }
+                }
+            },
+            _ => Plan::Defend,
+        };
+
+        let formation_id = match self.plan {
+            Plan::Muster { group_id, .. } => Some(group_id),
+            _ => None,
+        };
+
+        for id in army.iter() {
+            let beetle = match game.field_state.beetles.get_mut(id) {
+                Some(beetle) => beetle,
+                None => continue,
+            };
+
+            match self.plan {
+                Plan::Defend => {
+                    let command = match base {
+                        Some((base_id, _)) => Command::Guard { target_id: base_id },
+                        None => Command::HoldPosition,
+                    };
+
+                    if beetle.current_command != command {
+                        beetle.set_command(command);
+                    }
+                },
+                Plan::Muster { .. } => {
+                    // stragglers that missed the formation wait at home
+                    let in_formation = match beetle.current_command {
+                        Command::HoldFormation { group_id } => Some(group_id) == formation_id,
+                        _ => false,
+                    };
+
+                    if !in_formation {
+                        if let Some((base_id, _)) = base {
+                            let command = Command::Guard { target_id: base_id };
+                            if beetle.current_command != command {
+                                beetle.set_command(command);
+                            }
+                        }
+                    }
+                },
+                Plan::Attack { target } => {
+                    let attacking = match beetle.current_command {
+                        Command::AttackMove { position } => position == target,
+                        Command::Wander => true,
+                        _ => false,
+                    };
+
+                    if !attacking {
+                        beetle.set_command(Command::AttackMove { position: target });
+                        // keep hunting once it gets there
+                        beetle.queue_command(Command::Wander);
+                    }
+                },
+            }
+        }
+    }
+
+    // The enemy beetle closest to the middle of the army, or failing that
+    // the closest enemy base
+    fn find_target(&self, game: &Game, army: &[Id]) -> Option<Point2<f32>> {
+        let field_state = &game.field_state;
+
+        let positions: Vec<Point2<f32>> = army.iter()
+            .filter_map(|id| field_state.beetles.get(id))
+            .map(|beetle| beetle.position)
+            .collect();
+
+        if positions.is_empty() {
+            return None;
+        }
+
+        let sum = positions.iter()
+            .fold(Vector2::new(0.0, 0.0), |sum, position| sum + Vector2::new(position.x, position.y));
+        let centre = Point2::new(0.0, 0.0) + sum / positions.len() as f32;
+
+        let distance = |position: &Point2<f32>| (position - centre).magnitude();
+
+        let enemy = field_state.beetles.values()
+            .filter(|beetle| field_state.teams.are_hostile(self.team_id, beetle.team_id))
+            .map(|beetle| beetle.position)
+            .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap());
+
+        if enemy.is_some() {
+            return enemy;
+        }
+
+        field_state.get_home_bases().values()
+            .filter(|home_base| {
+                field_state.teams.are_hostile(self.team_id, home_base.get_team_id())
+            })
+            .map(|home_base| home_base.get_position())
+            .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+    }
+}
+
+// Rough fighting worth of a beetle as it is right now
+fn strength(beetle: &Beetle) -> f32 {
+    beetle.attack_power() as f32 * beetle.health as f32
+}
+
+// Fighting worth of a beetle's design, however hurt it is
+fn potential(beetle: &Beetle) -> f32 {
+    beetle.attack_power() as f32 * beetle.max_health() as f32
+}
+
+fn staging_point(base_position: Point2<f32>, target: Point2<f32>) -> Point2<f32> {
+    let travel = target - base_position;
+    let distance = travel.magnitude();
+
+    if distance <= STAGING_DISTANCE_UNITS {
+        return base_position;
+    }
+
+    base_position + travel / distance * STAGING_DISTANCE_UNITS
+}
+
+// Sets up a match between whoever's playing through the browser and a
+// computer team that starts out with army. Both sides get a home base, and
+// food is put on the map if there isn't any. Where the army and food start
+// out is up to rng. Returns the computer's team id.
+pub fn setup_match<R: Rng>(
+        game: &mut Game, army: Beetles, difficulty: Difficulty, rng: &mut R) -> Id {
+
+    let team_id = game.team_for("Computer", Color { r: 200, g: 40, b: 40, a: 255 });
+
+    let width = game.field_state.world.width;
+    let height = game.field_state.world.height;
+
+    let player_has_base = game.field_state.get_home_bases().values()
+        .any(|home_base| home_base.get_team_id() == PLAYER_TEAM_ID);
+    if !player_has_base {
+        game.add_home_base(PLAYER_BASE_POSITION.0 * width, PLAYER_BASE_POSITION.1 * height);
+    }
+
+    let base_position = Point2::new(
+        COMPUTER_BASE_POSITION.0 * width, COMPUTER_BASE_POSITION.1 * height);
+    let base_id = game.add_home_base(base_position.x, base_position.y);
+    game.set_home_base_team(base_id, team_id);
+
+    for (_, mut beetle) in army.into_iter() {
+        beetle.position = Point2::new(
+            base_position.x + rng.gen_range(-ARMY_SPREAD_UNITS, ARMY_SPREAD_UNITS),
+            base_position.y + rng.gen_range(-ARMY_SPREAD_UNITS, ARMY_SPREAD_UNITS));
+        beetle.direction = Vector2::new(-1.0, 0.0);
+        beetle.set_command(Command::Stop);
+
+        let id = game.add_beetle(beetle);
+        game.assign_team(id, team_id);
+    }
+
+    if game.field_state.no_food_left() {
+        game.set_food_ecology(FoodEcology::scarce(width, height), rng);
+    }
+
+    game.ai_players.retain(|ai_player| ai_player.team_id != team_id);
+    game.ai_players.push(AiPlayer::new(team_id, difficulty));
+
+    game.start_match(vec![VictoryCondition::LastTeamStanding, VictoryCondition::CaptureBase]);
+
+    team_id
+}
@@ -1,13 +1,16 @@
 use cgmath::{Point2, Vector2, InnerSpace, Rotation, Rotation2, Rad, Basis2};
-use game::{State, Command, Action};
-use entities::{
-    FoodSource, FoodSources, Entity, HomeBases, HomeBase, HasFood, find_closest
-};
-use std::collections::HashMap;
+use rand::{Rng, thread_rng};
+use game::{State, Command, Action, FieldState};
+use entities::{FoodSource, Entity, HomeBase, HasFood};
+use std::collections::{HashMap, VecDeque};
 use beetle_genome::{BeetleGenome, BeetleGeneIndex as Gene};
 use utils::{
-    convert_value_for_sim_period, MIN_SPEED_UNITS_PER_SECOND, Color, Positioned
+    convert_value_for_sim_period, seconds_to_ticks, MIN_SPEED_UNITS_PER_SECOND,
+    Color, Positioned
 };
+use status_effect;
+use status_effect::{StatusEffect, StatusEffectKind};
+use food_ecology::FoodType;
 
 //const MAX_QUICKNESS: f32 = 10.0;
 //const MAX_STRENGTH: f32 = 10.0;
@@ -18,10 +21,43 @@ const MIN_BODY_WIDTH_UNITS: f32 = MIN_BODY_LENGTH_UNITS;
 const FOOD_SIZE_UNITS: f32 = MAX_BODY_LENGTH_UNITS / 4.0;
 const MAX_HEALTH: f32 = 200.0;
 const MIN_HEALTH: f32 = 10.0;
+// how much energy the smallest and biggest bodies can hold, see
+// metabolism.rs
+const MIN_ENERGY: f32 = 60.0;
+const MAX_ENERGY: f32 = 140.0;
 const MAX_ATTACK: f32 = 50.0;
 const MIN_ATTACK: f32 = 1.0;
-//const MAX_CARAPACE_DENSITY: f32 = 10.0;
-//const MAX_MASS: f32 = MAX_SIZE * MAX_CARAPACE_DENSITY;
+// even the flimsiest carapace weighs something
+const MIN_CARAPACE_DENSITY: f32 = 0.2;
+// fraction of incoming damage the densest possible carapace soaks up
+const MAX_ARMOUR: f32 = 0.5;
+// time between attacks for the quickest and slowest beetles
+const MIN_ATTACK_COOLDOWN_SECONDS: f32 = 0.25;
+const MAX_ATTACK_COOLDOWN_SECONDS: f32 = 1.0;
+// how far past the end of its body a beetle can bite, on top of a share of
+// its body length
+const MIN_REACH_UNITS: f32 = 2.0;
+const REACH_PER_BODY_LENGTH: f32 = 0.25;
+const INTERACTION_DISTANCE_UNITS: f32 = 5.0;
+pub const MAX_COLLISION_RADIUS_UNITS: f32 = MAX_BODY_LENGTH_UNITS / 2.0;
+const MIN_SMELL_RANGE_UNITS: f32 = 50.0;
+const MAX_SMELL_RANGE_UNITS: f32 = 400.0;
+// wandering beetles aim this far ahead, and turn up to WANDER_MAX_TURN
+// radians either way each tick
+const WANDER_LOOKAHEAD_UNITS: f32 = 40.0;
+const WANDER_MAX_TURN: f32 = 0.5;
+// wanderers this close to the edge of the map head back toward the middle
+const WANDER_EDGE_MARGIN_UNITS: f32 = 50.0;
+// guards fight enemies this close to whatever they're guarding
+const GUARD_RADIUS_UNITS: f32 = 100.0;
+// and otherwise stay at least this close to it
+const GUARD_DISTANCE_UNITS: f32 = 50.0;
+const FOLLOW_GAP_UNITS: f32 = 20.0;
+// stopped beetles only fight enemies this close to where they were left, and
+// walk back once they've strayed further than half of it
+const STOP_LEASH_UNITS: f32 = 60.0;
+// foragers can pick up pheromone trails this far away
+const PHEROMONE_SENSE_UNITS: f32 = 48.0;
 
 pub type Id = i32;
 pub type Beetles = HashMap<Id, Beetle>;
@@ -32,36 +68,62 @@ pub struct Beetle {
     pub position: Point2<f32>,
     pub direction: Vector2<f32>,
     pub angle: Rad<f32>,
-    smell_range: i32,
     max_speed_units_per_tick: f32,
     rotation_radians_per_tick: Rad<f32>,
-    num_eaten: i32,
+    pub num_eaten: i32,
+    pub energy: f32,
+    // how far the beetle walked this tick, so it can be charged for it
+    pub distance_moved: f32,
     pub current_state: State,
     pub current_command: Command,
+    // orders to carry out once current_command is done
+    pub command_queue: VecDeque<Command>,
     pub health: i32,
     pub selected: bool,
     pub genome: BeetleGenome,
     pub color: Color,
     pub team_id: Id,
     pub food_collected: i32,
+    // pieces of food, all of carried_food_type
     pub food_carrying: i32,
+    pub carried_food_type: FoodType,
     pub damage_inflicted: i32,
+    pub kills: i32,
+    pub ticks_since_hurt: u32,
+    // healing that doesn't add up to a whole point of health yet, see
+    // regeneration.rs
+    pub pending_healing: f32,
+    // ticks until the beetle can attack again
+    pub attack_cooldown: u32,
+    pub status_effects: Vec<StatusEffect>,
+    // how much pheromone the beetle lays while carrying food, see
+    // pheromone.rs
+    pub trail_strength: f32,
+    // remaining waypoints on the way to path_goal, see pathfinding.rs
+    pub path: Vec<Point2<f32>>,
+    pub path_goal: Option<Point2<f32>>,
+    pub ticks_since_waypoint: u32,
+    // where the beetle was standing when it was stopped, see
+    // update_stop_position
+    pub stop_position: Option<Point2<f32>>,
 }
 
 impl Beetle {
     pub fn new() -> Beetle {
         
-        Beetle {
+        let mut beetle = Beetle {
             id: 0,
             position: Point2::new(0.0, 0.0),
             direction: Vector2::new(0.0, 1.0),
             angle: Rad(0.0),
-            smell_range: 5,
             max_speed_units_per_tick: 0.0,
             rotation_radians_per_tick: Rad(0.10),
             num_eaten: 0,
+            energy: 0.0,
+            distance_moved: 0.0,
             current_state: State::Idle,
             current_command: Command::Stop,
+            command_queue: VecDeque::new(),
             health: 100, 
             selected: false,
             genome: BeetleGenome::new(),
@@ -69,21 +131,37 @@ impl Beetle {
             team_id: 0,
             food_collected: 0,
             food_carrying: 0,
+            carried_food_type: FoodType::new(),
             damage_inflicted: 0,
-        }
+            kills: 0,
+            ticks_since_hurt: 0,
+            pending_healing: 0.0,
+            attack_cooldown: 0,
+            status_effects: Vec::new(),
+            trail_strength: 0.0,
+            path: Vec::new(),
+            path_goal: None,
+            ticks_since_waypoint: 0,
+            stop_position: None,
+        };
+
+        beetle.energy = beetle.max_energy();
+        beetle
     }
 
     pub fn speed(&self) -> f32 {
-        let speed_ratio =
-            self.genome.get_gene(Gene::Quickness) * 0.25 +
-            self.genome.get_gene(Gene::Strength) * 0.25 +
-            (1.0 - self.size()) * 0.25 + 
-            (1.0 - self.genome.get_gene(Gene::CarapaceDensity)) * 0.25;
-
         let min_speed = convert_value_for_sim_period(
                 MIN_SPEED_UNITS_PER_SECOND);
-        let speed = (speed_ratio * (self.max_speed_units_per_tick - min_speed)) + min_speed;
-        return speed;
+        let speed = (self.speed_ratio() * (self.max_speed_units_per_tick - min_speed)) + min_speed;
+        return speed * status_effect::speed_multiplier(&self.status_effects);
+    }
+
+    // Where this beetle's speed falls between the min and max speeds, 0.0-1.0
+    pub fn speed_ratio(&self) -> f32 {
+        self.genome.get_gene(Gene::Quickness) * 0.25 +
+            self.genome.get_gene(Gene::Strength) * 0.25 +
+            (1.0 - self.size()) * 0.25 + 
+            (1.0 - self.genome.get_gene(Gene::CarapaceDensity)) * 0.25
     }
 
     pub fn size(&self) -> f32 {
@@ -118,35 +196,147 @@ impl Beetle {
         return ((health_ratio * (MAX_HEALTH - MIN_HEALTH)) + MIN_HEALTH) as i32;
     }
 
+    pub fn max_energy(&self) -> f32 {
+        self.size() * (MAX_ENERGY - MIN_ENERGY) + MIN_ENERGY
+    }
+
+    // Damage from the bite itself. Venom is dealt with separately, see
+    // combat.rs
     pub fn attack_power(&self) -> i32 {
         let attack_ratio =
-            self.genome.get_gene(Gene::MandibleSharpness) * 0.30 +
-            self.genome.get_gene(Gene::Venomosity) * 0.30 +
-            self.genome.get_gene(Gene::Strength) * 0.20 +
-            self.size() * 0.10 +
+            self.genome.get_gene(Gene::MandibleSharpness) * 0.45 +
+            self.genome.get_gene(Gene::Strength) * 0.30 +
+            self.size() * 0.15 +
             self.genome.get_gene(Gene::Quickness) * 0.10;
 
-        return ((attack_ratio * (MAX_ATTACK - MIN_ATTACK)) + MIN_ATTACK) as i32;
+        let attack = (attack_ratio * (MAX_ATTACK - MIN_ATTACK)) + MIN_ATTACK;
+        return (attack * status_effect::attack_multiplier(&self.status_effects)) as i32;
+    }
+
+    pub fn rotation_per_tick(&self) -> Rad<f32> {
+        self.rotation_radians_per_tick *
+            status_effect::turn_multiplier(&self.status_effects)
+    }
+
+    pub fn add_status_effect(&mut self, effect: StatusEffect) {
+        status_effect::add(&mut self.status_effects, effect);
+    }
+
+    pub fn remove_status_effect(&mut self, kind: StatusEffectKind) {
+        status_effect::remove(&mut self.status_effects, kind);
+    }
+
+    pub fn has_status_effect(&self, kind: StatusEffectKind) -> bool {
+        self.status_effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    pub fn attack_cooldown_ticks(&self) -> u32 {
+        let cooldown_range = MAX_ATTACK_COOLDOWN_SECONDS - MIN_ATTACK_COOLDOWN_SECONDS;
+        let seconds = MAX_ATTACK_COOLDOWN_SECONDS -
+            self.genome.get_gene(Gene::Quickness) * cooldown_range;
+
+        seconds_to_ticks(seconds)
+    }
+
+    // How far past the end of its body the beetle's mandibles reach
+    pub fn reach(&self) -> f32 {
+        self.body_length() * REACH_PER_BODY_LENGTH + MIN_REACH_UNITS
+    }
+
+    // Fraction of incoming damage the carapace soaks up
+    pub fn armour(&self) -> f32 {
+        self.genome.get_gene(Gene::CarapaceDensity) * MAX_ARMOUR
+    }
+
+    // How far away this beetle can sense food and enemies
+    pub fn smell_range(&self) -> f32 {
+        let range = MAX_SMELL_RANGE_UNITS - MIN_SMELL_RANGE_UNITS;
+
+        (self.genome.get_gene(Gene::SmellSensitivity) * range) +
+            MIN_SMELL_RANGE_UNITS
     }
 
     pub fn carrying_capacity(&self) -> i32 {
         (self.body_length() / FOOD_SIZE_UNITS).floor() as i32
     }
 
-    //pub fn mass(&self) -> f32 {
-    //    ((self.genome.size() * MAX_SIZE) *
-    //    (self.genome.carapace_density() * MAX_CARAPACE_DENSITY)) /
-    //    MAX_MASS
-    //}
+    // How many pieces of food_type fit in the beetle's carrying capacity.
+    // Always at least one, however bulky.
+    pub fn carrying_capacity_for(&self, food_type: FoodType) -> i32 {
+        (self.carrying_capacity() / food_type.carry_cost.max(1)).max(1)
+    }
+
+    // How full the beetle is, as a fraction of its carrying capacity
+    pub fn load(&self) -> f32 {
+        let load = self.food_carrying * self.carried_food_type.carry_cost;
+        load as f32 / self.carrying_capacity().max(1) as f32
+    }
+
+    // Relative to the heaviest possible beetle, 0.0-1.0
+    pub fn mass(&self) -> f32 {
+        let density_range = 1.0 - MIN_CARAPACE_DENSITY;
+        let density = (self.genome.get_gene(Gene::CarapaceDensity) * density_range) +
+            MIN_CARAPACE_DENSITY;
+
+        self.size() * density
+    }
+
+    // Beetles are treated as circles that cover the length of the body
+    pub fn collision_radius(&self) -> f32 {
+        self.body_length() / 2.0
+    }
 
+    // Replaces whatever the beetle was doing, including anything queued up
     pub fn set_command(&mut self, command: Command) {
         self.current_command = command;
+        self.command_queue.clear();
+    }
+
+    pub fn queue_command(&mut self, command: Command) {
+        if self.current_command == Command::Stop && self.command_queue.len() == 0 {
+            self.current_command = command;
+        }
+        else {
+            self.command_queue.push_back(command);
+        }
+    }
+
+    // Moves on to the next queued command, or stops if there isn't one
+    pub fn next_command(&mut self) {
+        self.current_command = self.command_queue.pop_front()
+            .unwrap_or(Command::Stop);
+    }
+
+    // Remembers where the beetle stopped, so it can find its way back after
+    // a fight. Called by the game every tick.
+    pub fn update_stop_position(&mut self) {
+        if self.current_command == Command::Stop {
+            if self.stop_position.is_none() {
+                self.stop_position = Some(self.position);
+            }
+        }
+        else {
+            self.stop_position = None;
+        }
     }
 
-    pub fn tick(
-            &self, beetles: &Beetles,
-            food_sources: &FoodSources,
-            home_bases: &HomeBases) -> Action {
+    pub fn clear_path(&mut self) {
+        self.path.clear();
+        self.path_goal = None;
+        self.ticks_since_waypoint = 0;
+    }
+
+    pub fn tick(&self, field_state: &FieldState) -> Action {
+
+        let beetles = &field_state.beetles;
+        let food_sources = field_state.get_food_sources();
+        let home_bases = field_state.get_home_bases();
+
+        if self.has_status_effect(StatusEffectKind::Stunned) {
+            return Action::Wait {
+                beetle_id: self.id,
+            };
+        }
 
         let action = match self.current_command {
             Command::Move{ position } => {
@@ -164,21 +354,61 @@ impl Beetle {
                     }
                 }
             },
-            Command::Interact { target_id } => {
-                if let Some(target) = beetles.get(&target_id) {
-                    if self.can_interact(target.position) {
-                        if target.team_id != self.team_id {
-                            Action::Attack{
-                                source_id: self.id,
-                                target_id: target_id,
-                                attack_power: self.attack_power(),
+            Command::GroupMove{ flow_field_id, position } => {
+                match field_state.flow_fields.get(&flow_field_id) {
+                    Some(flow_field) => {
+                        if flow_field.has_arrived(
+                                self, beetles, &field_state.spatial_index.beetles) {
+                            Action::Nothing {
+                                beetle_id: self.id,
                             }
                         }
                         else {
+                            Action::FollowFlowField {
+                                beetle_id: self.id,
+                                flow_field_id,
+                            }
+                        }
+                    },
+                    // shouldn't happen, but fall back on moving alone
+                    None => {
+                        if self.basically_here(position) {
                             Action::Nothing {
                                 beetle_id: self.id,
                             }
                         }
+                        else {
+                            Action::MoveToward {
+                                beetle_id: self.id,
+                                x: position.x,
+                                y: position.y,
+                            }
+                        }
+                    },
+                }
+            },
+            Command::HoldFormation{ group_id } => {
+                if field_state.formation_groups.contains_key(&group_id) {
+                    Action::HoldFormation {
+                        beetle_id: self.id,
+                        group_id,
+                    }
+                }
+                else {
+                    Action::Nothing {
+                        beetle_id: self.id,
+                    }
+                }
+            },
+            Command::Interact { target_id } => {
+                if let Some(target) = beetles.get(&target_id) {
+                    if field_state.teams.are_hostile(self.team_id, target.team_id) {
+                        self.engage(target)
+                    }
+                    else if self.can_interact_with_beetle(target) {
+                        Action::Nothing {
+                            beetle_id: self.id,
+                        }
                     }
                     else {
                         Action::MoveToward {
@@ -189,10 +419,14 @@ impl Beetle {
                     }
                 }
                 else if let Some(food_source) = food_sources.get(&target_id) {
-                    self.handle_collect_food_command(food_source, home_bases) 
+                    self.handle_collect_food_command(food_source, field_state) 
                 }
                 else if let Some(home_base) = home_bases.get(&target_id) {
-                    if self.food_carrying > 0 {
+                    // food only goes to bases on the beetle's side
+                    let friendly = field_state.teams.are_allied(
+                        self.team_id, home_base.get_team_id());
+
+                    if self.food_carrying > 0 && friendly {
                         self.take_food_to_base(&home_base)
                     }
                     else {
@@ -202,7 +436,7 @@ impl Beetle {
                     }
                 }
                 else if self.food_carrying > 0 {
-                    self.take_food_to_closest_base(home_bases)
+                    self.take_food_to_closest_base(field_state)
                 }
                 else {
                     Action::Nothing {
@@ -211,18 +445,225 @@ impl Beetle {
                 }
             },
             Command::HarvestClosestFood => {
-                if let Some(closest_food) = find_closest(self, food_sources) {
-                   self.handle_collect_food_command(closest_food, home_bases)
+                if let Some(closest_food) = field_state.find_closest_food_source(
+                        self.position, self.smell_range()) {
+                   self.handle_collect_food_command(closest_food, field_state)
+                }
+                else if field_state.no_food_left() {
+                    Action::Nothing {
+                        beetle_id: self.id,
+                    }
+                }
+                else if self.food_carrying > 0 {
+                    self.take_food_to_closest_base(field_state)
                 }
+                // there's food out there somewhere, go look for it
                 else {
+                    self.wander(field_state)
+                }
+            },
+            Command::Forage => {
+                if let Some(closest_food) = field_state.find_closest_food_source(
+                        self.position, self.smell_range()) {
+                   self.handle_collect_food_command(closest_food, field_state)
+                }
+                else if field_state.no_food_left() {
                     Action::Nothing {
                         beetle_id: self.id,
                     }
                 }
+                else if self.food_carrying > 0 {
+                    self.take_food_to_closest_base(field_state)
+                }
+                else if let Some(trail) = field_state.pheromones.strongest_nearby(
+                        self.position, PHEROMONE_SENSE_UNITS) {
+                    Action::MoveToward {
+                        beetle_id: self.id,
+                        x: trail.x,
+                        y: trail.y,
+                    }
+                }
+                else {
+                    self.wander(field_state)
+                }
+            },
+            Command::ReturnToBase => {
+                if self.food_carrying > 0 {
+                    self.take_food_to_closest_base(field_state)
+                }
+                else if let Some(closest_base) = field_state.find_closest_home_base(
+                        self.team_id, self.position) {
+                    if self.can_interact(closest_base.get_position()) {
+                        Action::Nothing {
+                            beetle_id: self.id,
+                        }
+                    }
+                    else {
+                        Action::MoveToward {
+                            beetle_id: self.id,
+                            x: closest_base.get_position().x,
+                            y: closest_base.get_position().y,
+                        }
+                    }
+                }
+                else {
+                    Action::Nothing {
+                        beetle_id: self.id,
+                    }
+                }
+            },
+            Command::AttackMove{ position } => {
+                if let Some(enemy) = field_state.find_closest_enemy(
+                        self.team_id, self.position, self.smell_range()) {
+                    self.engage(enemy)
+                }
+                else if self.basically_here(position) {
+                    Action::Nothing {
+                        beetle_id: self.id,
+                    }
+                }
+                else {
+                    Action::MoveToward {
+                        beetle_id: self.id,
+                        x: position.x,
+                        y: position.y,
+                    }
+                }
+            },
+            Command::Patrol{ from, to } => {
+                if let Some(enemy) = field_state.find_closest_enemy(
+                        self.team_id, self.position, self.smell_range()) {
+                    self.engage(enemy)
+                }
+                else if self.basically_here(to) {
+                    Action::UpdateCommand {
+                        beetle_id: self.id,
+                        command: Command::Patrol{ from: to, to: from },
+                    }
+                }
+                else {
+                    Action::MoveToward {
+                        beetle_id: self.id,
+                        x: to.x,
+                        y: to.y,
+                    }
+                }
+            },
+            Command::Guard{ target_id } => {
+                let guard_position = if let Some(target) = beetles.get(&target_id) {
+                    Some(target.position)
+                }
+                else if let Some(home_base) = home_bases.get(&target_id) {
+                    Some(home_base.get_position())
+                }
+                else {
+                    None
+                };
+
+                match guard_position {
+                    Some(guard_position) => {
+                        let enemy = field_state.find_closest_enemy(
+                                self.team_id, guard_position, GUARD_RADIUS_UNITS)
+                            .and_then(|enemy| {
+                                if self.can_smell(enemy.position) {
+                                    Some(enemy)
+                                }
+                                else {
+                                    None
+                                }
+                            });
+
+                        if let Some(enemy) = enemy {
+                            self.engage(enemy)
+                        }
+                        else if (guard_position - self.position).magnitude() >
+                                GUARD_DISTANCE_UNITS {
+                            Action::MoveToward {
+                                beetle_id: self.id,
+                                x: guard_position.x,
+                                y: guard_position.y,
+                            }
+                        }
+                        else {
+                            Action::Wait {
+                                beetle_id: self.id,
+                            }
+                        }
+                    },
+                    // nothing left to guard
+                    None => {
+                        Action::Nothing {
+                            beetle_id: self.id,
+                        }
+                    },
+                }
+            },
+            Command::Follow{ target_id } => {
+                if let Some(target) = beetles.get(&target_id) {
+                    let gap = (target.position - self.position).magnitude() -
+                        self.collision_radius() - target.collision_radius();
+
+                    if gap > FOLLOW_GAP_UNITS {
+                        Action::MoveToward {
+                            beetle_id: self.id,
+                            x: target.position.x,
+                            y: target.position.y,
+                        }
+                    }
+                    else {
+                        Action::Wait {
+                            beetle_id: self.id,
+                        }
+                    }
+                }
+                else {
+                    Action::Nothing {
+                        beetle_id: self.id,
+                    }
+                }
+            },
+            Command::HoldPosition => {
+                let reach = self.collision_radius() + MAX_COLLISION_RADIUS_UNITS +
+                    self.reach();
+
+                match field_state.find_closest_enemy(self.team_id, self.position, reach) {
+                    Some(enemy) if self.within_reach(enemy) => self.strike(enemy),
+                    _ => {
+                        Action::Wait {
+                            beetle_id: self.id,
+                        }
+                    },
+                }
+            },
+            Command::Wander => {
+                if let Some(enemy) = field_state.find_closest_enemy(
+                        self.team_id, self.position, self.smell_range()) {
+                    self.engage(enemy)
+                }
+                else {
+                    self.wander(field_state)
+                }
             },
             Command::Stop => {
-                Action::Nothing {
-                    beetle_id: self.id,
+                // idle beetles stand up for themselves, but don't wander
+                // off to do it
+                let post = self.stop_position.unwrap_or(self.position);
+
+                if let Some(enemy) = field_state.find_closest_enemy(
+                        self.team_id, post, STOP_LEASH_UNITS) {
+                    self.engage(enemy)
+                }
+                else if (post - self.position).magnitude() > STOP_LEASH_UNITS / 2.0 {
+                    Action::MoveToward {
+                        beetle_id: self.id,
+                        x: post.x,
+                        y: post.y,
+                    }
+                }
+                else {
+                    Action::Nothing {
+                        beetle_id: self.id,
+                    }
                 }
             },
         };
@@ -231,14 +672,20 @@ impl Beetle {
     }
 
     fn handle_collect_food_command(
-            &self, food_source: &FoodSource, home_bases: &HomeBases) -> Action {
+            &self, food_source: &FoodSource, field_state: &FieldState) -> Action {
+
+        let food_type = food_source.food_type();
+        // only one kind of food can be carried at a time
+        let room_for_more = if self.food_carrying == 0 {
+            true
+        }
+        else {
+            self.carried_food_type == food_type &&
+                self.food_carrying < self.carrying_capacity_for(food_type)
+        };
 
-        // TODO: need to be careful with this. As it's currently coded if the
-        // beetle ever drops off less than its entire load it's going to be
-        // carrying food back and forth
-        //
         // get more food
-        if self.food_carrying < self.carrying_capacity() {
+        if room_for_more && food_source.amount() > 0 {
             if self.can_interact(food_source.get_position()) {
                 Action::TakeFood {
                     beetle_id: self.get_id(),
@@ -254,13 +701,19 @@ impl Beetle {
                 }
             }
         }
+        else if self.food_carrying > 0 {
+            self.take_food_to_closest_base(field_state)
+        }
         else {
-            self.take_food_to_closest_base(&home_bases)
+            Action::Nothing {
+                beetle_id: self.id,
+            }
         }
     }
 
-    fn take_food_to_closest_base(&self, home_bases: &HomeBases) -> Action {
-        if let Some(closest_base) = find_closest(self, home_bases) {
+    fn take_food_to_closest_base(&self, field_state: &FieldState) -> Action {
+        if let Some(closest_base) = field_state.find_closest_home_base(
+                self.team_id, self.position) {
            self.take_food_to_base(closest_base)
         }
         else {
@@ -275,7 +728,7 @@ impl Beetle {
             Action::DumpFood {
                 beetle_id: self.get_id(),
                 home_base_id: home_base.get_id(),
-                amount: self.carrying_capacity(),
+                amount: self.food_carrying,
             }
         }
         else {
@@ -288,29 +741,48 @@ impl Beetle {
     }
 
     pub fn move_toward(&mut self, a: &Point2<f32>) {
+        let speed = self.speed();
+        self.move_toward_with_speed(a, speed);
+    }
 
-        let rot: Basis2<f32> =
-            Rotation2::from_angle(self.rotation_radians_per_tick);
-        let rot_neg: Basis2<f32> =
-            Rotation2::from_angle(-self.rotation_radians_per_tick);
+    pub fn move_toward_with_speed(&mut self, a: &Point2<f32>, speed: f32) {
 
         let vector = a - self.position;
-        let angle = self.direction.angle(vector);
+
+        if self.turn_toward(vector) {
+            self.position.x += self.direction.x * speed;
+            self.position.y += self.direction.y * speed;
+            self.distance_moved += speed;
+        }
+    }
+
+    // Turns one tick's worth toward target_direction. Returns true if the
+    // beetle was already facing close enough to it.
+    pub fn turn_toward(&mut self, target_direction: Vector2<f32>) -> bool {
+
+        let rotation = self.rotation_per_tick();
+        let rot: Basis2<f32> = Rotation2::from_angle(rotation);
+        let rot_neg: Basis2<f32> = Rotation2::from_angle(-rotation);
+
+        let angle = self.direction.angle(target_direction);
 
         let thresh = Rad(0.1);
 
-        if angle < -thresh {
+        let facing = if angle < -thresh {
             self.direction = rot_neg.rotate_vector(self.direction);
+            false
         }
         else if angle > thresh {
             self.direction = rot.rotate_vector(self.direction);
+            false
         }
         else {
-            self.position.x += self.direction.x * self.speed();
-            self.position.y += self.direction.y * self.speed();
-        }
+            true
+        };
 
         self.angle = Vector2::new(1.0, 0.0).angle(self.direction);
+
+        facing
     }
 
     fn can_interact(&self, target_position: Point2<f32>) -> bool {
@@ -322,7 +794,87 @@ impl Beetle {
         let vector = target_position - self.position;
         let dist = vector.magnitude();
 
-        return dist < 5.0;
+        return dist < INTERACTION_DISTANCE_UNITS;
+    }
+
+    // Beetles can't overlap, so the distance that matters is the gap between
+    // their bodies rather than between their centers.
+    fn can_interact_with_beetle(&self, target: &Beetle) -> bool {
+        self.within_reach(target) && self.facing_target(target.position)
+    }
+
+    fn within_reach(&self, target: &Beetle) -> bool {
+        let vector = target.position - self.position;
+        let gap = vector.magnitude() - self.collision_radius() -
+            target.collision_radius();
+
+        gap < self.reach()
+    }
+
+    fn can_smell(&self, position: Point2<f32>) -> bool {
+        (position - self.position).magnitude() <= self.smell_range()
+    }
+
+    // Roughly keeps going the way it's facing, with some random turns thrown
+    // in
+    fn wander(&self, field_state: &FieldState) -> Action {
+
+        let world = &field_state.world;
+        let near_edge =
+            self.position.x < WANDER_EDGE_MARGIN_UNITS ||
+            self.position.x > world.width - WANDER_EDGE_MARGIN_UNITS ||
+            self.position.y < WANDER_EDGE_MARGIN_UNITS ||
+            self.position.y > world.height - WANDER_EDGE_MARGIN_UNITS;
+
+        let target = if near_edge {
+            Point2::new(world.width / 2.0, world.height / 2.0)
+        }
+        else {
+            let turn = thread_rng().gen_range(-WANDER_MAX_TURN, WANDER_MAX_TURN);
+            let rot: Basis2<f32> = Rotation2::from_angle(Rad(turn));
+            let direction = rot.rotate_vector(self.direction);
+
+            self.position + direction * WANDER_LOOKAHEAD_UNITS
+        };
+
+        Action::MoveToward {
+            beetle_id: self.id,
+            x: target.x,
+            y: target.y,
+        }
+    }
+
+    // Attacks target if it's in reach, otherwise closes in on it
+    fn engage(&self, target: &Beetle) -> Action {
+        if self.within_reach(target) {
+            self.strike(target)
+        }
+        else {
+            Action::MoveToward {
+                beetle_id: self.id,
+                x: target.position.x,
+                y: target.position.y,
+            }
+        }
+    }
+
+    // Bites target if facing it and ready to, otherwise keeps turning toward
+    // it while the mandibles recover
+    fn strike(&self, target: &Beetle) -> Action {
+        if self.attack_cooldown == 0 && self.facing_target(target.position) {
+            Action::Attack {
+                source_id: self.id,
+                target_id: target.id,
+                attack_power: self.attack_power(),
+            }
+        }
+        else {
+            Action::TurnToward {
+                beetle_id: self.id,
+                x: target.position.x,
+                y: target.position.y,
+            }
+        }
     }
 
     fn facing_target(&self, target_position: Point2<f32>) -> bool {
@@ -338,6 +890,7 @@ impl Beetle {
 
     pub fn take_damage(&mut self, damage_amount: i32) -> bool {
         self.health -= damage_amount;
+        self.ticks_since_hurt = 0;
         let mut dead = false;
         if self.health <= 0 {
             dead = true;
@@ -345,6 +898,10 @@ impl Beetle {
         return dead;
     }
 
+    pub fn heal(&mut self, amount: i32) {
+        self.health = (self.health + amount).min(self.max_health());
+    }
+
 }
 
 pub struct BeetleBuilder {
@@ -401,6 +958,7 @@ impl BeetleBuilder {
         beetle.rotation_radians_per_tick = self.rotation_radians_per_tick;
         // TODO: figure out how to move self.genome rather than cloning it
         beetle.genome = self.genome.clone();
+        beetle.energy = beetle.max_energy();
         return beetle;
     }
 }
@@ -19,6 +19,7 @@ enum BeetleGene {
     MandibleSharpness(Ratio),
     BodyWidth(Ratio),
     BodyLength(Ratio),
+    SmellSensitivity(Ratio),
 }
 
 pub enum BeetleGeneIndex {
@@ -29,6 +30,7 @@ pub enum BeetleGeneIndex {
     MandibleSharpness = 4,
     BodyWidth = 5,
     BodyLength = 6,
+    SmellSensitivity = 7,
 }
 
 impl From<i32> for BeetleGeneIndex {
@@ -41,6 +43,7 @@ impl From<i32> for BeetleGeneIndex {
             4 => BeetleGeneIndex::MandibleSharpness,
             5 => BeetleGeneIndex::BodyWidth,
             6 => BeetleGeneIndex::BodyLength,
+            7 => BeetleGeneIndex::SmellSensitivity,
             _ => panic!("Invalid gene index {}", val)
         }
     }
@@ -57,6 +60,7 @@ impl BeetleGenome {
                 BeetleGene::MandibleSharpness(0.5),
                 BeetleGene::BodyWidth(0.5),
                 BeetleGene::BodyLength(0.5),
+                BeetleGene::SmellSensitivity(0.5),
                 // other gene ideas:
                 // coordination (affects turning speed, etc)
                 // mandible size
@@ -80,8 +84,10 @@ impl BeetleGenome {
     }
 
     pub fn set_random_genome(&mut self) {
+        self.set_random_genome_with_rng(&mut thread_rng());
+    }
 
-        let mut rng = thread_rng();
+    pub fn set_random_genome_with_rng<R: Rng>(&mut self, rng: &mut R) {
         self.set_gene_value(BeetleGeneIndex::CarapaceDensity, rng.gen());
         self.set_gene_value(BeetleGeneIndex::Strength, rng.gen());
         self.set_gene_value(BeetleGeneIndex::Quickness, rng.gen());
@@ -89,6 +95,32 @@ impl BeetleGenome {
         self.set_gene_value(BeetleGeneIndex::MandibleSharpness, rng.gen());
         self.set_gene_value(BeetleGeneIndex::BodyWidth, rng.gen());
         self.set_gene_value(BeetleGeneIndex::BodyLength, rng.gen());
+        self.set_gene_value(BeetleGeneIndex::SmellSensitivity, rng.gen());
+    }
+
+    // Gene values in BeetleGeneIndex order. Any that are left out stay at
+    // 0.5.
+    pub fn from_values(values: &[Ratio]) -> BeetleGenome {
+        let mut genome = BeetleGenome::new();
+
+        for (index, value) in values.iter().take(genome.genome.len()).enumerate() {
+            genome.set_gene_value(BeetleGeneIndex::from(index as i32), value.max(0.0).min(1.0));
+        }
+
+        genome
+    }
+
+    // Each gene comes from one parent or the other, at random
+    pub fn crossover(&self, other: &BeetleGenome) -> BeetleGenome {
+        let mut rng = thread_rng();
+
+        let genome = self.genome.iter().zip(other.genome.iter())
+            .map(|(mine, theirs)| {
+                if rng.gen::<bool>() { mine.clone() } else { theirs.clone() }
+            })
+            .collect();
+
+        BeetleGenome { genome }
     }
 
     pub fn get_gene(
@@ -102,6 +134,7 @@ impl BeetleGenome {
             BeetleGene::MandibleSharpness(value) => value,
             BeetleGene::BodyWidth(value) => value,
             BeetleGene::BodyLength(value) => value,
+            BeetleGene::SmellSensitivity(value) => value,
         }
     }
 
@@ -144,6 +177,11 @@ impl BeetleGenome {
                         as usize] =
                     BeetleGene::BodyLength(value);
             },
+            BeetleGeneIndex::SmellSensitivity => {
+                self.genome[BeetleGeneIndex::SmellSensitivity
+                        as usize] =
+                    BeetleGene::SmellSensitivity(value);
+            },
         }
     }
 }
@@ -0,0 +1,424 @@
+use std::thread;
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Sender, Receiver, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, Instant};
+use websocket::{OwnedMessage};
+use websocket::sync::{Server, Writer};
+use gen::bot::{
+    BotMessage, ServerMessage, TeamClaimed, BotError, Observation, BotBeetle,
+    BotFoodSource, BotHomeBase, BotCommand, BotCommandType
+};
+use protobuf::{parse_from_bytes, RepeatedField, Message};
+
+use cgmath::{Point2, InnerSpace};
+use entities::{Id, Entity, Beetle};
+use game::{Game, FieldState, Command};
+use utils::{Color, Positioned};
+
+pub const BOT_ADDRESS: &str = "127.0.0.1:4021";
+const BOT_PROTOCOL: &str = "battle-beetles-bot";
+// how long the game waits each tick for every bot to answer. Bots that
+// don't make it have their commands carried out a tick late.
+pub const TICK_TIMEOUT_MS: u64 = 15;
+// a slow bot misses most ticks, so only every this many get reported
+const MISSED_TICKS_REPORTED_EVERY: u64 = 250;
+// handed out to new teams in order
+const TEAM_COLORS: [Color; 4] = [
+    Color { r: 92, g: 184, b: 92, a: 255 },
+    Color { r: 240, g: 173, b: 78, a: 255 },
+    Color { r: 153, g: 102, b: 204, a: 255 },
+    Color { r: 91, g: 192, b: 222, a: 255 },
+];
+
+struct Connection {
+    address: String,
+    sender: Writer<TcpStream>,
+    receiver: Receiver<BotMessage>,
+    team_id: Option<Id>,
+    missed_ticks: u64,
+    disconnected: bool,
+}
+
+impl Connection {
+    fn send(&mut self, message: ServerMessage) {
+        match message.write_to_bytes() {
+            Ok(encoded_message) => {
+                if let Err(e) = self.sender.send_message(&OwnedMessage::Binary(encoded_message)) {
+                    println!("Bot {}: {:?}", self.address, e);
+                    self.disconnected = true;
+                }
+            },
+            Err(e) => {
+                println!("encode error: {}", e);
+            }
+        }
+    }
+
+    fn send_error(&mut self, text: &str) {
+        let mut error = BotError::new();
+        error.set_message(text.to_string());
+
+        let mut message = ServerMessage::new();
+        message.set_error(error);
+        self.send(message);
+    }
+}
+
+// Lets programs outside the game control teams over a WebSocket, see
+// protos/bot.proto. Every tick each bot gets an observation of what its
+// team can see, and the game waits for its commands before moving on.
+pub struct BotServer {
+    new_connections: Receiver<Connection>,
+    bots: Vec<Connection>,
+    num_teams_claimed: usize,
+}
+
+impl BotServer {
+    pub fn new(address: &str) -> BotServer {
+
+        let (connection_sender, connection_receiver) = channel();
+
+        match Server::bind(address) {
+            Ok(server) => {
+                thread::spawn(move || accept_bots(server, connection_sender));
+            },
+            Err(e) => {
+                println!("Couldn't start the bot server on {}: {}", address, e);
+            },
+        }
+
+        BotServer {
+            new_connections: connection_receiver,
+            bots: Vec::new(),
+            num_teams_claimed: 0,
+        }
+    }
+
+    // Call once before every tick
+    pub fn update(&mut self, game: &mut Game) {
+
+        while let Ok(connection) = self.new_connections.try_recv() {
+            println!("Bot connected from {}", connection.address);
+            self.bots.push(connection);
+        }
+
+        if self.bots.len() == 0 {
+            return;
+        }
+
+        // claims and anything that showed up too late last tick
+        for index in 0..self.bots.len() {
+            loop {
+                match self.bots[index].receiver.try_recv() {
+                    Ok(message) => self.handle_message(index, game, message),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.bots[index].disconnected = true;
+                        break;
+                    },
+                }
+            }
+        }
+
+        // bots and the computer don't share, even when a match is started
+        // after the bot claimed its team
+        for bot in self.bots.iter() {
+            game.ai_players.retain(|ai_player| Some(ai_player.team_id) != bot.team_id);
+        }
+
+        let tick = game.field_state.tick;
+
+        for bot in self.bots.iter_mut() {
+            if let Some(team_id) = bot.team_id {
+                let mut message = ServerMessage::new();
+                message.set_observation(observe(&game.field_state, team_id));
+                bot.send(message);
+            }
+        }
+
+        let deadline = Instant::now() + Duration::from_millis(TICK_TIMEOUT_MS);
+
+        for index in 0..self.bots.len() {
+            let team_id = match self.bots[index].team_id {
+                Some(team_id) => team_id,
+                None => continue,
+            };
+
+            while !self.bots[index].disconnected {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+
+                match self.bots[index].receiver.recv_timeout(deadline - now) {
+                    Ok(mut message) => {
+                        if message.has_commands() && message.get_commands().get_tick() == tick {
+                            let commands = message.take_commands().take_commands();
+                            apply_commands(game, team_id, commands.into_vec());
+                            break;
+                        }
+                        self.handle_message(index, game, message);
+                    },
+                    Err(RecvTimeoutError::Timeout) => {
+                        let bot = &mut self.bots[index];
+                        bot.missed_ticks += 1;
+                        if bot.missed_ticks % MISSED_TICKS_REPORTED_EVERY == 1 {
+                            println!("Bot {} missed tick {} ({} missed so far)",
+                                bot.address, tick, bot.missed_ticks);
+                        }
+                        break;
+                    },
+                    Err(RecvTimeoutError::Disconnected) => {
+                        self.bots[index].disconnected = true;
+                    },
+                }
+            }
+        }
+
+        for bot in self.bots.iter().filter(|bot| bot.disconnected) {
+            println!("Bot {} disconnected after missing {} ticks",
+                bot.address, bot.missed_ticks);
+        }
+        self.bots.retain(|bot| !bot.disconnected);
+    }
+
+    fn handle_message(&mut self, index: usize, game: &mut Game, mut message: BotMessage) {
+
+        if message.has_claim_team() {
+            let name = message.get_claim_team().get_name();
+
+            if self.bots[index].team_id.is_some() {
+                self.bots[index].send_error("Already controlling a team");
+                return;
+            }
+
+            let color = TEAM_COLORS[self.num_teams_claimed % TEAM_COLORS.len()];
+            let team_id = game.team_for(name, color);
+
+            if self.bots.iter().any(|bot| bot.team_id == Some(team_id)) {
+                self.bots[index].send_error("Another bot controls that team");
+                return;
+            }
+
+            self.num_teams_claimed += 1;
+
+            let bot = &mut self.bots[index];
+            bot.team_id = Some(team_id);
+            println!("Bot {} claimed team {}", bot.address, name);
+
+            let mut team_claimed = TeamClaimed::new();
+            team_claimed.set_team_id(team_id);
+            team_claimed.set_world_width(game.field_state.world.width);
+            team_claimed.set_world_height(game.field_state.world.height);
+            team_claimed.set_tick_timeout_ms(TICK_TIMEOUT_MS as i32);
+
+            let mut reply = ServerMessage::new();
+            reply.set_team_claimed(team_claimed);
+            bot.send(reply);
+        }
+        else if message.has_commands() {
+            match self.bots[index].team_id {
+                // late, but still what the bot wants its beetles doing
+                Some(team_id) => {
+                    let commands = message.take_commands().take_commands();
+                    apply_commands(game, team_id, commands.into_vec());
+                },
+                None => self.bots[index].send_error("Claim a team first"),
+            }
+        }
+    }
+}
+
+fn accept_bots(mut server: Server<::websocket::server::NoTlsAcceptor>, connections: Sender<Connection>) {
+    loop {
+        let upgrade = match server.accept() {
+            Ok(upgrade) => upgrade,
+            Err(_) => continue,
+        };
+
+        let mut client = match upgrade.use_protocol(BOT_PROTOCOL).accept() {
+            Ok(client) => client,
+            Err((_, e)) => {
+                println!("Bot connection failed: {:?}", e);
+                continue;
+            },
+        };
+
+        // observations are small and the bot is waiting on every one
+        if let Err(e) = client.set_nodelay(true) {
+            println!("Bot connection: {:?}", e);
+        }
+
+        let address = match client.peer_addr() {
+            Ok(address) => address.to_string(),
+            Err(_) => "unknown".to_string(),
+        };
+
+        let (mut receiver, sender) = match client.split() {
+            Ok(halves) => halves,
+            Err(e) => {
+                println!("Bot connection failed: {:?}", e);
+                continue;
+            },
+        };
+
+        let (message_sender, message_receiver) = channel();
+
+        thread::spawn(move || {
+            for message in receiver.incoming_messages() {
+                match message {
+                    Ok(OwnedMessage::Binary(message)) => {
+                        if let Ok(bot_message) = parse_from_bytes::<BotMessage>(&message) {
+                            if message_sender.send(bot_message).is_err() {
+                                return;
+                            }
+                        }
+                    },
+                    Ok(OwnedMessage::Close(_)) | Err(_) => return,
+                    Ok(_) => (),
+                }
+            }
+        });
+
+        let connection = Connection {
+            address,
+            sender,
+            receiver: message_receiver,
+            team_id: None,
+            missed_ticks: 0,
+            disconnected: false,
+        };
+
+        if connections.send(connection).is_err() {
+            return;
+        }
+    }
+}
+
+fn bot_beetle(beetle: &Beetle) -> BotBeetle {
+    let mut new_beetle = BotBeetle::new();
+
+    new_beetle.set_id(beetle.id);
+    new_beetle.set_team_id(beetle.team_id);
+    new_beetle.set_x(beetle.position.x);
+    new_beetle.set_y(beetle.position.y);
+    new_beetle.set_angle(beetle.angle.0);
+    new_beetle.set_health(beetle.health);
+    new_beetle.set_max_health(beetle.max_health());
+    new_beetle.set_energy(beetle.energy);
+    new_beetle.set_food_carrying(beetle.food_carrying);
+    new_beetle.set_attack_power(beetle.attack_power());
+    new_beetle.set_speed(beetle.speed());
+    new_beetle.set_smell_range(beetle.smell_range());
+
+    new_beetle
+}
+
+// What team_id's beetles can see. Anything within smell range of one of them
+// shows up, along with every base the team is allied to.
+pub fn observe(field_state: &FieldState, team_id: Id) -> Observation {
+
+    let team: Vec<&Beetle> = field_state.beetles.values()
+        .filter(|beetle| beetle.team_id == team_id)
+        .collect();
+
+    let can_smell = |position: Point2<f32>| {
+        team.iter().any(|beetle| {
+            (beetle.position - position).magnitude() <= beetle.smell_range()
+        })
+    };
+
+    let mut observation = Observation::new();
+    observation.set_tick(field_state.tick);
+    observation.set_team_id(team_id);
+    observation.set_score(field_state.teams.get(team_id).map(|team| team.score).unwrap_or(0));
+
+    observation.set_beetles(RepeatedField::from_vec(
+        team.iter().map(|beetle| bot_beetle(beetle)).collect()));
+
+    observation.set_enemies(RepeatedField::from_vec(
+        field_state.beetles.values()
+            .filter(|beetle| {
+                field_state.teams.are_hostile(team_id, beetle.team_id) &&
+                    can_smell(beetle.position)
+            })
+            .map(bot_beetle)
+            .collect()));
+
+    let mut food_sources = RepeatedField::new();
+    for food_source in field_state.get_food_sources().values() {
+        if !can_smell(food_source.get_position()) {
+            continue;
+        }
+
+        let mut new_food_source = BotFoodSource::new();
+        new_food_source.set_id(food_source.get_id());
+        new_food_source.set_x(food_source.get_position().x);
+        new_food_source.set_y(food_source.get_position().y);
+        new_food_source.set_amount(food_source.amount());
+        new_food_source.set_food_yield(food_source.food_type().food_yield);
+        new_food_source.set_carry_cost(food_source.food_type().carry_cost);
+        food_sources.push(new_food_source);
+    }
+    observation.set_food_sources(food_sources);
+
+    let mut home_bases = RepeatedField::new();
+    for home_base in field_state.get_home_bases().values() {
+        if !field_state.teams.are_allied(team_id, home_base.get_team_id()) &&
+                !can_smell(home_base.get_position()) {
+            continue;
+        }
+
+        let mut new_home_base = BotHomeBase::new();
+        new_home_base.set_id(home_base.get_id());
+        new_home_base.set_team_id(home_base.get_team_id());
+        new_home_base.set_x(home_base.get_position().x);
+        new_home_base.set_y(home_base.get_position().y);
+        new_home_base.set_food_stored(home_base.get_food_stored_amount());
+        home_bases.push(new_home_base);
+    }
+    observation.set_home_bases(home_bases);
+
+    observation
+}
+
+// Hands out the commands to whichever beetles belong to team_id. Anything
+// aimed at someone else's beetles is ignored.
+pub fn apply_commands(game: &mut Game, team_id: Id, commands: Vec<BotCommand>) {
+
+    for bot_command in commands {
+        let beetle = match game.field_state.beetles.get_mut(&bot_command.get_beetle_id()) {
+            Some(beetle) => beetle,
+            None => continue,
+        };
+
+        if beetle.team_id != team_id {
+            continue;
+        }
+
+        let position = Point2::new(bot_command.get_x(), bot_command.get_y());
+        let target_id = bot_command.get_target_id();
+
+        let command = match bot_command.get_kind() {
+            BotCommandType::MOVE => Command::Move { position },
+            BotCommandType::ATTACK_MOVE => Command::AttackMove { position },
+            BotCommandType::INTERACT => Command::Interact { target_id },
+            BotCommandType::HARVEST => Command::HarvestClosestFood,
+            BotCommandType::FORAGE => Command::Forage,
+            BotCommandType::RETURN_TO_BASE => Command::ReturnToBase,
+            BotCommandType::PATROL => Command::Patrol { from: beetle.position, to: position },
+            BotCommandType::GUARD => Command::Guard { target_id },
+            BotCommandType::FOLLOW => Command::Follow { target_id },
+            BotCommandType::HOLD_POSITION => Command::HoldPosition,
+            BotCommandType::WANDER => Command::Wander,
+            BotCommandType::STOP => Command::Stop,
+        };
+
+        if bot_command.get_queue() {
+            beetle.queue_command(command);
+        }
+        else {
+            beetle.set_command(command);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+use cgmath::Point2;
+use rand::{Rng, thread_rng};
+
+use entities::{Id, Beetles, HomeBase, HomeBases, HasFood};
+use beetle_genome::BeetleGenome;
+use utils::{Color, Positioned, seconds_to_ticks};
+use simulation::MUTATION_RATE;
+
+// food a base spends on every beetle it hatches
+pub const HATCH_FOOD_COST: i32 = 20;
+// how long one beetle takes to hatch, once the base can afford it
+const HATCH_SECONDS: f32 = 3.0;
+// how many recent deliveries each base remembers when picking parents
+const MAX_DELIVERIES_REMEMBERED: usize = 32;
+// hatchlings appear this far from the middle of the base, at most
+const HATCH_SPREAD_UNITS: f32 = 20.0;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum GenomeSource {
+    // a mutated copy of one of the team's living beetles
+    BreedingPool,
+    // a cross between two beetles that recently brought food to the base,
+    // favouring whoever brought the most
+    Crossover,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ProductionOrder {
+    pub genome_source: GenomeSource,
+    // None keeps hatching for as long as there's food to pay for it
+    pub remaining: Option<u32>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Delivery {
+    genome: BeetleGenome,
+    color: Color,
+    amount: i32,
+}
+
+// The hatchery inside each home base
+#[derive(Serialize, Debug, Clone)]
+pub struct Production {
+    pub queue: VecDeque<ProductionOrder>,
+    // ticks spent on the beetle at the front of the queue
+    pub progress_ticks: u32,
+    deliveries: VecDeque<Delivery>,
+}
+
+impl Production {
+    pub fn new() -> Production {
+        Production {
+            queue: VecDeque::new(),
+            progress_ticks: 0,
+            deliveries: VecDeque::new(),
+        }
+    }
+
+    // Replaces whatever the base was making, or with queue set adds to the
+    // end of it instead
+    pub fn set_order(&mut self, order: Option<ProductionOrder>, queue: bool) {
+        if !queue {
+            self.queue.clear();
+            self.progress_ticks = 0;
+        }
+
+        if let Some(order) = order {
+            self.queue.push_back(order);
+        }
+    }
+
+    // How far along the current hatchling is, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.progress_ticks as f32 / hatch_ticks() as f32
+    }
+
+    pub fn record_delivery(&mut self, genome: &BeetleGenome, color: Color, amount: i32) {
+        if amount <= 0 {
+            return;
+        }
+
+        self.deliveries.push_back(Delivery { genome: genome.clone(), color, amount });
+
+        while self.deliveries.len() > MAX_DELIVERIES_REMEMBERED {
+            self.deliveries.pop_front();
+        }
+    }
+
+    fn finish_one(&mut self) {
+        self.progress_ticks = 0;
+
+        let finished = match self.queue.front_mut() {
+            Some(&mut ProductionOrder { remaining: Some(ref mut remaining), .. }) => {
+                *remaining = remaining.saturating_sub(1);
+                *remaining == 0
+            },
+            _ => false,
+        };
+
+        if finished {
+            self.queue.pop_front();
+        }
+    }
+}
+
+// Everything needed to put a freshly hatched beetle into the game
+pub struct Hatchling {
+    pub team_id: Id,
+    pub position: Point2<f32>,
+    pub genome: BeetleGenome,
+    pub color: Color,
+}
+
+fn hatch_ticks() -> u32 {
+    seconds_to_ticks(HATCH_SECONDS).max(1)
+}
+
+// hatchlings mutate at the same rate as the genetic algorithms' offspring
+fn mutate(genome: &mut BeetleGenome) {
+    let mut rng = thread_rng();
+
+    if rng.gen::<f32>() < MUTATION_RATE {
+        let index = genome.get_random_gene_index();
+        genome.set_gene_value(index, rng.gen());
+    }
+}
+
+fn from_breeding_pool(home_base: &HomeBase, beetles: &Beetles) -> Option<(BeetleGenome, Color)> {
+    let team: Vec<_> = beetles.values()
+        .filter(|beetle| beetle.team_id == home_base.get_team_id())
+        .collect();
+
+    if !team.is_empty() {
+        let parent = team[thread_rng().gen_range(0, team.len())];
+        return Some((parent.genome.clone(), parent.color));
+    }
+
+    // the whole team is gone, but the base still remembers who fed it
+    let deliveries = &home_base.production.deliveries;
+    if deliveries.is_empty() {
+        return None;
+    }
+
+    let delivery = &deliveries[thread_rng().gen_range(0, deliveries.len())];
+    Some((delivery.genome.clone(), delivery.color))
+}
+
+fn pick_delivery(deliveries: &VecDeque<Delivery>) -> &Delivery {
+    let total: i32 = deliveries.iter().map(|delivery| delivery.amount).sum();
+    let mut choice = thread_rng().gen_range(0, total);
+
+    for delivery in deliveries.iter() {
+        if choice < delivery.amount {
+            return delivery;
+        }
+        choice -= delivery.amount;
+    }
+
+    &deliveries[deliveries.len() - 1]
+}
+
+fn from_crossover(home_base: &HomeBase, beetles: &Beetles) -> Option<(BeetleGenome, Color)> {
+    let deliveries = &home_base.production.deliveries;
+
+    // not enough to go on yet
+    if deliveries.len() < 2 {
+        return from_breeding_pool(home_base, beetles);
+    }
+
+    let mother = pick_delivery(deliveries);
+    let father = pick_delivery(deliveries);
+
+    Some((mother.genome.crossover(&father.genome), mother.color))
+}
+
+// Moves every base's production along by a tick and pays for anything that
+// finishes hatching. Returns the new beetles and leaves it to the caller to
+// add them to the game.
+pub fn update(home_bases: &mut HomeBases, beetles: &Beetles) -> Vec<Hatchling> {
+    let mut hatchlings = Vec::new();
+    let mut rng = thread_rng();
+
+    for home_base in home_bases.values_mut() {
+        let genome_source = match home_base.production.queue.front() {
+            Some(order) => order.genome_source,
+            None => continue,
+        };
+
+        // waits for more food rather than starting over
+        if home_base.get_food_stored_amount() < HATCH_FOOD_COST {
+            continue;
+        }
+
+        home_base.production.progress_ticks += 1;
+        if home_base.production.progress_ticks < hatch_ticks() {
+            continue;
+        }
+
+        let parents = match genome_source {
+            GenomeSource::BreedingPool => from_breeding_pool(home_base, beetles),
+            GenomeSource::Crossover => from_crossover(home_base, beetles),
+        };
+
+        // nobody to hatch from, so the base sits at full progress until
+        // there is
+        let (mut genome, color) = match parents {
+            Some(parents) => parents,
+            None => {
+                home_base.production.progress_ticks = hatch_ticks();
+                continue;
+            },
+        };
+
+        mutate(&mut genome);
+
+        home_base.remove_food(HATCH_FOOD_COST);
+        home_base.production.finish_one();
+
+        let position = home_base.get_position();
+        hatchlings.push(Hatchling {
+            team_id: home_base.get_team_id(),
+            position: Point2::new(
+                position.x + rng.gen_range(-HATCH_SPREAD_UNITS, HATCH_SPREAD_UNITS),
+                position.y + rng.gen_range(-HATCH_SPREAD_UNITS, HATCH_SPREAD_UNITS)),
+            genome,
+            color,
+        });
+    }
+
+    hatchlings
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use entities::{Beetles, HomeBase, HomeBases, HasFood};
+    use beetle_genome::BeetleGenome;
+    use utils::Color;
+    use super::{Delivery, GenomeSource, ProductionOrder, HATCH_FOOD_COST, MAX_DELIVERIES_REMEMBERED,
+        hatch_ticks, pick_delivery, update};
+
+    fn delivery(amount: i32) -> Delivery {
+        Delivery { genome: BeetleGenome::new(), color: Color::new(), amount }
+    }
+
+    fn crossover_base(food: i32) -> HomeBases {
+        let mut home_base = HomeBase::new(1);
+        home_base.set_team_id(2);
+        home_base.add_food(food);
+        home_base.production.record_delivery(&BeetleGenome::new(), Color::new(), 5);
+        home_base.production.record_delivery(&BeetleGenome::new(), Color::new(), 5);
+        home_base.production.set_order(Some(ProductionOrder {
+            genome_source: GenomeSource::Crossover,
+            remaining: Some(1),
+        }), false);
+
+        let mut home_bases = HomeBases::new();
+        home_bases.insert(1, home_base);
+        home_bases
+    }
+
+    #[test]
+    fn picks_deliveries_by_how_much_they_brought() {
+        let mut deliveries = VecDeque::new();
+        deliveries.push_back(delivery(1));
+        deliveries.push_back(delivery(9));
+
+        let picks = 10000;
+        let big = (0..picks)
+            .filter(|_| pick_delivery(&deliveries).amount == 9)
+            .count();
+
+        // 90% expected, with plenty of room for chance
+        assert!(big > picks * 85 / 100 && big < picks * 95 / 100, "{} of {}", big, picks);
+    }
+
+    #[test]
+    fn a_lone_delivery_is_always_picked() {
+        let mut deliveries = VecDeque::new();
+        deliveries.push_back(delivery(3));
+
+        for _ in 0..100 {
+            assert_eq!(pick_delivery(&deliveries).amount, 3);
+        }
+    }
+
+    #[test]
+    fn remembers_only_recent_real_deliveries() {
+        let mut home_base = HomeBase::new(1);
+        home_base.production.record_delivery(&BeetleGenome::new(), Color::new(), 0);
+        assert!(home_base.production.deliveries.is_empty());
+
+        for amount in 1..(MAX_DELIVERIES_REMEMBERED as i32 + 6) {
+            home_base.production.record_delivery(&BeetleGenome::new(), Color::new(), amount);
+        }
+
+        let deliveries = &home_base.production.deliveries;
+        assert_eq!(deliveries.len(), MAX_DELIVERIES_REMEMBERED);
+        // the oldest went first
+        assert_eq!(deliveries[0].amount, 6);
+    }
+
+    #[test]
+    fn hatches_from_deliveries_and_pays_for_it() {
+        let mut home_bases = crossover_base(HATCH_FOOD_COST + 1);
+        let beetles = Beetles::new();
+
+        for _ in 1..hatch_ticks() {
+            assert!(update(&mut home_bases, &beetles).is_empty());
+        }
+
+        let hatchlings = update(&mut home_bases, &beetles);
+        assert_eq!(hatchlings.len(), 1);
+        assert_eq!(hatchlings[0].team_id, 2);
+
+        let home_base = &home_bases[&1];
+        assert_eq!(home_base.get_food_stored_amount(), 1);
+        assert!(home_base.production.queue.is_empty());
+        assert_eq!(home_base.production.progress_ticks, 0);
+    }
+
+    #[test]
+    fn waits_for_food_before_hatching() {
+        let mut home_bases = crossover_base(HATCH_FOOD_COST - 1);
+        let beetles = Beetles::new();
+
+        for _ in 0..(hatch_ticks() * 2) {
+            assert!(update(&mut home_bases, &beetles).is_empty());
+        }
+
+        let home_base = &home_bases[&1];
+        assert_eq!(home_base.production.progress_ticks, 0);
+        assert_eq!(home_base.production.queue.len(), 1);
+    }
+}
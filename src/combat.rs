@@ -0,0 +1,221 @@
+use std::f32;
+use cgmath::InnerSpace;
+use rand::{Rng, thread_rng};
+
+use entities::{Beetle, Beetles};
+use beetle_genome::BeetleGeneIndex as Gene;
+use rules::Rules;
+use utils::seconds_to_ticks;
+use status_effect::{StatusEffect, StatusEffectKind};
+
+const CRITICAL_HIT_MULTIPLIER: f32 = 2.0;
+// sharp mandibles find the gaps in a carapace more often
+const MIN_CRITICAL_CHANCE: f32 = 0.02;
+const MAX_CRITICAL_CHANCE: f32 = 0.15;
+// how far round from straight ahead of the target an attack has to come
+// from to count as hitting its side or its rear
+const FLANK_ANGLE_RADIANS: f32 = f32::consts::PI / 3.0;
+const REAR_ANGLE_RADIANS: f32 = 2.0 * f32::consts::PI / 3.0;
+const FLANK_MULTIPLIER: f32 = 1.25;
+const REAR_MULTIPLIER: f32 = 1.5;
+// total damage a bite from the most venomous beetle possible does, spread
+// out over VENOM_DURATION_SECONDS. Armour doesn't help against it.
+const MAX_VENOM_DAMAGE: f32 = 30.0;
+const VENOM_DURATION_SECONDS: f32 = 3.0;
+// venom numbs as well, slowing the target by up to this much while it lasts
+const MAX_VENOM_SLOWDOWN: f32 = 0.3;
+const CRITICAL_HIT_STUN_SECONDS: f32 = 0.5;
+// beetles hit while under this fraction of their health fly into a rage
+const ENRAGED_HEALTH_FRACTION: f32 = 0.33;
+// extra damage per stack of rage
+const ENRAGED_ATTACK_BONUS: f32 = 0.2;
+const ENRAGED_SECONDS: f32 = 5.0;
+
+pub struct Hit {
+    pub damage: i32,
+    // status effects the target comes away with
+    pub effects: Vec<StatusEffect>,
+}
+
+// Works out how much an attack does to target. Doesn't change anything, so
+// it's up to the caller to apply the result.
+pub fn resolve_attack(
+        attacker: &Beetle, target: &Beetle, attack_power: i32,
+        rules: &Rules) -> Hit {
+
+    let mut damage = attack_power as f32;
+    let mut effects = Vec::new();
+
+    if rules.flanking {
+        let from_target = attacker.position - target.position;
+
+        if from_target.magnitude() > 0.0 {
+            // the angle is signed, and either side counts
+            let angle = target.direction.angle(from_target).0.abs();
+
+            if angle > REAR_ANGLE_RADIANS {
+                damage *= REAR_MULTIPLIER;
+            }
+            else if angle > FLANK_ANGLE_RADIANS {
+                damage *= FLANK_MULTIPLIER;
+            }
+        }
+    }
+
+    if rules.critical_hits {
+        let chance_range = MAX_CRITICAL_CHANCE - MIN_CRITICAL_CHANCE;
+        let chance = attacker.genome.get_gene(Gene::MandibleSharpness) *
+            chance_range + MIN_CRITICAL_CHANCE;
+
+        if thread_rng().gen::<f32>() < chance {
+            damage *= CRITICAL_HIT_MULTIPLIER;
+            effects.push(StatusEffect::new(
+                StatusEffectKind::Stunned, 1.0, CRITICAL_HIT_STUN_SECONDS));
+        }
+    }
+
+    damage *= 1.0 - target.armour();
+
+    let venomosity = attacker.genome.get_gene(Gene::Venomosity);
+    let venom_damage = venomosity * MAX_VENOM_DAMAGE;
+
+    if venom_damage >= 1.0 {
+        let ticks = seconds_to_ticks(VENOM_DURATION_SECONDS).max(1);
+
+        effects.push(StatusEffect::new(
+                StatusEffectKind::Poisoned, venom_damage / ticks as f32,
+                VENOM_DURATION_SECONDS)
+            .caused_by(attacker.id));
+        effects.push(StatusEffect::new(
+                StatusEffectKind::Slowed, venomosity * MAX_VENOM_SLOWDOWN,
+                VENOM_DURATION_SECONDS)
+            .caused_by(attacker.id));
+    }
+
+    Hit {
+        // a hit always does something
+        damage: (damage.round() as i32).max(1),
+        effects,
+    }
+}
+
+// Returns true if the hit killed target
+pub fn apply_hit(target: &mut Beetle, hit: Hit) -> bool {
+    let dead = target.take_damage(hit.damage);
+
+    for effect in hit.effects {
+        target.add_status_effect(effect);
+    }
+
+    if (target.health as f32) < target.max_health() as f32 * ENRAGED_HEALTH_FRACTION {
+        target.add_status_effect(StatusEffect::new(
+            StatusEffectKind::Enraged, ENRAGED_ATTACK_BONUS, ENRAGED_SECONDS));
+    }
+
+    dead
+}
+
+pub fn update_cooldowns(beetles: &mut Beetles) {
+    for beetle in beetles.values_mut() {
+        if beetle.attack_cooldown > 0 {
+            beetle.attack_cooldown -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point2, Vector2};
+    use entities::Beetle;
+    use beetle_genome::BeetleGeneIndex as Gene;
+    use rules::Rules;
+    use status_effect::StatusEffectKind;
+    use super::{resolve_attack, apply_hit};
+
+    // no armour, no venom and blunt mandibles, so only what's being tested
+    // changes the damage
+    fn plain_beetle(id: i32, x: f32, y: f32) -> Beetle {
+        let mut beetle = Beetle::new();
+        beetle.id = id;
+        beetle.position = Point2::new(x, y);
+        beetle.direction = Vector2::new(1.0, 0.0);
+        for index in 0..8 {
+            beetle.genome.set_gene_value(Gene::from(index), 0.0);
+        }
+        beetle
+    }
+
+    fn flanking() -> Rules {
+        let mut rules = Rules::new();
+        rules.flanking = true;
+        rules
+    }
+
+    fn damage_from(x: f32, y: f32, rules: &Rules) -> i32 {
+        let attacker = plain_beetle(1, x, y);
+        let target = plain_beetle(2, 0.0, 0.0);
+        resolve_attack(&attacker, &target, 100, rules).damage
+    }
+
+    #[test]
+    fn flanking_counts_from_either_side() {
+        let rules = flanking();
+        assert_eq!(damage_from(10.0, 0.0, &rules), 100);
+        assert_eq!(damage_from(0.0, 10.0, &rules), 125);
+        assert_eq!(damage_from(0.0, -10.0, &rules), 125);
+        assert_eq!(damage_from(-10.0, 1.0, &rules), 150);
+        assert_eq!(damage_from(-10.0, -1.0, &rules), 150);
+    }
+
+    #[test]
+    fn no_flanking_without_the_rule() {
+        let rules = Rules::new();
+        assert_eq!(damage_from(-10.0, 0.0, &rules), 100);
+        assert_eq!(damage_from(0.0, 10.0, &rules), 100);
+    }
+
+    #[test]
+    fn armour_soaks_up_damage() {
+        let attacker = plain_beetle(1, 10.0, 0.0);
+        let mut target = plain_beetle(2, 0.0, 0.0);
+        target.genome.set_gene_value(Gene::CarapaceDensity, 1.0);
+
+        assert_eq!(resolve_attack(&attacker, &target, 100, &Rules::new()).damage, 50);
+        // but something always gets through
+        assert_eq!(resolve_attack(&attacker, &target, 1, &Rules::new()).damage, 1);
+    }
+
+    #[test]
+    fn venom_poisons_and_slows() {
+        let mut attacker = plain_beetle(1, 10.0, 0.0);
+        let target = plain_beetle(2, 0.0, 0.0);
+
+        let hit = resolve_attack(&attacker, &target, 10, &Rules::new());
+        assert!(hit.effects.is_empty());
+
+        attacker.genome.set_gene_value(Gene::Venomosity, 1.0);
+        let hit = resolve_attack(&attacker, &target, 10, &Rules::new());
+        let kinds: Vec<StatusEffectKind> = hit.effects.iter().map(|effect| effect.kind).collect();
+        assert_eq!(kinds, vec![StatusEffectKind::Poisoned, StatusEffectKind::Slowed]);
+        assert!(hit.effects.iter().all(|effect| effect.source_id == Some(1)));
+    }
+
+    #[test]
+    fn badly_hurt_beetles_get_enraged() {
+        let attacker = plain_beetle(1, 10.0, 0.0);
+        let mut target = plain_beetle(2, 0.0, 0.0);
+        let max_health = target.max_health();
+        target.health = max_health;
+
+        let hit = resolve_attack(&attacker, &target, 1, &Rules::new());
+        assert!(!apply_hit(&mut target, hit));
+        assert!(!target.has_status_effect(StatusEffectKind::Enraged));
+
+        let hit = resolve_attack(&attacker, &target, max_health - 2, &Rules::new());
+        assert!(!apply_hit(&mut target, hit));
+        assert!(target.has_status_effect(StatusEffectKind::Enraged));
+
+        let hit = resolve_attack(&attacker, &target, 100, &Rules::new());
+        assert!(apply_hit(&mut target, hit));
+    }
+}
@@ -1,7 +1,8 @@
 use std::collections::HashMap;
-use cgmath::{Point2, InnerSpace};
+use cgmath::{Point2};
 use utils::Positioned;
-use std::f32;
+use colony::Production;
+use food_ecology::FoodType;
 
 pub use beetle::{Id, BeetleBuilder, Beetle, Beetles};
 
@@ -23,6 +24,8 @@ pub struct HomeBase {
     id: Id,
     food_stored_amount: i32,
     position: Point2<f32>,
+    team_id: Id,
+    pub production: Production,
 }
 
 impl HomeBase {
@@ -31,12 +34,22 @@ impl HomeBase {
             id: id,
             food_stored_amount: 0,
             position: Point2::new(0.0, 0.0),
+            team_id: 0,
+            production: Production::new(),
         }
     }
 
     pub fn get_food_stored_amount(&self) -> i32 {
         self.food_stored_amount
     }
+
+    pub fn get_team_id(&self) -> Id {
+        self.team_id
+    }
+
+    pub fn set_team_id(&mut self, team_id: Id) {
+        self.team_id = team_id;
+    }
 }
 
 impl Entity for HomeBase {
@@ -76,15 +89,50 @@ pub struct FoodSource {
     id: Id,
     amount: i32,
     position: Point2<f32>,
+    food_type: FoodType,
+    // regrowth stops here
+    max_amount: i32,
+    regrowth_per_second: f32,
+    // regrowth that doesn't add up to a whole piece of food yet
+    pending_regrowth: f32,
 }
 
 impl FoodSource {
-    pub fn new(id: i32) -> FoodSource {
+    pub fn with_type(
+            id: Id, food_type: FoodType, amount: i32,
+            regrowth_per_second: f32) -> FoodSource {
         FoodSource {
             id: id,
-            amount: 100,
+            amount: amount,
             position: Point2::new(0.0, 0.0),
+            food_type,
+            max_amount: amount,
+            regrowth_per_second,
+            pending_regrowth: 0.0,
+        }
+    }
+
+    pub fn food_type(&self) -> FoodType {
+        self.food_type
+    }
+
+    // Sources that regrow are left on the map when they run out
+    pub fn regrows(&self) -> bool {
+        self.regrowth_per_second > 0.0
+    }
+
+    // seconds can be a fraction of a second, or scaled by the season
+    pub fn regrow(&mut self, seconds: f32) {
+        if !self.regrows() || self.amount >= self.max_amount {
+            self.pending_regrowth = 0.0;
+            return;
         }
+
+        self.pending_regrowth += self.regrowth_per_second * seconds;
+
+        let growth = self.pending_regrowth.floor();
+        self.pending_regrowth -= growth;
+        self.amount = (self.amount + growth as i32).min(self.max_amount);
     }
 
     // TODO: impl HasFood instead
@@ -100,12 +148,6 @@ impl FoodSource {
         }
     }
 
-    // TODO: impl HasFood instead
-    pub fn increase_food(&mut self, amount: i32) -> i32 {
-        self.amount += amount;
-        self.amount
-    }
-
     pub fn amount(&self) -> i32 {
         self.amount
     }
@@ -129,21 +171,3 @@ impl Positioned for FoodSource {
         self.position = position;
     }
 }
-
-pub fn find_closest<'a, T: Entity, U: Entity>(entity: &T, collection: &'a HashMap<Id, U>) -> Option<&'a U> {
-    
-    let mut closest_distance = f32::MAX;
-    let mut closest = None;
-
-    for other in collection.values() {
-        let vector = other.get_position() - entity.get_position();
-        let distance = vector.magnitude();
-
-        if distance < closest_distance {
-            closest_distance = distance;
-            closest = Some(other);
-        }
-    }
-
-    closest
-}
@@ -4,31 +4,30 @@ use std::time::{Duration};
 use std::io::prelude::*;
 use std::fs::File;
 
-use cgmath::{Vector2, InnerSpace};
 //use serde_json;
 
-use game::{Game, FieldState, Command};
+use game::Game;
 use ui::UI;
 use entities::{Id, Beetles};
-use utils::{
-    convert_value_for_sim_period, POPULATION_SIZE,
-    MAX_SPEED_UNITS_PER_SECOND, ROTATION_RADIANS_PER_SECOND,
-    SIMULATION_PERIOD_MS
-};
+use utils::SIMULATION_PERIOD_MS;
 use simulation::Simulate;
 use simulation::fight_simulation::FightSimulation;
 use simulation::GeneticAlgorithm;
 use simulation::battle_ga::BattleGA;
 use simulation::food_ga::FoodGA;
+use scenario::Scenario;
+use game_match::{VictoryCondition, MatchState};
 
 const NUM_ITERATIONS: usize = 1024;
-const FORMATION_SPACING: f32 = 50.0;
-const FORMATION_COLUMN_WIDTH: usize = 8;
-const TEAM0_START_X: f32 = 50.0;
-const TEAM0_START_Y: f32 = 50.0;
-const TEAM1_START_X: f32 = 600.0;
-const TEAM1_START_Y: f32 = 50.0;
-const TEAM_SIZE: i32 = POPULATION_SIZE / 2;
+// both scenarios have a team for each population
+const BATTLE_SCENARIO: &str = "showdown";
+const FOOD_SCENARIO: &str = "food_race";
+// evolved by the battle GA, written to the team0 files
+const BATTLE_TEAM: &str = "Fighters";
+// evolved by the food GA, written to the team1 files
+const FOOD_TEAM: &str = "Foragers";
+// how long the food race lasts when its scenario doesn't say
+const DEFAULT_FOOD_SECONDS: f32 = 20.0;
 
 
 #[derive(Debug)]
@@ -39,13 +38,15 @@ struct SimulationResult {
 
 #[derive(Debug)]
 struct BattleResult {
-    winning_team_id: Id,
+    // None when the fight was called off before anybody won
+    winning_team: Option<String>,
     surviving_population_ratio: f32,
 }
 
 #[derive(Debug)]
 struct FoodResult {
-    winning_team_id: Id,
+    // None when both teams collected the same amount
+    winning_team: Option<String>,
     victory_ratio: f32,
 }
 
@@ -56,6 +57,9 @@ pub fn run_experiment(ui: &UI) {
     let mut team0_food_file = File::create("team0_food_victories.txt").unwrap();
     let mut team1_food_file = File::create("team1_food_victories.txt").unwrap();
 
+    let mut battle_draws = 0;
+    let mut food_draws = 0;
+
     for i in 0..NUM_ITERATIONS {
 
         println!("Iter {}", i);
@@ -63,45 +67,61 @@ pub fn run_experiment(ui: &UI) {
 
         println!("{:?}", result);
 
-        if result.battle_result.winning_team_id == 0 {
-            write!(team0_battle_file, "{}\n", result.battle_result.surviving_population_ratio).unwrap();
-        }
-        else if result.battle_result.winning_team_id == 1 {
-            write!(team1_battle_file, "{}\n", result.battle_result.surviving_population_ratio).unwrap();
-        }
-        else {
-            panic!("Invalid battle team id {}", result.battle_result.winning_team_id);
+        match result.battle_result.winning_team {
+            Some(ref team) if team == BATTLE_TEAM => {
+                write!(team0_battle_file, "{}\n", result.battle_result.surviving_population_ratio).unwrap();
+            },
+            Some(ref team) if team == FOOD_TEAM => {
+                write!(team1_battle_file, "{}\n", result.battle_result.surviving_population_ratio).unwrap();
+            },
+            Some(ref team) => {
+                panic!("Invalid battle team {}", team);
+            },
+            None => {
+                battle_draws += 1;
+            },
         }
 
-        if result.food_result.winning_team_id == 0 {
-            write!(team0_food_file, "{}\n", result.food_result.victory_ratio).unwrap();
-        }
-        else if result.food_result.winning_team_id == 1 {
-            write!(team1_food_file, "{}\n", result.food_result.victory_ratio).unwrap();
-        }
-        else {
-            panic!("Invalid food team id {}", result.food_result.winning_team_id);
+        match result.food_result.winning_team {
+            Some(ref team) if team == BATTLE_TEAM => {
+                write!(team0_food_file, "{}\n", result.food_result.victory_ratio).unwrap();
+            },
+            Some(ref team) if team == FOOD_TEAM => {
+                write!(team1_food_file, "{}\n", result.food_result.victory_ratio).unwrap();
+            },
+            Some(ref team) => {
+                panic!("Invalid food team {}", team);
+            },
+            None => {
+                food_draws += 1;
+            },
         }
     }
 
+    println!("{} of {} battles were draws", battle_draws, NUM_ITERATIONS);
+    println!("{} of {} food races were draws", food_draws, NUM_ITERATIONS);
+
     //out_file.write_all(serde_json::to_string(&experiment_result).unwrap().as_bytes()).unwrap();
 }
 
 fn run_iteration(ui: &UI) -> SimulationResult {
 
+    let battle_scenario = Scenario::builtin(BATTLE_SCENARIO).unwrap();
+    let food_scenario = Scenario::builtin(FOOD_SCENARIO).unwrap();
+
     let mut next_id = 0;
     let mut id_generator = || {
         next_id += 1;
         next_id
     };
 
-    let mut population = Beetles::new();
-
-    evolve_battle_population(&mut population, &ui, &mut id_generator);
-    evolve_food_population(&mut population, &ui, &mut id_generator);
+    let battle_beetles = evolve_battle_population(&battle_scenario, &ui, &mut id_generator);
+    let food_beetles = evolve_food_population(&food_scenario, &ui, &mut id_generator);
 
-    let battle_result = run_battle_simulation(population.clone(), ui);
-    let food_result = run_food_simulation(population.clone(), ui);
+    let battle_result = run_battle_simulation(
+        &battle_scenario, battle_beetles.clone(), food_beetles.clone(), ui);
+    let food_result = run_food_simulation(
+        &food_scenario, battle_beetles, food_beetles, ui);
 
     SimulationResult {
         battle_result,
@@ -109,29 +129,28 @@ fn run_iteration(ui: &UI) -> SimulationResult {
     }
 }
 
-fn run_food_simulation(population: Beetles, ui: &UI) -> FoodResult {
+fn run_food_simulation(
+        scenario: &Scenario, battle_beetles: Beetles, food_beetles: Beetles,
+        ui: &UI) -> FoodResult {
 
     let mut game = Game::new();
-    game.set_population(population);
-
-    game.add_home_base(TEAM0_START_X + 100.0, 500.0);
-    game.add_home_base(TEAM1_START_X + 100.0, 500.0);
-
-    game.add_food_source(TEAM0_START_X + 100.0, 350.0);
-    game.add_food_source(TEAM1_START_X + 100.0, 350.0);
-
-    for food_source in game.field_state.food_sources.values_mut() {
-        food_source.increase_food(1_000_000);
-    }
-
-    for beetle in game.field_state.beetles.values_mut() {
-        // point all beetles down
-        beetle.direction = Vector2::new(0.0, 1.0);
-        beetle.angle = Vector2::new(1.0, 0.0).angle(beetle.direction);
-        beetle.set_command(Command::HarvestClosestFood);
-    }
-
-    for _ in 0..1000 {
+    scenario.set_up_map(&mut game);
+    let team0_id = scenario.place_team(&mut game, BATTLE_TEAM, battle_beetles).unwrap();
+    let team1_id = scenario.place_team(&mut game, FOOD_TEAM, food_beetles).unwrap();
+
+    // the scenario's time limit decides how long they get, and without one
+    // the race would never end
+    let mut conditions = scenario.victory_conditions.clone();
+    let has_time_limit = conditions.iter().any(|condition| match *condition {
+        VictoryCondition::TimeLimit { .. } => true,
+        _ => false,
+    });
+    if !has_time_limit {
+        conditions.push(VictoryCondition::TimeLimit { seconds: DEFAULT_FOOD_SECONDS });
+    }
+    game.start_match(conditions);
+
+    while game.field_state.game_match.state() != MatchState::Ended {
         game.tick();
         ui.update_game_state(&game.field_state);
         thread::sleep(Duration::from_millis(SIMULATION_PERIOD_MS));
@@ -141,162 +160,91 @@ fn run_food_simulation(population: Beetles, ui: &UI) -> FoodResult {
     let mut team1_sum = 0;
 
     for beetle in game.field_state.beetles.values() {
-        if beetle.team_id == 0 {
+        if beetle.team_id == team0_id {
             team0_sum += beetle.food_collected;
         }
-        else if beetle.team_id == 1 {
+        else if beetle.team_id == team1_id {
             team1_sum += beetle.food_collected;
         }
     }
 
-    let winning_team_id;
+    // the loser's total is counted as at least 1 so a team that collected
+    // nothing doesn't make the ratio infinite
+    let winning_team;
     let victory_ratio;
 
     if team0_sum > team1_sum {
-        winning_team_id = 0;
-        victory_ratio = (team0_sum as f32) / (team1_sum as f32);
+        winning_team = Some(BATTLE_TEAM.to_string());
+        victory_ratio = (team0_sum as f32) / (team1_sum.max(1) as f32);
+    }
+    else if team1_sum > team0_sum {
+        winning_team = Some(FOOD_TEAM.to_string());
+        victory_ratio = (team1_sum as f32) / (team0_sum.max(1) as f32);
     }
     else {
-        winning_team_id = 1;
-        victory_ratio = (team1_sum as f32) / (team0_sum as f32);
+        winning_team = None;
+        victory_ratio = 1.0;
     }
 
     FoodResult {
-        winning_team_id,
+        winning_team,
         victory_ratio,
     }
 }
 
-fn run_battle_simulation(population: Beetles, ui: &UI) -> BattleResult {
-
-    let mut game = Game::new();
-    game.set_population(population);
+fn run_battle_simulation(
+        scenario: &Scenario, battle_beetles: Beetles, food_beetles: Beetles,
+        ui: &UI) -> BattleResult {
 
-    let check_done_callback = |state: &FieldState| {
-        // whatever the first beetle's team is, make sure there are no
-        // enemies left.
-        if let Some(beetle) = state.beetles.iter().next() {
-            let team_id = beetle.1.team_id;
+    let team_size = battle_beetles.len();
 
-            for other in state.beetles.values() {
-                if other.team_id != team_id {
-                    return false;
-                }
-            }
-
-            true
-        }
-        else {
-            true
-        }
-    };
+    let mut game = Game::new();
+    scenario.set_up_map(&mut game);
+    scenario.place_team(&mut game, BATTLE_TEAM, battle_beetles).unwrap();
+    scenario.place_team(&mut game, FOOD_TEAM, food_beetles).unwrap();
 
-    {
-        let mut sim = FightSimulation::new(&mut game, check_done_callback);
+    let winners = {
+        let mut sim = FightSimulation::new(
+            &mut game, vec![VictoryCondition::LastTeamStanding]);
         sim.set_tick_callback(|state| {
             ui.update_game_state(&state);
             thread::sleep(Duration::from_millis(SIMULATION_PERIOD_MS));
         });
         sim.run();
-    }
 
-    let mut winning_team_id = -1;
-    if let Some(beetle) = game.field_state.beetles.iter().next() {
-        winning_team_id = beetle.1.team_id;
-    }
+        sim.result().map(|result| result.winners.clone()).unwrap_or(Vec::new())
+    };
 
-    let surviving_population_ratio = (game.field_state.beetles.len() as f32) / (TEAM_SIZE as f32);
+    // a stalemate or running out of time leaves nobody winning
+    let winning_team = winners.first().cloned()
+        .and_then(|team_id| game.field_state.teams.get(team_id))
+        .map(|team| team.name.clone());
+
+    let surviving_population_ratio = (game.field_state.beetles.len() as f32) / (team_size as f32);
 
     BattleResult {
-        winning_team_id,
+        winning_team,
         surviving_population_ratio,
     }
 
 }
 
 fn evolve_battle_population<T: FnMut() -> Id>(
-        population: &mut Beetles, ui: &UI, id_generator: &mut T) {
-
-    let mut row = -1;
-
-    let max_speed =
-        convert_value_for_sim_period(MAX_SPEED_UNITS_PER_SECOND);
-
-    let max_rotation =
-        convert_value_for_sim_period(ROTATION_RADIANS_PER_SECOND);
-
-    let mut battle_beetles = Game::generate_random_population(
-            TEAM_SIZE, max_speed, max_rotation, id_generator);
-
-    {
-        let mut ga = BattleGA::new(battle_beetles, &ui);
-        ga.run();
-        battle_beetles = ga.get_population().clone();
-    }
-
-    for (i, (_, mut beetle)) in battle_beetles.into_iter().enumerate() {
+        scenario: &Scenario, ui: &UI, id_generator: &mut T) -> Beetles {
 
-        if i % FORMATION_COLUMN_WIDTH == 0 {
-            row += 1;
-        }
+    let battle_beetles = scenario.team_beetles(BATTLE_TEAM, id_generator).unwrap();
 
-        let x_offset = (i % FORMATION_COLUMN_WIDTH) as f32;
-        let y_offset = row as f32;
-        let x = TEAM0_START_X + x_offset * FORMATION_SPACING;
-        let y = TEAM0_START_Y + y_offset * FORMATION_SPACING;
-
-        beetle.id = id_generator();
-        beetle.position.x = x;
-        beetle.position.y = y;
-        beetle.direction = Vector2::new(1.0, 0.0);
-        beetle.angle = Vector2::new(1.0, 0.0).angle(beetle.direction);
-        beetle.team_id = 0;
-        beetle.damage_inflicted = 0;
-        beetle.health = beetle.max_health();
-        population.insert(beetle.id, beetle);
-    }
+    let mut ga = BattleGA::new(battle_beetles, &ui, Scenario::builtin("melee").unwrap());
+    ga.run();
+    ga.get_population().clone()
 }
 
 fn evolve_food_population<T: FnMut() -> Id>(
-        population: &mut Beetles, ui: &UI, id_generator: &mut T) {
-
-    let mut row = -1;
+        scenario: &Scenario, ui: &UI, id_generator: &mut T) -> Beetles {
 
-    let max_speed =
-        convert_value_for_sim_period(MAX_SPEED_UNITS_PER_SECOND);
+    let food_beetles = scenario.team_beetles(FOOD_TEAM, id_generator).unwrap();
 
-    let max_rotation =
-        convert_value_for_sim_period(ROTATION_RADIANS_PER_SECOND);
-
-    let mut food_beetles = Game::generate_random_population(
-            TEAM_SIZE, max_speed, max_rotation, id_generator);
-
-    {
-        let mut ga = FoodGA::new(food_beetles, &ui);
-        ga.run();
-        food_beetles = ga.get_population().clone();
-    }
-
-    for (i, (_, mut beetle)) in food_beetles.into_iter().enumerate() {
-
-        if i % FORMATION_COLUMN_WIDTH == 0 {
-            row += 1;
-        }
-
-        let x_offset = (i % FORMATION_COLUMN_WIDTH) as f32;
-        let y_offset = row as f32;
-        let x = TEAM1_START_X + x_offset * FORMATION_SPACING;
-        let y = TEAM1_START_Y + y_offset * FORMATION_SPACING;
-
-        beetle.id = id_generator();
-        beetle.position.x = x;
-        beetle.position.y = y;
-        beetle.team_id = 0;
-        beetle.direction = Vector2::new(-1.0, 0.0);
-        beetle.angle = Vector2::new(1.0, 0.0).angle(beetle.direction);
-        beetle.team_id = 1;
-        beetle.food_collected = 0;
-        beetle.food_carrying = 0;
-        population.insert(beetle.id, beetle);
-    }
+    let mut ga = FoodGA::new(food_beetles, &ui, Scenario::builtin("foraging").unwrap());
+    ga.run();
+    ga.get_population().clone()
 }
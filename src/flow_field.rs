@@ -0,0 +1,244 @@
+use std::collections::{BinaryHeap, HashSet};
+use std::cmp::Ordering;
+use std::f32;
+use cgmath::{Point2, Vector2, InnerSpace};
+
+use entities::{Beetle, Beetles, Id};
+use beetle::MAX_COLLISION_RADIUS_UNITS;
+use pathfinding::NavGrid;
+use spatial_index::SpatialGrid;
+
+// how far ahead of itself a beetle aims when following the field
+const LOOKAHEAD_UNITS: f32 = 20.0;
+// extra room beetles try to keep between each other on top of their bodies
+const SEPARATION_PADDING_UNITS: f32 = 10.0;
+const SEPARATION_WEIGHT: f32 = 1.5;
+// how far from the goal a group can end up, relative to the tightest
+// possible packing
+const ARRIVAL_PACKING_FACTOR: f32 = 1.5;
+
+// Every cell of the nav grid points along the cheapest route to a single
+// goal, so any number of beetles can share one search instead of each
+// planning their own path.
+#[derive(Debug)]
+pub struct FlowField {
+    goal: Point2<f32>,
+    arrival_radius: f32,
+    // the beetles sent along the field, so a group doesn't stop short on
+    // bystanders that happen to be standing around
+    members: HashSet<Id>,
+    // members that have stopped at the goal, even if they've since moved on
+    // to their next command
+    arrived: HashSet<Id>,
+    width: i32,
+    directions: Vec<Option<Vector2<f32>>>,
+}
+
+impl FlowField {
+
+    // group_area is the total area taken up by the beetles following the
+    // field, which decides how far out from the goal the group can pack.
+    pub fn new(
+            nav_grid: &NavGrid, goal: Point2<f32>, members: HashSet<Id>,
+            group_area: f32) -> FlowField {
+
+        let (width, height) = nav_grid.dimensions();
+        let num_cells = (width * height) as usize;
+        let index = |cell: (i32, i32)| (cell.1 * width + cell.0) as usize;
+
+        // Dijkstra outward from the goal
+        let mut costs = vec![f32::MAX; num_cells];
+        let mut open = BinaryHeap::new();
+
+        // nobody could ever get close to a goal inside a wall, so it's moved
+        // to the closest spot they can get to
+        let mut goal = goal;
+
+        if let Some(goal_cell) = nav_grid.nearest_open_cell(nav_grid.cell_for(goal)) {
+            if goal_cell != nav_grid.cell_for(goal) {
+                goal = nav_grid.cell_center(goal_cell);
+            }
+            costs[index(goal_cell)] = 0.0;
+            open.push(Node { cell: goal_cell, cost: 0.0 });
+        }
+
+        while let Some(Node { cell, cost }) = open.pop() {
+            if cost > costs[index(cell)] {
+                continue;
+            }
+
+            for (next, step_cost) in nav_grid.neighbours(cell) {
+                let new_cost = cost + step_cost;
+                if new_cost < costs[index(next)] {
+                    costs[index(next)] = new_cost;
+                    open.push(Node { cell: next, cost: new_cost });
+                }
+            }
+        }
+
+        // then each cell points at its cheapest neighbour. Blocked cells next
+        // to open ones get a direction too, since beetles regularly get
+        // pushed into the padding around walls.
+        let mut directions = vec![None; num_cells];
+
+        for y in 0..height {
+            for x in 0..width {
+                let cell = (x, y);
+                let cost = costs[index(cell)];
+
+                if cost == 0.0 {
+                    continue;
+                }
+
+                let mut best = None;
+                let mut best_cost = cost;
+
+                for (next, _) in nav_grid.neighbours(cell) {
+                    if costs[index(next)] < best_cost {
+                        best_cost = costs[index(next)];
+                        best = Some(next);
+                    }
+                }
+
+                if let Some(next) = best {
+                    let vector = nav_grid.cell_center(next) - nav_grid.cell_center(cell);
+                    directions[index(cell)] = Some(vector.normalize());
+                }
+            }
+        }
+
+        let arrival_radius =
+            ((group_area / f32::consts::PI).sqrt()) * ARRIVAL_PACKING_FACTOR;
+
+        FlowField {
+            goal,
+            arrival_radius,
+            members,
+            arrived: HashSet::new(),
+            width,
+            directions,
+        }
+    }
+
+    pub fn mark_arrived(&mut self, id: Id) {
+        if self.members.contains(&id) {
+            self.arrived.insert(id);
+        }
+    }
+
+    // The first beetles in stop right on the goal. Everyone after that stops
+    // as soon as they bump into an arrived member that's closer in, so the
+    // group packs itself around the goal instead of the back of the group
+    // shoving the front forever.
+    pub fn has_arrived(
+            &self, beetle: &Beetle, beetles: &Beetles, grid: &SpatialGrid) -> bool {
+
+        let distance = (self.goal - beetle.position).magnitude();
+        let radius = beetle.collision_radius();
+
+        if distance < radius {
+            return true;
+        }
+
+        if distance > self.arrival_radius {
+            return false;
+        }
+        let query_radius = radius + MAX_COLLISION_RADIUS_UNITS + SEPARATION_PADDING_UNITS;
+
+        grid.query_radius(beetle.position, query_radius).iter().any(|id| {
+            match beetles.get(id) {
+                Some(other) => {
+                    other.id != beetle.id &&
+                        self.arrived.contains(&other.id) &&
+                        (self.goal - other.position).magnitude() < distance &&
+                        (other.position - beetle.position).magnitude() <
+                            radius + other.collision_radius() + SEPARATION_PADDING_UNITS
+                },
+                None => false,
+            }
+        })
+    }
+
+    fn direction_at(&self, nav_grid: &NavGrid, position: Point2<f32>) -> Option<Vector2<f32>> {
+        let cell = nav_grid.cell_for(position);
+        self.directions[(cell.1 * self.width + cell.0) as usize]
+    }
+
+    // Where a beetle following this field should steer this tick. Mostly
+    // follows the field, but gets pushed away from nearby beetles so the
+    // group spreads out instead of squeezing into single file.
+    pub fn steering_target(
+            &self, beetle: &Beetle, beetles: &Beetles, grid: &SpatialGrid,
+            nav_grid: &NavGrid) -> Point2<f32> {
+
+        // head straight for the goal when nothing's in the way rather than
+        // zig-zagging along the grid
+        let to_goal = self.goal - beetle.position;
+        let mut direction = if nav_grid.line_of_sight(beetle.position, self.goal) {
+            if to_goal.magnitude() > 0.0 {
+                to_goal.normalize()
+            }
+            else {
+                Vector2::new(0.0, 0.0)
+            }
+        }
+        else {
+            match self.direction_at(nav_grid, beetle.position) {
+                Some(direction) => direction,
+                None => to_goal.normalize(),
+            }
+        };
+
+        let radius = beetle.collision_radius();
+        let query_radius = radius * 4.0 + SEPARATION_PADDING_UNITS;
+
+        let mut separation = Vector2::new(0.0, 0.0);
+
+        for other_id in grid.query_radius(beetle.position, query_radius) {
+            if other_id == beetle.id {
+                continue;
+            }
+
+            if let Some(other) = beetles.get(&other_id) {
+                let away = beetle.position - other.position;
+                let distance = away.magnitude();
+                let comfortable = radius + other.collision_radius() +
+                    SEPARATION_PADDING_UNITS;
+
+                if distance > 0.0 && distance < comfortable {
+                    separation += (away / distance) * (1.0 - distance / comfortable);
+                }
+            }
+        }
+
+        direction += separation * SEPARATION_WEIGHT;
+
+        if direction.magnitude() > 0.0 {
+            beetle.position + direction.normalize() * LOOKAHEAD_UNITS
+        }
+        else {
+            self.goal
+        }
+    }
+}
+
+#[derive(PartialEq)]
+struct Node {
+    cell: (i32, i32),
+    cost: f32,
+}
+
+impl Eq for Node {}
+
+// BinaryHeap is a max heap, so lower costs need to compare as greater
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
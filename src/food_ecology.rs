@@ -0,0 +1,245 @@
+use cgmath::Point2;
+use rand::{Rng, thread_rng};
+
+use entities::{FoodSource, FoodSources};
+use world::World;
+use utils::{Positioned, seconds_to_ticks};
+
+// new sources won't be placed inside walls, but give up on a spawn rather
+// than trying forever
+const MAX_SPAWN_ATTEMPTS: u32 = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct FoodType {
+    // stored food a home base gets for every piece delivered. Eating a piece
+    // is worth the same.
+    pub food_yield: i32,
+    // how much of a beetle's carrying capacity each piece takes up
+    pub carry_cost: i32,
+}
+
+impl FoodType {
+    pub fn new() -> FoodType {
+        FoodType {
+            food_yield: 1,
+            carry_cost: 1,
+        }
+    }
+}
+
+// An area of the map where food sources can show up
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpawnRegion {
+    pub min: Point2<f32>,
+    pub max: Point2<f32>,
+    pub food_type: FoodType,
+    // what each new source starts with, which is also the most it can regrow
+    // to
+    pub amount: i32,
+    // food regained every second. Sources that regrow stay put when they're
+    // emptied, the rest disappear.
+    pub regrowth_per_second: f32,
+    // how many sources are placed here when the ecology is set up
+    pub initial_sources: u32,
+    // chance of new sources spawning here rather than in the other regions,
+    // relative to their weights
+    pub weight: f32,
+}
+
+impl SpawnRegion {
+    fn spawn<R: Rng>(&self, world: &World, rng: &mut R) -> Option<FoodSource> {
+        for _ in 0..MAX_SPAWN_ATTEMPTS {
+            let position = Point2::new(
+                rng.gen_range(self.min.x, self.max.x.max(self.min.x + 1.0)),
+                rng.gen_range(self.min.y, self.max.y.max(self.min.y + 1.0)));
+
+            if world.walls.iter().any(|wall| wall.contains(position)) {
+                continue;
+            }
+
+            let mut food_source = FoodSource::with_type(
+                0, self.food_type, self.amount, self.regrowth_per_second);
+            food_source.set_position(position);
+            return Some(food_source);
+        }
+
+        None
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Season {
+    pub name: String,
+    pub seconds: f32,
+    // new sources per minute, shared out between the regions
+    pub spawns_per_minute: f32,
+    // scales how fast every source regrows
+    pub regrowth_multiplier: f32,
+}
+
+// Where food comes from over the course of a game. With no regions or
+// seasons nothing ever spawns or changes, and the food on the map is all
+// there is.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FoodEcology {
+    pub regions: Vec<SpawnRegion>,
+    // cycled through in order, forever
+    pub seasons: Vec<Season>,
+    // nothing new spawns while there are this many sources on the map
+    pub max_sources: usize,
+    #[serde(skip)]
+    ticks: u64,
+}
+
+impl FoodEcology {
+    pub fn new() -> FoodEcology {
+        FoodEcology {
+            regions: Vec::new(),
+            seasons: Vec::new(),
+            max_sources: 0,
+            ticks: 0,
+        }
+    }
+
+    // Small patches of food spread around the map that only just keep up
+    // with demand in the good seasons, and not at all in winter
+    pub fn scarce(width: f32, height: f32) -> FoodEcology {
+        let region = |min: (f32, f32), max: (f32, f32), food_type, amount,
+                      regrowth_per_second, initial_sources, weight| {
+            SpawnRegion {
+                min: Point2::new(min.0 * width, min.1 * height),
+                max: Point2::new(max.0 * width, max.1 * height),
+                food_type,
+                amount,
+                regrowth_per_second,
+                initial_sources,
+                weight,
+            }
+        };
+
+        let seeds = FoodType { food_yield: 1, carry_cost: 1 };
+        let fruit = FoodType { food_yield: 3, carry_cost: 2 };
+        let carrion = FoodType { food_yield: 8, carry_cost: 4 };
+
+        let season = |name: &str, spawns_per_minute, regrowth_multiplier| {
+            Season {
+                name: name.to_string(),
+                seconds: 30.0,
+                spawns_per_minute,
+                regrowth_multiplier,
+            }
+        };
+
+        FoodEcology {
+            regions: vec![
+                // a meadow near the middle that regrows
+                region((0.35, 0.35), (0.65, 0.65), seeds, 20, 0.5, 2, 1.0),
+                // orchards out on the edges
+                region((0.05, 0.05), (0.3, 0.95), fruit, 15, 0.0, 1, 0.5),
+                region((0.7, 0.05), (0.95, 0.95), fruit, 15, 0.0, 1, 0.5),
+                // the odd carcass, anywhere
+                region((0.0, 0.0), (1.0, 1.0), carrion, 10, 0.0, 0, 0.2),
+            ],
+            seasons: vec![
+                season("Spring", 6.0, 1.5),
+                season("Summer", 3.0, 1.0),
+                season("Autumn", 2.0, 0.5),
+                season("Winter", 0.0, 0.1),
+            ],
+            max_sources: 12,
+            ticks: 0,
+        }
+    }
+
+    pub fn current_season(&self) -> Option<&Season> {
+        let cycle_ticks: u64 = self.seasons.iter()
+            .map(|season| seconds_to_ticks(season.seconds) as u64)
+            .sum();
+
+        if cycle_ticks == 0 {
+            return self.seasons.first();
+        }
+
+        let mut tick = self.ticks % cycle_ticks;
+        for season in self.seasons.iter() {
+            let season_ticks = seconds_to_ticks(season.seconds) as u64;
+            if tick < season_ticks {
+                return Some(season);
+            }
+            tick -= season_ticks;
+        }
+
+        None
+    }
+
+    // Whether food can still turn up on a map that doesn't have any
+    pub fn spawns_food(&self) -> bool {
+        !self.regions.is_empty() &&
+            self.seasons.iter().any(|season| season.spawns_per_minute > 0.0)
+    }
+
+    // Starts the seasons over and returns the sources the map should begin
+    // with
+    pub fn reset<R: Rng>(&mut self, world: &World, rng: &mut R) -> Vec<FoodSource> {
+        self.ticks = 0;
+
+        let mut food_sources = Vec::new();
+        for region in self.regions.iter() {
+            for _ in 0..region.initial_sources {
+                if let Some(food_source) = region.spawn(world, rng) {
+                    food_sources.push(food_source);
+                }
+            }
+        }
+
+        food_sources
+    }
+
+    fn pick_region<R: Rng>(&self, rng: &mut R) -> Option<&SpawnRegion> {
+        let total_weight: f32 = self.regions.iter().map(|region| region.weight).sum();
+        if total_weight <= 0.0 {
+            return None;
+        }
+
+        let mut choice = rng.gen_range(0.0, total_weight);
+        for region in self.regions.iter() {
+            if choice < region.weight {
+                return Some(region);
+            }
+            choice -= region.weight;
+        }
+
+        self.regions.last()
+    }
+
+    // Regrows every source a tick's worth and maybe spawns a new one.
+    // Returns anything new and leaves it to the caller to give it an id and
+    // add it to the game.
+    pub fn update(&mut self, food_sources: &mut FoodSources, world: &World) -> Vec<FoodSource> {
+        self.ticks += 1;
+
+        let ticks_per_second = seconds_to_ticks(1.0) as f32;
+
+        let (regrowth_multiplier, spawns_per_minute) = match self.current_season() {
+            Some(season) => (season.regrowth_multiplier, season.spawns_per_minute),
+            None => (1.0, 0.0),
+        };
+
+        for food_source in food_sources.values_mut() {
+            food_source.regrow(regrowth_multiplier / ticks_per_second);
+        }
+
+        let mut spawned = Vec::new();
+
+        let mut rng = thread_rng();
+        let spawn_chance = spawns_per_minute / (ticks_per_second * 60.0);
+        if food_sources.len() < self.max_sources && rng.gen::<f32>() < spawn_chance {
+            let region = self.pick_region(&mut rng);
+            if let Some(food_source) = region.and_then(|region| region.spawn(world, &mut rng)) {
+                spawned.push(food_source);
+            }
+        }
+
+        spawned
+    }
+}
@@ -0,0 +1,624 @@
+use std::f32;
+use cgmath::{Point2, Vector2, InnerSpace};
+
+use entities::{Id, Beetle, Beetles};
+use game::Command;
+use pathfinding::{NavGrid, next_waypoint};
+
+// gap left between the biggest beetles in a formation
+const FORMATION_PADDING_UNITS: f32 = 10.0;
+// lines wider than this get extra ranks behind them
+const MAX_LINE_WIDTH: usize = 16;
+const COLUMN_WIDTH: usize = 3;
+// how close to its slot a beetle needs to be to count as in formation
+const SLOT_TOLERANCE_UNITS: f32 = 8.0;
+// the group stops and waits if anyone falls further behind than this
+const MAX_LAG_UNITS: f32 = 24.0;
+// stragglers that can't reach their slot, say because something's in the
+// way, don't hold the group up forever
+const MAX_FORMING_TICKS: u32 = 300;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum FormationShape {
+    Line,
+    Column,
+    Wedge,
+    Circle,
+    Box,
+}
+
+// Layout of a group relative to an anchor point. Slots are laid out with the
+// front of the formation pointing along facing and the anchor in the middle.
+#[derive(Serialize, Debug, Clone)]
+pub struct Formation {
+    pub shape: FormationShape,
+    pub facing: Vector2<f32>,
+    pub spacing: f32,
+}
+
+impl Formation {
+    pub fn new(shape: FormationShape, facing: Vector2<f32>, spacing: f32) -> Formation {
+        Formation {
+            shape,
+            facing: if facing.magnitude() > 0.0 {
+                facing.normalize()
+            }
+            else {
+                Vector2::new(1.0, 0.0)
+            },
+            spacing,
+        }
+    }
+
+    // Offsets from the anchor for each slot, already rotated to match facing
+    pub fn slot_offsets(&self, num_slots: usize) -> Vec<Vector2<f32>> {
+
+        let forward = self.facing;
+        let right = Vector2::new(-forward.y, forward.x);
+
+        self.local_offsets(num_slots).iter().map(|&(f, r)| {
+            forward * f * self.spacing + right * r * self.spacing
+        }).collect()
+    }
+
+    // Slots as (forward, right) pairs in units of spacing, centred on 0,0
+    fn local_offsets(&self, num_slots: usize) -> Vec<(f32, f32)> {
+
+        let mut offsets = match self.shape {
+            FormationShape::Line => ranks(num_slots, MAX_LINE_WIDTH),
+            FormationShape::Column => ranks(num_slots, COLUMN_WIDTH),
+            FormationShape::Box => {
+                ranks(num_slots, (num_slots as f32).sqrt().ceil() as usize)
+            },
+            FormationShape::Wedge => wedge(num_slots),
+            FormationShape::Circle => rings(num_slots),
+        };
+
+        if offsets.len() > 0 {
+            let count = offsets.len() as f32;
+            let center_f = offsets.iter().map(|o| o.0).sum::<f32>() / count;
+            let center_r = offsets.iter().map(|o| o.1).sum::<f32>() / count;
+
+            for offset in offsets.iter_mut() {
+                offset.0 -= center_f;
+                offset.1 -= center_r;
+            }
+        }
+
+        offsets
+    }
+}
+
+// Rows of up to width beetles, one behind the other. A partly filled back
+// row is centred behind the others.
+fn ranks(num_slots: usize, width: usize) -> Vec<(f32, f32)> {
+
+    let width = width.max(1);
+    let mut offsets = Vec::with_capacity(num_slots);
+
+    for i in 0..num_slots {
+        let rank = i / width;
+        let file = i % width;
+        let in_rank = width.min(num_slots - rank * width);
+
+        offsets.push((-(rank as f32), file as f32 - (in_rank - 1) as f32 / 2.0));
+    }
+
+    offsets
+}
+
+// Single beetle at the tip, each row behind it two wider than the last
+fn wedge(num_slots: usize) -> Vec<(f32, f32)> {
+
+    let mut offsets = Vec::with_capacity(num_slots);
+    let mut row = 0;
+
+    while offsets.len() < num_slots {
+        let in_row = (2 * row + 1).min(num_slots - offsets.len());
+
+        for j in 0..in_row {
+            offsets.push((-(row as f32), j as f32 - (in_row - 1) as f32 / 2.0));
+        }
+
+        row += 1;
+    }
+
+    offsets
+}
+
+// Concentric rings one spacing apart, each holding as many beetles as fit
+// around it
+fn rings(num_slots: usize) -> Vec<(f32, f32)> {
+
+    let mut offsets = Vec::with_capacity(num_slots);
+
+    if num_slots > 0 {
+        offsets.push((0.0, 0.0));
+    }
+
+    let mut ring = 1;
+
+    while offsets.len() < num_slots {
+        let capacity = (2.0 * f32::consts::PI * ring as f32).floor() as usize;
+        let in_ring = capacity.min(num_slots - offsets.len());
+
+        for j in 0..in_ring {
+            let angle = 2.0 * f32::consts::PI * (j as f32 / in_ring as f32);
+            offsets.push((ring as f32 * angle.cos(), ring as f32 * angle.sin()));
+        }
+
+        ring += 1;
+    }
+
+    offsets
+}
+
+// Far enough apart that even the biggest beetle in the group has some room
+pub fn spacing_for(beetles: &[&Beetle]) -> f32 {
+    let biggest = beetles.iter()
+        .map(|beetle| beetle.body_length())
+        .fold(0.0, f32::max);
+
+    biggest + FORMATION_PADDING_UNITS
+}
+
+// Gives each beetle a slot such that the total distance travelled is as
+// small as possible. Returns the slot index for each position, in order.
+pub fn assign_slots(positions: &[Point2<f32>], slots: &[Point2<f32>]) -> Vec<usize> {
+
+    let costs: Vec<Vec<f32>> = positions.iter().map(|position| {
+        slots.iter().map(|slot| (slot - position).magnitude()).collect()
+    }).collect();
+
+    hungarian(&costs)
+}
+
+// Hungarian algorithm for the assignment problem, O(n^3). Needs at least as
+// many columns as rows.
+fn hungarian(costs: &Vec<Vec<f32>>) -> Vec<usize> {
+
+    let n = costs.len();
+
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let m = costs[0].len();
+
+    // everything is 1-indexed so that 0 can mean unassigned
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; m + 1];
+    let mut assigned_row = vec![0; m + 1];
+    let mut way = vec![0; m + 1];
+
+    for i in 1..(n + 1) {
+        assigned_row[0] = i;
+        let mut j0 = 0;
+        let mut min_values = vec![f32::MAX; m + 1];
+        let mut used = vec![false; m + 1];
+
+        loop {
+            used[j0] = true;
+            let i0 = assigned_row[j0];
+            let mut delta = f32::MAX;
+            let mut j1 = 0;
+
+            for j in 1..(m + 1) {
+                if !used[j] {
+                    let reduced = costs[i0 - 1][j - 1] - u[i0] - v[j];
+
+                    if reduced < min_values[j] {
+                        min_values[j] = reduced;
+                        way[j] = j0;
+                    }
+
+                    if min_values[j] < delta {
+                        delta = min_values[j];
+                        j1 = j;
+                    }
+                }
+            }
+
+            for j in 0..(m + 1) {
+                if used[j] {
+                    u[assigned_row[j]] += delta;
+                    v[j] -= delta;
+                }
+                else {
+                    min_values[j] -= delta;
+                }
+            }
+
+            j0 = j1;
+
+            if assigned_row[j0] == 0 {
+                break;
+            }
+        }
+
+        // walk back along the augmenting path
+        loop {
+            let j1 = way[j0];
+            assigned_row[j0] = assigned_row[j1];
+            j0 = j1;
+
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assignment = vec![0; n];
+    for j in 1..(m + 1) {
+        if assigned_row[j] != 0 {
+            assignment[assigned_row[j] - 1] = j - 1;
+        }
+    }
+
+    assignment
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum FormationState {
+    // members making their way to their slots
+    Forming,
+    // whole group moving toward the target together
+    Marching,
+    // in formation with nowhere to go
+    Holding,
+}
+
+// A set of beetles that move as one. The anchor moves at the speed of the
+// slowest member and everyone else keeps pace in their slot around it.
+#[derive(Serialize, Debug)]
+pub struct FormationGroup {
+    pub id: Id,
+    pub formation: Formation,
+    // members[i] fills slot i
+    pub members: Vec<Id>,
+    pub anchor: Point2<f32>,
+    pub target: Option<Point2<f32>>,
+    // waypoints the anchor still has to pass on the way to target
+    route: Vec<Point2<f32>>,
+    pub state: FormationState,
+    offsets: Vec<Vector2<f32>>,
+    speed: f32,
+    ticks_in_state: u32,
+    // how long the march has been held up by stragglers
+    ticks_waiting: u32,
+}
+
+impl FormationGroup {
+    pub fn new(
+            id: Id, formation: Formation, anchor: Point2<f32>,
+            beetles: &[&Beetle]) -> FormationGroup {
+
+        let mut group = FormationGroup {
+            id,
+            formation,
+            members: beetles.iter().map(|beetle| beetle.id).collect(),
+            anchor,
+            target: None,
+            route: Vec::new(),
+            state: FormationState::Forming,
+            offsets: Vec::new(),
+            speed: 0.0,
+            ticks_in_state: 0,
+            ticks_waiting: 0,
+        };
+
+        group.assign_slots(beetles);
+
+        group
+    }
+
+    // Lays the slots out for the current facing and hands them out with as
+    // little walking as possible.
+    fn assign_slots(&mut self, beetles: &[&Beetle]) {
+
+        let offsets = self.formation.slot_offsets(beetles.len());
+        let slots: Vec<Point2<f32>> =
+            offsets.iter().map(|offset| self.anchor + offset).collect();
+        let positions: Vec<Point2<f32>> =
+            beetles.iter().map(|beetle| beetle.position).collect();
+
+        let assignment = assign_slots(&positions, &slots);
+
+        let mut members = vec![0; beetles.len()];
+        for (beetle, slot_index) in beetles.iter().zip(assignment) {
+            members[slot_index] = beetle.id;
+        }
+
+        self.members = members;
+        self.offsets = offsets;
+        self.speed = beetles.iter()
+            .map(|beetle| beetle.speed())
+            .fold(f32::MAX, f32::min);
+    }
+
+    // Slots that end up inside a wall are moved to the closest open spot
+    pub fn slot_position(&self, beetle_id: Id, nav_grid: &NavGrid) -> Option<Point2<f32>> {
+        self.members.iter()
+            .position(|id| *id == beetle_id)
+            .map(|index| nav_grid.nearest_open_position(self.anchor + self.offsets[index]))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.len() == 0
+    }
+
+    // Plans a route around any walls to the target, then marches it one leg
+    // at a time, turning to face each leg before setting off.
+    pub fn move_to(&mut self, target: Point2<f32>, beetles: &Beetles, nav_grid: &NavGrid) {
+
+        self.route = nav_grid.find_path(self.anchor, target)
+            .unwrap_or(vec![target]);
+        self.target = Some(target);
+        self.face_next_leg(beetles);
+    }
+
+    fn face_next_leg(&mut self, beetles: &Beetles) {
+        if let Some(&waypoint) = self.route.first() {
+            let travel = waypoint - self.anchor;
+            if travel.magnitude() > 0.0 {
+                self.formation.facing = travel.normalize();
+            }
+        }
+
+        self.reform(beetles);
+    }
+
+    fn reform(&mut self, beetles: &Beetles) {
+        let members: Vec<&Beetle> = self.members.iter()
+            .filter_map(|id| beetles.get(id))
+            .collect();
+
+        self.assign_slots(&members);
+        self.set_state(FormationState::Forming);
+    }
+
+    fn set_state(&mut self, state: FormationState) {
+        self.state = state;
+        self.ticks_in_state = 0;
+    }
+
+    // Called once a tick before the beetles act
+    pub fn update(&mut self, beetles: &Beetles, nav_grid: &NavGrid) {
+
+        // anyone who died or was given other orders has left the group
+        let id = self.id;
+        let num_members = self.members.len();
+        self.members.retain(|member_id| {
+            match beetles.get(member_id) {
+                Some(beetle) => {
+                    beetle.current_command == Command::HoldFormation{ group_id: id }
+                },
+                None => false,
+            }
+        });
+
+        if self.members.len() == 0 {
+            return;
+        }
+
+        if self.members.len() != num_members {
+            // close up the gaps
+            self.reform(beetles);
+        }
+
+        self.ticks_in_state += 1;
+
+        // members whose slot is in a wall are doing their best somewhere
+        // nearby, and can't hold the group up
+        let max_lag = self.members.iter().zip(self.offsets.iter())
+            .filter_map(|(member_id, offset)| {
+                let slot = self.anchor + offset;
+                if nav_grid.is_blocked(nav_grid.cell_for(slot)) {
+                    return None;
+                }
+                beetles.get(member_id).map(|beetle| {
+                    (slot - beetle.position).magnitude()
+                })
+            })
+            .fold(0.0, f32::max);
+
+        match self.state {
+            FormationState::Forming => {
+                if max_lag < SLOT_TOLERANCE_UNITS ||
+                        self.ticks_in_state > MAX_FORMING_TICKS {
+                    if self.target.is_some() {
+                        self.set_state(FormationState::Marching);
+                    }
+                    else {
+                        self.set_state(FormationState::Holding);
+                    }
+                }
+            },
+            FormationState::Marching => {
+                match self.route.first().cloned() {
+                    Some(waypoint) => {
+                        // wait for anyone who's dropped behind, but not
+                        // forever, since they might be stuck on a wall
+                        if max_lag < MAX_LAG_UNITS {
+                            self.ticks_waiting = 0;
+                        }
+                        else {
+                            self.ticks_waiting += 1;
+                        }
+
+                        if self.ticks_waiting == 0 || self.ticks_waiting > MAX_FORMING_TICKS {
+                            let remaining = waypoint - self.anchor;
+                            let distance = remaining.magnitude();
+
+                            if distance <= self.speed {
+                                self.anchor = waypoint;
+                                self.route.remove(0);
+
+                                if self.route.len() > 0 {
+                                    self.face_next_leg(beetles);
+                                }
+                                else {
+                                    self.target = None;
+                                    self.set_state(FormationState::Forming);
+                                }
+                            }
+                            else {
+                                self.anchor += (remaining / distance) * self.speed;
+                            }
+                        }
+                    },
+                    None => {
+                        self.target = None;
+                        self.set_state(FormationState::Forming);
+                    },
+                }
+            },
+            FormationState::Holding => {},
+        }
+    }
+
+    // Moves a single member this tick
+    pub fn steer(&self, beetle: &mut Beetle, nav_grid: &NavGrid) {
+
+        let slot = match self.slot_position(beetle.id, nav_grid) {
+            Some(slot) => slot,
+            None => return,
+        };
+
+        let distance = (slot - beetle.position).magnitude();
+
+        match self.state {
+            FormationState::Marching => {
+                if nav_grid.line_of_sight(beetle.position, slot) {
+                    // keep pace with the slot rather than running ahead of it
+                    let speed = beetle.speed().min(distance);
+                    beetle.clear_path();
+                    beetle.move_toward_with_speed(&slot, speed);
+                }
+                else {
+                    // a slot moved out of a wall can end up round a corner
+                    let waypoint = next_waypoint(beetle, slot, nav_grid);
+                    beetle.move_toward(&waypoint);
+                }
+            },
+            FormationState::Forming | FormationState::Holding => {
+                if distance > SLOT_TOLERANCE_UNITS / 2.0 {
+                    let waypoint = next_waypoint(beetle, slot, nav_grid);
+                    beetle.move_toward(&waypoint);
+                }
+                else {
+                    beetle.clear_path();
+                    beetle.turn_toward(self.formation.facing);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point2, Vector2, InnerSpace};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use super::{Formation, FormationShape, assign_slots, hungarian};
+
+    fn total_cost(costs: &Vec<Vec<f32>>, assignment: &[usize]) -> f32 {
+        assignment.iter().enumerate().map(|(row, &col)| costs[row][col]).sum()
+    }
+
+    // every way of giving each row its own column
+    fn cheapest_by_brute_force(costs: &Vec<Vec<f32>>) -> f32 {
+        fn search(costs: &Vec<Vec<f32>>, row: usize, used: &mut Vec<bool>) -> f32 {
+            if row == costs.len() {
+                return 0.0;
+            }
+            let mut best = ::std::f32::MAX;
+            for col in 0..used.len() {
+                if !used[col] {
+                    used[col] = true;
+                    best = best.min(costs[row][col] + search(costs, row + 1, used));
+                    used[col] = false;
+                }
+            }
+            best
+        }
+        search(costs, 0, &mut vec![false; costs[0].len()])
+    }
+
+    #[test]
+    fn hungarian_picks_the_cheapest_assignment() {
+        let costs = vec![
+            vec![4.0, 1.0, 3.0],
+            vec![2.0, 0.0, 5.0],
+            vec![3.0, 2.0, 2.0],
+        ];
+        let assignment = hungarian(&costs);
+        assert_eq!(assignment, vec![1, 0, 2]);
+        assert_eq!(total_cost(&costs, &assignment), 5.0);
+    }
+
+    #[test]
+    fn hungarian_matches_brute_force() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+
+        for _ in 0..50 {
+            let rows = rng.gen_range(1, 6);
+            let cols = rng.gen_range(rows, 7);
+            let costs: Vec<Vec<f32>> = (0..rows).map(|_| {
+                (0..cols).map(|_| rng.gen_range(0.0, 100.0)).collect()
+            }).collect();
+
+            let assignment = hungarian(&costs);
+
+            let mut cols_used = assignment.clone();
+            cols_used.sort();
+            cols_used.dedup();
+            assert_eq!(cols_used.len(), rows);
+
+            let found = total_cost(&costs, &assignment);
+            assert!((found - cheapest_by_brute_force(&costs)).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn assign_slots_avoids_crossing_over() {
+        let positions = vec![Point2::new(0.0, 0.0), Point2::new(100.0, 0.0)];
+        let slots = vec![Point2::new(100.0, 10.0), Point2::new(0.0, 10.0)];
+        assert_eq!(assign_slots(&positions, &slots), vec![1, 0]);
+        assert!(assign_slots(&[], &slots).is_empty());
+    }
+
+    #[test]
+    fn slots_are_centred_and_spaced_out() {
+        let shapes = [
+            FormationShape::Line, FormationShape::Column, FormationShape::Wedge,
+            FormationShape::Circle, FormationShape::Box,
+        ];
+
+        for shape in shapes.iter() {
+            let formation = Formation::new(*shape, Vector2::new(0.0, 1.0), 20.0);
+
+            for count in 1..40 {
+                let offsets = formation.slot_offsets(count);
+                assert_eq!(offsets.len(), count);
+
+                let sum = offsets.iter().fold(Vector2::new(0.0, 0.0), |sum, offset| sum + offset);
+                assert!((sum / count as f32).magnitude() < 0.01, "{:?} off centre", shape);
+
+                for (i, a) in offsets.iter().enumerate() {
+                    for b in offsets[(i + 1)..].iter() {
+                        assert!((a - b).magnitude() > 10.0, "{:?} slots overlap", shape);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn line_is_square_to_facing() {
+        let formation = Formation::new(FormationShape::Line, Vector2::new(3.0, 4.0), 10.0);
+        let offsets = formation.slot_offsets(5);
+        for offset in offsets {
+            assert!(offset.dot(formation.facing).abs() < 0.01);
+        }
+    }
+}
@@ -1,14 +1,37 @@
-use cgmath::{Rad, Point2, InnerSpace};
+use std::collections::HashMap;
+use cgmath::{Rad, Point2, Vector2};
 use entities::{BeetleBuilder, Beetle, Id, Beetles};
 use beetle_genome::{BeetleGenome};
 use rand::{Rng, thread_rng};
 use std::f32;
 use entities::{Entity, FoodSource, FoodSources, HomeBase, HomeBases, HasFood};
 use utils::Positioned;
+use spatial_index::SpatialIndex;
+use physics::resolve_collisions;
+use world::{World, DEFAULT_WORLD_WIDTH, DEFAULT_WORLD_HEIGHT};
+use pathfinding::{NavGrid, next_waypoint};
+use flow_field::FlowField;
+use formation::{Formation, FormationShape, FormationGroup, spacing_for};
+use pheromone::{PheromoneField, TRAIL_START_STRENGTH, TRAIL_FADE_PER_TICK};
+use rules::Rules;
+use combat;
+use status_effect;
+use regeneration;
+use metabolism;
+use colony::{self, ProductionOrder};
+use food_ecology::FoodEcology;
+use ai::AiPlayer;
+use game_match::{Match, MatchState, VictoryCondition};
+use team::{Team, Teams, KILL_SCORE, PLAYER_TEAM_ID};
+use utils::Color;
+use utils::{convert_value_for_sim_period, MAX_SPEED_UNITS_PER_SECOND, ROTATION_RADIANS_PER_SECOND};
 
 // This needs to start at 1 because protobuf doesn't handle
 // 0s well. See https://github.com/google/protobuf/issues/1606
 pub const STARTING_ID: Id = 1;
+// move orders for groups at least this big share a flow field instead of
+// every beetle finding its own way
+const FLOW_FIELD_MIN_GROUP_SIZE: usize = 8;
 
 #[derive(PartialEq, Serialize, Debug, Clone)]
 pub enum State {
@@ -22,13 +45,63 @@ pub enum Command {
     Move {
         position: Point2<f32>,
     },
+    GroupMove {
+        flow_field_id: Id,
+        position: Point2<f32>,
+    },
+    HoldFormation {
+        group_id: Id,
+    },
     Interact {
         target_id: Id,
     },
     HarvestClosestFood,
+    // like HarvestClosestFood, but beetles that can't smell any food follow
+    // pheromone trails toward it before giving up and wandering
+    Forage,
+    ReturnToBase,
+    // move, but fight any enemies met along the way
+    AttackMove {
+        position: Point2<f32>,
+    },
+    // back and forth forever, heading for to
+    Patrol {
+        from: Point2<f32>,
+        to: Point2<f32>,
+    },
+    // stay close to a beetle or home base and fight off enemies near it
+    Guard {
+        target_id: Id,
+    },
+    Follow {
+        target_id: Id,
+    },
+    // never move, but fight anything that comes in reach
+    HoldPosition,
+    // explore, fighting any enemies smelled along the way
+    Wander,
     Stop,
 }
 
+// Things that happened during a tick that are worth telling the UI about
+#[derive(Serialize, Debug, Clone)]
+pub enum GameEvent {
+    BeetleDied {
+        id: Id,
+        // None if nobody in particular was to blame
+        killer_id: Option<Id>,
+        position: Point2<f32>,
+        tick: u64,
+    },
+    // one beetle hurting another, by biting or poisoning it
+    BeetleDamaged {
+        id: Id,
+        attacker_id: Id,
+        damage: i32,
+        tick: u64,
+    },
+}
+
 #[derive(Debug)]
 pub enum Action {
     MoveToward {
@@ -36,6 +109,14 @@ pub enum Action {
         x: f32,
         y: f32,
     },
+    FollowFlowField {
+        beetle_id: Id,
+        flow_field_id: Id,
+    },
+    HoldFormation {
+        beetle_id: Id,
+        group_id: Id,
+    },
     Attack {
         source_id: i32,
         target_id: i32,
@@ -51,6 +132,19 @@ pub enum Action {
         home_base_id: Id,
         amount: i32,
     },
+    TurnToward {
+        beetle_id: Id,
+        x: f32,
+        y: f32,
+    },
+    UpdateCommand {
+        beetle_id: Id,
+        command: Command,
+    },
+    // nothing to do this tick, but the command isn't finished
+    Wait {
+        beetle_id: Id,
+    },
     Nothing {
         beetle_id: Id,
     },
@@ -62,6 +156,30 @@ pub struct FieldState {
     pub beetles: Beetles,
     home_bases: HomeBases,
     pub selected_beetles: Vec<Id>,
+    #[serde(skip_serializing)]
+    pub spatial_index: SpatialIndex,
+    pub world: World,
+    #[serde(skip_serializing)]
+    pub nav_grid: NavGrid,
+    #[serde(skip_serializing)]
+    pub flow_fields: HashMap<Id, FlowField>,
+    pub formation_groups: HashMap<Id, FormationGroup>,
+    #[serde(skip_serializing)]
+    pub pheromones: PheromoneField,
+    pub rules: Rules,
+    pub teams: Teams,
+    // how food regrows and spawns over time
+    pub food_ecology: FoodEcology,
+    // how the game is won, if it can be
+    pub game_match: Match,
+    // number of ticks so far
+    pub tick: u64,
+    // everything that happened during the last tick
+    pub events: Vec<GameEvent>,
+    // stream every beetle's planned path to the UI
+    pub debug_paths: bool,
+    // stream the pheromone field to the UI
+    pub show_pheromones: bool,
 }
 
 impl FieldState {
@@ -73,23 +191,108 @@ impl FieldState {
     pub fn get_home_bases(&self) -> &HomeBases {
         &self.home_bases
     }
+
+    // Nothing to harvest now, and nothing will ever grow back or turn up
+    pub fn no_food_left(&self) -> bool {
+        !self.food_ecology.spawns_food() &&
+            self.food_sources.values().all(|food_source| {
+                food_source.amount() == 0 && !food_source.regrows()
+            })
+    }
+
+    pub fn update_spatial_index(&mut self) {
+        self.spatial_index.rebuild(
+            &self.beetles, &self.food_sources, &self.home_bases);
+    }
+
+    pub fn find_closest_food_source(
+            &self, position: Point2<f32>, range: f32) -> Option<&FoodSource> {
+
+        let food_sources = &self.food_sources;
+        let has_food = |id| {
+            food_sources.get(&id).map_or(false, |food_source| food_source.amount() > 0)
+        };
+
+        self.spatial_index.food_sources
+            .nearest_within(position, range, has_food)
+            .and_then(|id| food_sources.get(&id))
+    }
+
+    // Only enemies within range of position count
+    pub fn find_closest_enemy(
+            &self, team_id: Id, position: Point2<f32>,
+            range: f32) -> Option<&Beetle> {
+
+        let beetles = &self.beetles;
+        let teams = &self.teams;
+
+        self.spatial_index.beetles
+            .nearest_within(position, range, |id| {
+                match beetles.get(&id) {
+                    Some(other) => teams.are_hostile(team_id, other.team_id),
+                    None => false,
+                }
+            })
+            .and_then(|id| beetles.get(&id))
+    }
+
+    // Only bases that belong to team_id or its allies count
+    pub fn find_closest_home_base(
+            &self, team_id: Id, position: Point2<f32>) -> Option<&HomeBase> {
+
+        let home_bases = &self.home_bases;
+        let teams = &self.teams;
+
+        self.spatial_index.home_bases
+            .nearest(position, |id| {
+                match home_bases.get(&id) {
+                    Some(home_base) => teams.are_allied(team_id, home_base.get_team_id()),
+                    None => false,
+                }
+            })
+            .and_then(|id| home_bases.get(&id))
+    }
 }
 
 pub struct Game {
     pub field_state: FieldState,
+    // computer players, each in charge of a team
+    pub ai_players: Vec<AiPlayer>,
     next_id: i32,
 }
 
 impl Game {
 
     pub fn new() -> Game {
+        let world = World::new(DEFAULT_WORLD_WIDTH, DEFAULT_WORLD_HEIGHT);
+        let nav_grid = NavGrid::new(&world);
+        let pheromones = PheromoneField::new(&world);
+
+        let mut teams = Teams::new();
+        teams.insert(Team::new(PLAYER_TEAM_ID, "Player", Color::new()));
+
         let game = Game {
             field_state: FieldState {
                 food_sources: FoodSources::new(),
                 beetles: Beetles::new(),
                 home_bases: HomeBases::new(),
                 selected_beetles: Vec::new(),
+                spatial_index: SpatialIndex::new(),
+                world,
+                nav_grid,
+                flow_fields: HashMap::new(),
+                formation_groups: HashMap::new(),
+                pheromones,
+                rules: Rules::new(),
+                teams,
+                food_ecology: FoodEcology::new(),
+                game_match: Match::new(Vec::new()),
+                tick: 0,
+                events: Vec::new(),
+                debug_paths: false,
+                show_pheromones: false,
             },
+            ai_players: Vec::new(),
             next_id: STARTING_ID,
         };
 
@@ -133,27 +336,18 @@ impl Game {
     }
             
 
-    pub fn set_population(&mut self, population: Beetles) {
-        self.field_state.beetles = population;
+    // Swaps in a new map. Everything worked out from the old one is
+    // rebuilt.
+    pub fn set_world(&mut self, world: World) {
+        self.field_state.nav_grid = NavGrid::new(&world);
+        self.field_state.pheromones = PheromoneField::new(&world);
+        self.field_state.flow_fields.clear();
+        self.field_state.world = world;
     }
 
-    pub fn set_random_population(
-            &mut self, population_size: i32, max_speed: f32,
-            max_rotation: f32) {
-
-        let beetles;
-
-        {
-            let mut id_generator = || {
-                self.get_next_id()
-            };
-
-
-            beetles = Game::generate_random_population(
-                population_size, max_speed, max_rotation, &mut id_generator); 
-        }
-
-        self.field_state.beetles = beetles;
+    pub fn set_population(&mut self, population: Beetles) {
+        self.field_state.beetles = population;
+        self.field_state.update_spatial_index();
     }
 
     pub fn select_beetle(&mut self, beetle_id: Id) {
@@ -202,42 +396,171 @@ impl Game {
     }
 
     pub fn selected_move_command(&mut self, x: f32, y: f32) {
-        if self.field_state.selected_beetles.len() == 1 {
+        if let Some(group_id) = self.selected_formation_group() {
+            let beetles = &self.field_state.beetles;
+            let nav_grid = &self.field_state.nav_grid;
+            if let Some(group) = self.field_state.formation_groups.get_mut(&group_id) {
+                group.move_to(Point2::new(x, y), beetles, nav_grid);
+            }
+        }
+        else if self.field_state.selected_beetles.len() == 1 {
             for id in self.field_state.selected_beetles.iter() {
                 if let Some(beetle) = self.field_state.beetles.get_mut(id) {
                     beetle.set_command(Command::Move{ position: Point2::new(x, y) });
                 }
             }
         }
+        else if self.field_state.selected_beetles.len() >= FLOW_FIELD_MIN_GROUP_SIZE {
+            self.move_with_flow_field(x, y);
+        }
         else {
             self.move_in_formation(x, y);
         }
     }
 
-    pub fn create_formation(&mut self) {
+    fn move_with_flow_field(&mut self, x: f32, y: f32) {
+        let goal = Point2::new(x, y);
 
-        // create rectangular formation in upper left corner of bounding box
-        let (x1, y1, _, _) = self.calculate_selected_bounding_box();
+        let group_area: f32 = self.field_state.selected_beetles.iter()
+            .filter_map(|id| self.field_state.beetles.get(id))
+            .map(|beetle| {
+                let radius = beetle.collision_radius();
+                f32::consts::PI * radius * radius
+            })
+            .sum();
 
-        let spacing = 42.0;
-        let column_width = 8;
-        let mut row = -1;
+        let flow_field_id = self.get_next_id();
+        let members = self.field_state.selected_beetles.iter().cloned().collect();
+        let flow_field = FlowField::new(
+            &self.field_state.nav_grid, goal, members, group_area);
+        self.field_state.flow_fields.insert(flow_field_id, flow_field);
 
-        for (i, id) in self.field_state.selected_beetles.iter().enumerate() {
+        for id in self.field_state.selected_beetles.iter() {
             if let Some(beetle) = self.field_state.beetles.get_mut(id) {
+                beetle.clear_path();
+                beetle.set_command(Command::GroupMove{ flow_field_id, position: goal });
+            }
+        }
+    }
+
+    // Flow fields are big, so get rid of them as soon as nobody is following
+    // them any more
+    fn remove_unused_flow_fields(&mut self) {
+        if self.field_state.flow_fields.len() == 0 {
+            return;
+        }
 
-                if i % column_width == 0 {
-                    row += 1;
+        let beetles = &self.field_state.beetles;
+
+        self.field_state.flow_fields.retain(|id, _| {
+            beetles.values().any(|beetle| {
+                match beetle.current_command {
+                    Command::GroupMove{ flow_field_id, .. } => flow_field_id == *id,
+                    _ => false,
                 }
+            })
+        });
+    }
+
+    // Groups the selected beetles into a formation, see create_formation_for
+    pub fn create_formation(
+            &mut self, shape: FormationShape, facing: Option<Vector2<f32>>,
+            spacing: Option<f32>) -> Id {
+
+        let ids = self.field_state.selected_beetles.clone();
+        self.create_formation_for(&ids, shape, facing, spacing)
+    }
+
+    // Groups the beetles into a formation around the middle of their bounding
+    // box. Without a facing the formation points whichever way the group is
+    // mostly facing already, and without a spacing it's worked out from the
+    // biggest beetle. Returns the id of the new group.
+    pub fn create_formation_for(
+            &mut self, ids: &[Id], shape: FormationShape,
+            facing: Option<Vector2<f32>>, spacing: Option<f32>) -> Id {
+
+        let group_id = self.get_next_id();
+
+        let (x1, y1, x2, y2) = self.calculate_bounding_box(ids);
+        let anchor = Point2::new((x1 + x2) / 2.0, (y1 + y2) / 2.0);
 
-                let x_offset = (i % column_width) as f32;
-                let y_offset = row as f32;
-                let x = x1 + x_offset * spacing;
-                let y = y1 + y_offset * spacing;
+        let group = {
+            let beetles: Vec<&Beetle> = ids.iter()
+                .filter_map(|id| self.field_state.beetles.get(id))
+                .collect();
+
+            let facing = facing.unwrap_or_else(|| {
+                beetles.iter().fold(Vector2::new(0.0, 0.0), |sum, beetle| {
+                    sum + beetle.direction
+                })
+            });
+            let spacing = spacing.unwrap_or_else(|| spacing_for(&beetles));
+
+            let formation = Formation::new(shape, facing, spacing);
+
+            FormationGroup::new(group_id, formation, anchor, &beetles)
+        };
 
-                beetle.set_command(Command::Move{ position: Point2::new(x, y) });
+        for id in group.members.iter() {
+            if let Some(beetle) = self.field_state.beetles.get_mut(id) {
+                beetle.clear_path();
+                beetle.set_command(Command::HoldFormation{ group_id });
             }
         }
+
+        self.field_state.formation_groups.insert(group_id, group);
+
+        group_id
+    }
+
+    pub fn move_formation(&mut self, group_id: Id, target: Point2<f32>) {
+        let beetles = &self.field_state.beetles;
+        let nav_grid = &self.field_state.nav_grid;
+        if let Some(group) = self.field_state.formation_groups.get_mut(&group_id) {
+            group.move_to(target, beetles, nav_grid);
+        }
+    }
+
+    // The group the selection makes up, if it's exactly one formation
+    fn selected_formation_group(&self) -> Option<Id> {
+        let selected = &self.field_state.selected_beetles;
+
+        let group_id = match selected.first()
+                .and_then(|id| self.field_state.beetles.get(id)) {
+            Some(beetle) => match beetle.current_command {
+                Command::HoldFormation{ group_id } => group_id,
+                _ => return None,
+            },
+            None => return None,
+        };
+
+        match self.field_state.formation_groups.get(&group_id) {
+            Some(group) => {
+                if group.members.len() == selected.len() &&
+                        selected.iter().all(|id| group.members.contains(id)) {
+                    Some(group_id)
+                }
+                else {
+                    None
+                }
+            },
+            None => None,
+        }
+    }
+
+    fn update_formation_groups(&mut self) {
+        if self.field_state.formation_groups.len() == 0 {
+            return;
+        }
+
+        let beetles = &self.field_state.beetles;
+        let nav_grid = &self.field_state.nav_grid;
+
+        for group in self.field_state.formation_groups.values_mut() {
+            group.update(beetles, nav_grid);
+        }
+
+        self.field_state.formation_groups.retain(|_, group| !group.is_empty());
     }
 
     fn move_in_formation(&mut self, x: f32, y: f32) {
@@ -255,13 +578,17 @@ impl Game {
     }
 
     fn calculate_selected_bounding_box(&self) -> (f32, f32, f32, f32) {
+        self.calculate_bounding_box(&self.field_state.selected_beetles)
+    }
+
+    fn calculate_bounding_box(&self, ids: &[Id]) -> (f32, f32, f32, f32) {
 
         let mut x_low = f32::MAX;
         let mut y_low = f32::MAX;
         let mut x_high = f32::MIN;
         let mut y_high = f32::MIN;
 
-        for id in self.field_state.selected_beetles.iter() {
+        for id in ids.iter() {
             if let Some(beetle) = self.field_state.beetles.get(id) {
                 //beetle.set_command(Command::Move{ position: Point2::new(x, y) });
                 if beetle.position.x < x_low {
@@ -290,6 +617,33 @@ impl Game {
         }
     }
 
+    pub fn selected_command(&mut self, command: Command) {
+        for id in self.field_state.selected_beetles.iter() {
+            if let Some(beetle) = self.field_state.beetles.get_mut(id) {
+                beetle.set_command(command.clone());
+            }
+        }
+    }
+
+    // Each selected beetle patrols between where it is now and position
+    pub fn selected_patrol_command(&mut self, x: f32, y: f32) {
+        for id in self.field_state.selected_beetles.iter() {
+            if let Some(beetle) = self.field_state.beetles.get_mut(id) {
+                let from = beetle.position;
+                beetle.set_command(Command::Patrol{ from, to: Point2::new(x, y) });
+            }
+        }
+    }
+
+    // Adds a command to the end of each selected beetle's queue
+    pub fn selected_queue_command(&mut self, command: Command) {
+        for id in self.field_state.selected_beetles.iter() {
+            if let Some(beetle) = self.field_state.beetles.get_mut(id) {
+                beetle.queue_command(command.clone());
+            }
+        }
+    }
+
     //pub fn selected_idle_command(&mut self) {
     //    for id in self.field_state.selected_beetles.iter() {
     //        if let Some(beetle) = self.field_state.beetles.get_mut(id) {
@@ -302,24 +656,168 @@ impl Game {
 
         let id = self.get_next_id();
         beetle.id = id;
+        self.field_state.spatial_index.beetles.insert(id, beetle.position);
         self.field_state.beetles.insert(id, beetle);
         return id;
     }
 
-    pub fn add_food_source(&mut self, x: f32, y: f32) -> Id {
+    pub fn insert_food_source(&mut self, mut food_source: FoodSource) -> Id {
         let id = self.get_next_id();
-        let mut food_source = FoodSource::new(id);
-        food_source.set_position(Point2::new(x, y));
+        food_source.set_id(id);
+        self.field_state.spatial_index.food_sources.insert(id, food_source.get_position());
         self.field_state.food_sources.insert(id, food_source);
 
         id
     }
 
-    pub fn add_home_base(&mut self, x: f32, y: f32) {
+    // Replaces all the food on the map with whatever the new ecology starts
+    // with, placed by rng
+    pub fn set_food_ecology<R: Rng>(
+            &mut self, mut food_ecology: FoodEcology, rng: &mut R) {
+
+        let food_sources = food_ecology.reset(&self.field_state.world, rng);
+
+        self.field_state.food_sources.clear();
+        self.field_state.food_ecology = food_ecology;
+
+        for food_source in food_sources {
+            self.insert_food_source(food_source);
+        }
+        self.field_state.update_spatial_index();
+    }
+
+    fn update_food_ecology(&mut self) {
+        let spawned = self.field_state.food_ecology.update(
+            &mut self.field_state.food_sources, &self.field_state.world);
+
+        for food_source in spawned {
+            self.insert_food_source(food_source);
+        }
+    }
+
+    pub fn add_home_base(&mut self, x: f32, y: f32) -> Id {
         let id = self.get_next_id();
         let mut home_base = HomeBase::new(id);
         home_base.set_position(Point2::new(x, y));
+        self.field_state.spatial_index.home_bases.insert(id, home_base.get_position());
+        self.field_state.teams.claim_home_base(home_base.get_team_id(), id);
         self.field_state.home_bases.insert(id, home_base);
+
+        id
+    }
+
+    pub fn add_team(&mut self, name: &str, color: Color) -> Id {
+        let id = self.get_next_id();
+        self.field_state.teams.insert(Team::new(id, name, color));
+        id
+    }
+
+    // The team called name, which is created if there isn't one yet
+    pub fn team_for(&mut self, name: &str, color: Color) -> Id {
+        if let Some(team) = self.field_state.teams.find_by_name(name) {
+            return team.id;
+        }
+
+        self.add_team(name, color)
+    }
+
+    // Moves the beetle to team_id and paints it in the team's colours
+    pub fn assign_team(&mut self, beetle_id: Id, team_id: Id) {
+        let color = self.field_state.teams.get(team_id).map(|team| team.color);
+
+        if let Some(beetle) = self.field_state.beetles.get_mut(&beetle_id) {
+            beetle.team_id = team_id;
+            if let Some(color) = color {
+                beetle.color = color;
+            }
+        }
+    }
+
+    pub fn set_home_base_team(&mut self, home_base_id: Id, team_id: Id) {
+        match self.field_state.home_bases.get_mut(&home_base_id) {
+            Some(home_base) => home_base.set_team_id(team_id),
+            None => {
+                println!("No home base with id {}", home_base_id);
+                return;
+            },
+        }
+
+        self.field_state.teams.claim_home_base(team_id, home_base_id);
+    }
+
+    pub fn add_home_base_food(&mut self, home_base_id: Id, amount: i32) {
+        match self.field_state.home_bases.get_mut(&home_base_id) {
+            Some(home_base) => { home_base.add_food(amount); },
+            None => println!("No home base with id {}", home_base_id),
+        }
+    }
+
+    pub fn set_base_production(
+            &mut self, home_base_id: Id, order: Option<ProductionOrder>, queue: bool) {
+        match self.field_state.home_bases.get_mut(&home_base_id) {
+            Some(home_base) => home_base.production.set_order(order, queue),
+            None => println!("No home base with id {}", home_base_id),
+        }
+    }
+
+    fn hatch_beetles(&mut self) {
+        let hatchlings = colony::update(
+            &mut self.field_state.home_bases, &self.field_state.beetles);
+
+        let max_speed = convert_value_for_sim_period(MAX_SPEED_UNITS_PER_SECOND);
+        let max_rotation = convert_value_for_sim_period(ROTATION_RADIANS_PER_SECOND);
+
+        for hatchling in hatchlings {
+            let mut beetle = BeetleBuilder::new()
+                .max_speed_units_per_tick(max_speed)
+                .rotation_radians_per_tick(Rad(max_rotation))
+                .x_pos(hatchling.position.x)
+                .y_pos(hatchling.position.y)
+                .genome(hatchling.genome)
+                .build();
+
+            beetle.team_id = hatchling.team_id;
+            // registered teams all wear the same colours
+            beetle.color = match self.field_state.teams.get(hatchling.team_id) {
+                Some(team) => team.color,
+                None => hatchling.color,
+            };
+            // workers first, so the colony keeps feeding itself
+            beetle.set_command(Command::Forage);
+
+            self.add_beetle(beetle);
+        }
+    }
+
+    // Starts a new match from where everything is now
+    pub fn start_match(&mut self, conditions: Vec<VictoryCondition>) {
+        let mut game_match = Match::new(conditions);
+        game_match.start(&self.field_state);
+        self.field_state.game_match = game_match;
+    }
+
+    fn update_match(&mut self) {
+        let mut game_match = ::std::mem::replace(
+            &mut self.field_state.game_match, Match::new(Vec::new()));
+        let captured = game_match.update(&self.field_state);
+        self.field_state.game_match = game_match;
+
+        for (home_base_id, team_id) in captured {
+            self.set_home_base_team(home_base_id, team_id);
+        }
+    }
+
+    fn update_ai_players(&mut self) {
+        if self.ai_players.len() == 0 {
+            return;
+        }
+
+        // taken out while they run, since they need the whole game
+        let mut ai_players = ::std::mem::replace(&mut self.ai_players, Vec::new());
+        for ai_player in ai_players.iter_mut() {
+            ai_player.update(self);
+        }
+        self.ai_players = ai_players;
     }
 
     pub fn get_random_beetle_id(&self) -> i32 {
@@ -331,41 +829,47 @@ impl Game {
         return rand_id;
     }
 
+    // Closest enemy the beetle can smell
     pub fn find_closest_enemy(&self, beetle: &Beetle) -> Option<Id> {
-
-
-        let enemies = self.field_state.beetles.values().filter(|other| {
-            //other.id != beetle.id
-            other.team_id != beetle.team_id
-        })
-        .map(|other| {
-            other.id
-        })
-        .collect();
-
-        self.find_closest(beetle, enemies)
+        self.field_state
+            .find_closest_enemy(beetle.team_id, beetle.position, beetle.smell_range())
+            .map(|enemy| enemy.id)
     }
 
-    fn find_closest(&self, beetle: &Beetle, ids: Vec<Id>) -> Option<Id> {
+    // Beetles carrying food mark the way home, a little more faintly the
+    // further they've come from the food
+    fn lay_pheromones(&mut self) {
+        let pheromones = &mut self.field_state.pheromones;
 
-        let mut closest_id = None;
-        let mut closest_distance = f32::MAX;
+        for beetle in self.field_state.beetles.values_mut() {
+            if beetle.food_carrying > 0 {
+                pheromones.deposit(beetle.position, beetle.trail_strength);
+                beetle.trail_strength *= TRAIL_FADE_PER_TICK;
+            }
+        }
 
-        for id in ids {
+        pheromones.update();
+    }
 
-            if let Some(other) = self.field_state.beetles.get(&id) {
+    fn kill_beetle(&mut self, id: Id, killer_id: Option<Id>) {
+        if let Some(beetle) = self.field_state.beetles.remove(&id) {
 
-                let vector = other.position - beetle.position;
-                let distance = vector.magnitude();
+            let beetles = &mut self.field_state.beetles;
+            if let Some(killer) = killer_id.and_then(|id| beetles.get_mut(&id)) {
+                killer.kills += 1;
 
-                if distance < closest_distance {
-                    closest_distance = distance;
-                    closest_id = Some(other.id);
+                if self.field_state.teams.are_hostile(killer.team_id, beetle.team_id) {
+                    self.field_state.teams.add_score(killer.team_id, KILL_SCORE);
                 }
             }
-        }
 
-        closest_id
+            self.field_state.events.push(GameEvent::BeetleDied {
+                id,
+                killer_id,
+                position: beetle.position,
+                tick: self.field_state.tick,
+            });
+        }
     }
 
     pub fn tick(&mut self) -> &FieldState {
@@ -373,13 +877,28 @@ impl Game {
         // TODO: maybe move this to struct level to avoid re-allocating
         //let mut actions: Vec<Action> = Vec::with_capacity(self.field_state.beetles.len());
 
+        self.field_state.events.clear();
+
+        // a paused match stops the clock
+        if self.field_state.game_match.state() == MatchState::Paused {
+            return &self.field_state;
+        }
+
+        self.field_state.tick += 1;
+
+        self.field_state.update_spatial_index();
+        self.update_ai_players();
+        self.update_formation_groups();
+
+        for beetle in self.field_state.beetles.values_mut() {
+            beetle.update_stop_position();
+        }
+
         let actions: Vec<Action>;
         {
-            actions = self.field_state.beetles.values().map(|beetle| {
-                let action = beetle.tick(
-                        &self.field_state.beetles,
-                        &self.field_state.food_sources,
-                        &self.field_state.home_bases);
+            let field_state = &self.field_state;
+            actions = field_state.beetles.values().map(|beetle| {
+                let action = beetle.tick(field_state);
                 action
             }).collect();
         }
@@ -387,30 +906,89 @@ impl Game {
         for action in actions {
             match action {
                 Action::MoveToward{beetle_id, x, y} => {
+                    let nav_grid = &self.field_state.nav_grid;
+
                     if let Some(beetle) = self.field_state.beetles.get_mut(&beetle_id) {
 
                         let destination = Point2::new(x, y);
-                        beetle.move_toward(&destination);
+                        let waypoint = next_waypoint(beetle, destination, nav_grid);
+                        beetle.move_toward(&waypoint);
                     }
                 },
-                Action::Attack{source_id, target_id, attack_power} => {
+                Action::FollowFlowField{beetle_id, flow_field_id} => {
 
-                    let mut dead = false;
-                    let mut went_through = false;
+                    let mut target = None;
 
-                    if let Some(target) = self.field_state.beetles.get_mut(&target_id) {
-                        went_through = true;
-                        dead = target.take_damage(attack_power);
+                    {
+                        let field_state = &self.field_state;
+
+                        if let (Some(beetle), Some(flow_field)) = (
+                                field_state.beetles.get(&beetle_id),
+                                field_state.flow_fields.get(&flow_field_id)) {
+
+                            target = Some(flow_field.steering_target(
+                                beetle, &field_state.beetles,
+                                &field_state.spatial_index.beetles,
+                                &field_state.nav_grid));
+                        }
                     }
 
-                    if went_through {
-                        if let Some(source) = self.field_state.beetles.get_mut(&source_id) {
-                            source.damage_inflicted += attack_power;
+                    if let Some(target) = target {
+                        if let Some(beetle) = self.field_state.beetles.get_mut(&beetle_id) {
+                            beetle.move_toward(&target);
                         }
                     }
+                },
+                Action::HoldFormation{beetle_id, group_id} => {
+                    let nav_grid = &self.field_state.nav_grid;
+
+                    if let (Some(group), Some(beetle)) = (
+                            self.field_state.formation_groups.get(&group_id),
+                            self.field_state.beetles.get_mut(&beetle_id)) {
+                        group.steer(beetle, nav_grid);
+                    }
+                },
+                Action::Attack{source_id, target_id, attack_power} => {
+
+                    let hit = match (
+                            self.field_state.beetles.get(&source_id),
+                            self.field_state.beetles.get(&target_id)) {
+                        (Some(source), Some(target)) => {
+                            Some(combat::resolve_attack(
+                                source, target, attack_power, &self.field_state.rules))
+                        },
+                        _ => None,
+                    };
+
+                    if let Some(hit) = hit {
+                        let mut dead = false;
+
+                        let damage = hit.damage;
+
+                        if let Some(target) = self.field_state.beetles.get_mut(&target_id) {
+                            dead = combat::apply_hit(target, hit);
+                        }
+
+                        self.field_state.events.push(GameEvent::BeetleDamaged {
+                            id: target_id,
+                            attacker_id: source_id,
+                            damage,
+                            tick: self.field_state.tick,
+                        });
+
+                        if let Some(source) = self.field_state.beetles.get_mut(&source_id) {
+                            source.damage_inflicted += damage;
+                            source.attack_cooldown = source.attack_cooldown_ticks();
+
+                            if self.field_state.rules.metabolism {
+                                source.energy =
+                                    (source.energy - metabolism::attack_cost(source)).max(0.0);
+                            }
+                        }
 
-                    if dead {
-                        self.field_state.beetles.remove(&target_id);
+                        if dead {
+                            self.kill_beetle(target_id, Some(source_id));
+                        }
                     }
                 },
                 Action::TakeFood{beetle_id, food_source_id, amount} => {
@@ -423,11 +1001,13 @@ impl Game {
                         if amount_collected > 0 {
                             if let Some(beetle) = self.field_state.beetles.get_mut(&beetle_id) {
                                 beetle.add_food(amount_collected);
+                                beetle.carried_food_type = food_source.food_type();
+                                beetle.trail_strength = TRAIL_START_STRENGTH;
                             }
                         }
-                        else {
-                            empty = true;
-                        }
+
+                        // ones that regrow are left for later
+                        empty = food_source.amount() == 0 && !food_source.regrows();
                     }
 
                     if empty {
@@ -438,20 +1018,107 @@ impl Game {
 
                     if let Some(beetle) = self.field_state.beetles.get_mut(&beetle_id) {
                         if let Some(home_base) = self.field_state.home_bases.get_mut(&home_base_id) {
+                            if !self.field_state.teams.are_allied(
+                                    beetle.team_id, home_base.get_team_id()) {
+                                continue;
+                            }
+
+                            // richer food is worth more once it's stored
+                            let stored = amount * beetle.carried_food_type.food_yield;
+
                             beetle.remove_food(amount);
-                            home_base.add_food(amount);
+                            home_base.add_food(stored);
+                            home_base.production.record_delivery(
+                                &beetle.genome, beetle.color, stored);
 
-                            beetle.food_collected += amount;
+                            beetle.food_collected += stored;
+                            self.field_state.teams.add_score(beetle.team_id, stored);
                         }
                     }
                 },
+                Action::TurnToward{beetle_id, x, y} => {
+                    if let Some(beetle) = self.field_state.beetles.get_mut(&beetle_id) {
+                        let direction = Point2::new(x, y) - beetle.position;
+                        beetle.turn_toward(direction);
+                    }
+                },
+                Action::UpdateCommand{beetle_id, command} => {
+                    if let Some(beetle) = self.field_state.beetles.get_mut(&beetle_id) {
+                        // leaves the queue alone, unlike set_command
+                        beetle.current_command = command;
+                        beetle.clear_path();
+                    }
+                },
+                Action::Wait{beetle_id} => {
+                    if let Some(beetle) = self.field_state.beetles.get_mut(&beetle_id) {
+                        beetle.current_state = State::Idle;
+                        beetle.clear_path();
+                    }
+                },
                 Action::Nothing{beetle_id} => {
                     if let Some(beetle) = self.field_state.beetles.get_mut(&beetle_id) {
+                        // the rest of the group packs in around it from now on
+                        if let Command::GroupMove{ flow_field_id, .. } = beetle.current_command {
+                            if let Some(flow_field) = self.field_state.flow_fields.get_mut(&flow_field_id) {
+                                flow_field.mark_arrived(beetle_id);
+                            }
+                        }
+
                         beetle.current_state = State::Idle;
+                        beetle.clear_path();
+
+                        // done with the current command, on to the next
+                        beetle.next_command();
                     }
                 }
             }
         }
+
+        combat::update_cooldowns(&mut self.field_state.beetles);
+
+        metabolism::update(
+            &mut self.field_state.beetles, &mut self.field_state.home_bases,
+            &self.field_state.teams, &self.field_state.rules);
+
+        let (dead, poisonings) = status_effect::update(&mut self.field_state.beetles);
+
+        for (id, attacker_id, damage) in poisonings {
+            self.field_state.events.push(GameEvent::BeetleDamaged {
+                id,
+                attacker_id,
+                damage,
+                tick: self.field_state.tick,
+            });
+        }
+
+        for (id, killer_id) in dead {
+            self.kill_beetle(id, killer_id);
+        }
+
+        regeneration::update(
+            &mut self.field_state.beetles, &mut self.field_state.home_bases,
+            &self.field_state.teams, &self.field_state.rules);
+
+        self.hatch_beetles();
+        self.update_food_ecology();
+
+        self.field_state.update_spatial_index();
+        resolve_collisions(
+            &mut self.field_state.beetles,
+            &self.field_state.spatial_index.beetles);
+
+        for beetle in self.field_state.beetles.values_mut() {
+            self.field_state.world.constrain_beetle(beetle);
+        }
+
+        // so queries made between ticks see where everything ended up
+        self.field_state.update_spatial_index();
+
+        self.lay_pheromones();
+
+        self.remove_unused_flow_fields();
+
+        self.update_match();
         
         &self.field_state
     }
@@ -0,0 +1,522 @@
+use std::collections::{BTreeSet, HashMap};
+
+use cgmath::MetricSpace;
+
+use game::FieldState;
+use entities::{Entity, Id};
+use utils::{Positioned, seconds_to_ticks};
+
+// a home base changes hands when enemies hold the ground around it for this
+// long without any of its owners nearby
+const CAPTURE_RADIUS: f32 = 60.0;
+const CAPTURE_SECONDS: f32 = 5.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum VictoryCondition {
+    // the teams with beetles left win once none of them are hostile to each
+    // other
+    LastTeamStanding,
+    // over once there are no more than count beetles left, whoever's side
+    // they're on. Their teams win.
+    Survivors { count: usize },
+    // the first team to store this much food wins
+    FoodQuota { amount: i32 },
+    // the highest score when time runs out wins
+    TimeLimit { seconds: f32 },
+    // when the bases start out split between teams, a team wins by owning
+    // all of them, along with its allies
+    CaptureBase,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum MatchState {
+    NotStarted,
+    Running,
+    Paused,
+    Ended,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TeamResult {
+    pub team_id: Id,
+    pub won: bool,
+    pub beetles_alive: usize,
+    pub food_stored: i32,
+    pub home_bases: usize,
+    pub score: i32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MatchResult {
+    // the condition that ended the match
+    pub condition: VictoryCondition,
+    // more than one for allies or a tie, none if everybody lost
+    pub winners: Vec<Id>,
+    // how long the match was running for
+    pub ticks: u64,
+    // the game's tick when it ended
+    pub end_tick: u64,
+    pub teams: Vec<TeamResult>,
+}
+
+// A game with a way to win. Checked after every tick; the first condition
+// to be met ends the match.
+#[derive(Serialize, Debug, Clone)]
+pub struct Match {
+    pub conditions: Vec<VictoryCondition>,
+    state: MatchState,
+    // teams with beetles or home bases when the match started
+    team_ids: Vec<Id>,
+    // whether more than one team had a home base at the start
+    contested_bases: bool,
+    ticks: u64,
+    // home base id to the team taking it and how long they've been at it
+    captures: HashMap<Id, (Id, u32)>,
+    result: Option<MatchResult>,
+}
+
+impl Match {
+    pub fn new(conditions: Vec<VictoryCondition>) -> Match {
+        Match {
+            conditions,
+            state: MatchState::NotStarted,
+            team_ids: Vec::new(),
+            contested_bases: false,
+            ticks: 0,
+            captures: HashMap::new(),
+            result: None,
+        }
+    }
+
+    pub fn state(&self) -> MatchState {
+        self.state
+    }
+
+    pub fn result(&self) -> Option<&MatchResult> {
+        self.result.as_ref()
+    }
+
+    // True only on the tick the match ended
+    pub fn just_ended(&self, field_state: &FieldState) -> bool {
+        self.result.as_ref()
+            .map(|result| result.end_tick == field_state.tick)
+            .unwrap_or(false)
+    }
+
+    // Starts over with whoever is on the field now
+    pub fn start(&mut self, field_state: &FieldState) {
+        let mut team_ids = BTreeSet::new();
+        let mut base_owners = BTreeSet::new();
+
+        for beetle in field_state.beetles.values() {
+            team_ids.insert(beetle.team_id);
+        }
+        for home_base in field_state.get_home_bases().values() {
+            team_ids.insert(home_base.get_team_id());
+            base_owners.insert(home_base.get_team_id());
+        }
+
+        self.team_ids = team_ids.into_iter().collect();
+        self.contested_bases = base_owners.len() > 1;
+        self.ticks = 0;
+        self.captures.clear();
+        self.result = None;
+        self.state = MatchState::Running;
+    }
+
+    pub fn pause(&mut self) {
+        if self.state == MatchState::Running {
+            self.state = MatchState::Paused;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.state == MatchState::Paused {
+            self.state = MatchState::Running;
+        }
+    }
+
+    // Returns the home bases that have been captured, along with who by.
+    // It's up to the game to hand them over.
+    pub fn update(&mut self, field_state: &FieldState) -> Vec<(Id, Id)> {
+        if self.state != MatchState::Running {
+            return Vec::new();
+        }
+
+        self.ticks += 1;
+
+        let captured = if self.conditions.contains(&VictoryCondition::CaptureBase) {
+            self.update_captures(field_state)
+        }
+        else {
+            Vec::new()
+        };
+
+        let outcome = self.conditions.iter()
+            .filter_map(|condition| {
+                self.winners(*condition, field_state).map(|winners| (*condition, winners))
+            })
+            .next();
+
+        if let Some((condition, winners)) = outcome {
+            self.end(condition, winners, field_state);
+        }
+
+        captured
+    }
+
+    // Some(winners) if the condition has been met
+    fn winners(&self, condition: VictoryCondition, field_state: &FieldState) -> Option<Vec<Id>> {
+        match condition {
+            VictoryCondition::LastTeamStanding => {
+                let remaining: BTreeSet<Id> = field_state.beetles.values()
+                    .map(|beetle| beetle.team_id)
+                    .collect();
+
+                if none_hostile(&remaining, field_state) {
+                    Some(remaining.into_iter().collect())
+                }
+                else {
+                    None
+                }
+            },
+            VictoryCondition::Survivors { count } => {
+                if field_state.beetles.len() <= count {
+                    let remaining: BTreeSet<Id> = field_state.beetles.values()
+                        .map(|beetle| beetle.team_id)
+                        .collect();
+                    Some(remaining.into_iter().collect())
+                }
+                else {
+                    None
+                }
+            },
+            VictoryCondition::FoodQuota { amount } => {
+                let winners: Vec<Id> = self.team_ids.iter()
+                    .filter(|team_id| food_stored(**team_id, field_state) >= amount)
+                    .cloned()
+                    .collect();
+
+                if winners.len() > 0 {
+                    Some(winners)
+                }
+                else {
+                    None
+                }
+            },
+            VictoryCondition::TimeLimit { seconds } => {
+                if self.ticks < seconds_to_ticks(seconds) as u64 {
+                    return None;
+                }
+
+                let best = self.team_ids.iter()
+                    .map(|team_id| score(*team_id, field_state))
+                    .max();
+
+                Some(self.team_ids.iter()
+                    .filter(|team_id| Some(score(**team_id, field_state)) == best)
+                    .cloned()
+                    .collect())
+            },
+            VictoryCondition::CaptureBase => {
+                if !self.contested_bases {
+                    return None;
+                }
+
+                let owners: BTreeSet<Id> = field_state.get_home_bases().values()
+                    .map(|home_base| home_base.get_team_id())
+                    .collect();
+
+                if owners.len() > 0 && none_hostile(&owners, field_state) {
+                    Some(owners.into_iter().collect())
+                }
+                else {
+                    None
+                }
+            },
+        }
+    }
+
+    // Any base that enemies have held for long enough
+    fn update_captures(&mut self, field_state: &FieldState) -> Vec<(Id, Id)> {
+        let capture_ticks = seconds_to_ticks(CAPTURE_SECONDS);
+        let mut captured = Vec::new();
+
+        for home_base in field_state.get_home_bases().values() {
+            let owner_id = home_base.get_team_id();
+            let position = home_base.get_position();
+
+            let mut defended = false;
+            let mut attacker = None;
+            let mut closest_distance = CAPTURE_RADIUS;
+
+            for beetle in field_state.beetles.values() {
+                let distance = beetle.position.distance(position);
+                if distance > CAPTURE_RADIUS {
+                    continue;
+                }
+
+                if field_state.teams.are_allied(beetle.team_id, owner_id) {
+                    defended = true;
+                    break;
+                }
+                else if field_state.teams.are_hostile(beetle.team_id, owner_id) &&
+                        distance <= closest_distance {
+                    closest_distance = distance;
+                    attacker = Some(beetle.team_id);
+                }
+            }
+
+            let home_base_id = home_base.get_id();
+
+            match attacker {
+                Some(attacker_id) if !defended => {
+                    let progress = match self.captures.get(&home_base_id) {
+                        Some(&(team_id, ticks)) if team_id == attacker_id => ticks + 1,
+                        _ => 1,
+                    };
+
+                    if progress >= capture_ticks {
+                        captured.push((home_base_id, attacker_id));
+                        self.captures.remove(&home_base_id);
+                    }
+                    else {
+                        self.captures.insert(home_base_id, (attacker_id, progress));
+                    }
+                },
+                _ => {
+                    self.captures.remove(&home_base_id);
+                },
+            }
+        }
+
+        captured
+    }
+
+    fn end(&mut self, condition: VictoryCondition, winners: Vec<Id>, field_state: &FieldState) {
+
+        let mut team_ids: BTreeSet<Id> = self.team_ids.iter().cloned().collect();
+        for team_id in winners.iter() {
+            team_ids.insert(*team_id);
+        }
+
+        let teams = team_ids.iter().map(|team_id| {
+            TeamResult {
+                team_id: *team_id,
+                won: winners.contains(team_id),
+                beetles_alive: field_state.beetles.values()
+                    .filter(|beetle| beetle.team_id == *team_id)
+                    .count(),
+                food_stored: food_stored(*team_id, field_state),
+                home_bases: field_state.get_home_bases().values()
+                    .filter(|home_base| home_base.get_team_id() == *team_id)
+                    .count(),
+                score: score(*team_id, field_state),
+            }
+        }).collect();
+
+        self.result = Some(MatchResult {
+            condition,
+            winners,
+            ticks: self.ticks,
+            end_tick: field_state.tick,
+            teams,
+        });
+        self.state = MatchState::Ended;
+    }
+}
+
+fn none_hostile(team_ids: &BTreeSet<Id>, field_state: &FieldState) -> bool {
+    team_ids.iter().all(|team_id| {
+        team_ids.iter().all(|other_team_id| {
+            !field_state.teams.are_hostile(*team_id, *other_team_id)
+        })
+    })
+}
+
+fn food_stored(team_id: Id, field_state: &FieldState) -> i32 {
+    field_state.get_home_bases().values()
+        .filter(|home_base| home_base.get_team_id() == team_id)
+        .map(|home_base| home_base.get_food_stored_amount())
+        .sum()
+}
+
+fn score(team_id: Id, field_state: &FieldState) -> i32 {
+    field_state.teams.get(team_id).map(|team| team.score).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point2;
+    use entities::{Beetle, Id};
+    use game::Game;
+    use team::Relation;
+    use utils::{Color, seconds_to_ticks};
+    use super::{Match, MatchState, VictoryCondition, CAPTURE_SECONDS};
+
+    fn add_beetle(game: &mut Game, team_id: Id, x: f32, y: f32) -> Id {
+        let mut beetle = Beetle::new();
+        beetle.team_id = team_id;
+        beetle.position = Point2::new(x, y);
+        game.add_beetle(beetle)
+    }
+
+    // two hostile teams, each with a beetle
+    fn two_teams() -> (Game, Id, Id) {
+        let mut game = Game::new();
+        let red = game.add_team("Red", Color::new());
+        let blue = game.add_team("Blue", Color::new());
+        add_beetle(&mut game, red, 100.0, 100.0);
+        add_beetle(&mut game, blue, 500.0, 500.0);
+        (game, red, blue)
+    }
+
+    fn started(conditions: Vec<VictoryCondition>, game: &Game) -> Match {
+        let mut game_match = Match::new(conditions);
+        game_match.start(&game.field_state);
+        game_match
+    }
+
+    fn winners(game_match: &Match) -> Vec<Id> {
+        game_match.result().unwrap().winners.clone()
+    }
+
+    #[test]
+    fn last_team_standing_wins() {
+        let (mut game, red, blue) = two_teams();
+        let mut game_match = started(vec![VictoryCondition::LastTeamStanding], &game);
+
+        game_match.update(&game.field_state);
+        assert_eq!(game_match.state(), MatchState::Running);
+
+        game.field_state.beetles.retain(|_, beetle| beetle.team_id != blue);
+        game_match.update(&game.field_state);
+        assert_eq!(game_match.state(), MatchState::Ended);
+        assert_eq!(winners(&game_match), vec![red]);
+
+        let result = game_match.result().unwrap();
+        let blue_result = result.teams.iter().find(|team| team.team_id == blue).unwrap();
+        assert!(!blue_result.won);
+        assert_eq!(blue_result.beetles_alive, 0);
+    }
+
+    #[test]
+    fn allies_win_together() {
+        let (mut game, red, blue) = two_teams();
+        game.field_state.teams.set_relation(red, blue, Relation::Allied);
+        let mut game_match = started(vec![VictoryCondition::LastTeamStanding], &game);
+
+        game_match.update(&game.field_state);
+        assert_eq!(winners(&game_match), vec![red, blue]);
+    }
+
+    #[test]
+    fn survivors_ends_once_few_enough_are_left() {
+        let (mut game, red, _) = two_teams();
+        add_beetle(&mut game, red, 120.0, 100.0);
+        let mut game_match = started(vec![VictoryCondition::Survivors { count: 2 }], &game);
+
+        game_match.update(&game.field_state);
+        assert_eq!(game_match.state(), MatchState::Running);
+
+        let blue_beetle = *game.field_state.beetles.iter()
+            .find(|&(_, beetle)| beetle.team_id != red).unwrap().0;
+        game.field_state.beetles.remove(&blue_beetle);
+        game_match.update(&game.field_state);
+        assert_eq!(winners(&game_match), vec![red]);
+    }
+
+    #[test]
+    fn food_quota_goes_to_whoever_stores_enough() {
+        let (mut game, red, blue) = two_teams();
+        let base = game.add_home_base(100.0, 100.0);
+        game.set_home_base_team(base, blue);
+        let mut game_match = started(vec![VictoryCondition::FoodQuota { amount: 10 }], &game);
+
+        game.add_home_base_food(base, 9);
+        game_match.update(&game.field_state);
+        assert_eq!(game_match.state(), MatchState::Running);
+
+        game.add_home_base_food(base, 1);
+        game_match.update(&game.field_state);
+        assert_eq!(winners(&game_match), vec![blue]);
+        assert!(!winners(&game_match).contains(&red));
+    }
+
+    #[test]
+    fn time_limit_goes_to_the_best_score() {
+        let (mut game, red, blue) = two_teams();
+        let mut game_match = started(vec![VictoryCondition::TimeLimit { seconds: 1.0 }], &game);
+
+        game.field_state.teams.add_score(blue, 3);
+        for _ in 1..seconds_to_ticks(1.0) {
+            game_match.update(&game.field_state);
+        }
+        assert_eq!(game_match.state(), MatchState::Running);
+
+        game_match.update(&game.field_state);
+        assert_eq!(winners(&game_match), vec![blue]);
+        assert_eq!(game_match.result().unwrap().ticks, seconds_to_ticks(1.0) as u64);
+
+        // a tie is shared
+        game.field_state.teams.add_score(red, 3);
+        let mut game_match = started(vec![VictoryCondition::TimeLimit { seconds: 0.0 }], &game);
+        game_match.update(&game.field_state);
+        assert_eq!(winners(&game_match), vec![red, blue]);
+    }
+
+    #[test]
+    fn a_paused_match_stops_the_clock() {
+        let (game, _, _) = two_teams();
+        let mut game_match = started(vec![VictoryCondition::TimeLimit { seconds: 0.0 }], &game);
+
+        game_match.pause();
+        game_match.update(&game.field_state);
+        assert_eq!(game_match.state(), MatchState::Paused);
+
+        game_match.resume();
+        game_match.update(&game.field_state);
+        assert_eq!(game_match.state(), MatchState::Ended);
+    }
+
+    #[test]
+    fn bases_are_captured_by_holding_the_ground_around_them() {
+        let (mut game, red, blue) = two_teams();
+        let red_base = game.add_home_base(100.0, 100.0);
+        game.set_home_base_team(red_base, red);
+        let blue_base = game.add_home_base(300.0, 300.0);
+        game.set_home_base_team(blue_base, blue);
+        // a red beetle sitting on the blue base, with no blue beetles near it
+        add_beetle(&mut game, red, 310.0, 300.0);
+
+        let mut game_match = started(vec![VictoryCondition::CaptureBase], &game);
+
+        let capture_ticks = seconds_to_ticks(CAPTURE_SECONDS);
+        for _ in 1..capture_ticks {
+            assert!(game_match.update(&game.field_state).is_empty());
+        }
+        assert_eq!(game_match.update(&game.field_state), vec![(blue_base, red)]);
+
+        game.set_home_base_team(blue_base, red);
+        game_match.update(&game.field_state);
+        assert_eq!(winners(&game_match), vec![red]);
+    }
+
+    #[test]
+    fn defenders_stop_a_capture() {
+        let (mut game, red, blue) = two_teams();
+        let red_base = game.add_home_base(100.0, 100.0);
+        game.set_home_base_team(red_base, red);
+        let blue_base = game.add_home_base(300.0, 300.0);
+        game.set_home_base_team(blue_base, blue);
+        add_beetle(&mut game, red, 310.0, 300.0);
+        add_beetle(&mut game, blue, 290.0, 300.0);
+
+        let mut game_match = started(vec![VictoryCondition::CaptureBase], &game);
+
+        for _ in 0..(seconds_to_ticks(CAPTURE_SECONDS) * 2) {
+            assert!(game_match.update(&game.field_state).is_empty());
+        }
+        assert_eq!(game_match.state(), MatchState::Running);
+    }
+}
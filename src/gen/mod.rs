@@ -1 +1,3 @@
 pub mod messages;
+pub mod bot;
+pub mod gym;
@@ -0,0 +1,541 @@
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::collections::HashMap;
+use rand::{SeedableRng, XorShiftRng};
+use protobuf::{CodedInputStream, Message, ProtobufResult, parse_length_delimited_from};
+use gen::bot::{Observation, BotCommand};
+use gen::gym::{
+    GymRequest, GymResponse, GymInfo, RewardWeights as GymRewardWeights
+};
+
+use entities::Id;
+use game::Game;
+use scenario::Scenario;
+use team::PLAYER_TEAM_ID;
+use bot_server::{observe, apply_commands};
+use utils::seconds_to_ticks;
+
+// the agent always plays the same team the browser player would
+pub const AGENT_TEAM_ID: Id = PLAYER_TEAM_ID;
+
+// What a step's reward is made of
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RewardWeights {
+    pub damage_inflicted: f32,
+    pub food_collected: f32,
+    // per living beetle, per second
+    pub survival: f32,
+}
+
+impl RewardWeights {
+    pub fn fighting() -> RewardWeights {
+        RewardWeights {
+            damage_inflicted: 1.0,
+            food_collected: 0.0,
+            survival: 0.01,
+        }
+    }
+
+    pub fn foraging() -> RewardWeights {
+        RewardWeights {
+            damage_inflicted: 0.0,
+            food_collected: 1.0,
+            survival: 0.01,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GymScenario {
+    // two armies and nothing else
+    Battle,
+    // the agent's team alone with a base and scarce food
+    Forage,
+    // a full match against the computer, bases and all
+    Match,
+}
+
+impl GymScenario {
+    pub fn from_name(name: &str) -> Option<GymScenario> {
+        match name {
+            "battle" => Some(GymScenario::Battle),
+            "forage" => Some(GymScenario::Forage),
+            "match" => Some(GymScenario::Match),
+            _ => None,
+        }
+    }
+
+    // the built in scenario each episode starts from
+    fn scenario_name(&self) -> &'static str {
+        match *self {
+            GymScenario::Battle => "gym_battle",
+            GymScenario::Forage => "gym_forage",
+            GymScenario::Match => "match",
+        }
+    }
+
+    fn reward_weights(&self) -> RewardWeights {
+        match *self {
+            GymScenario::Battle => RewardWeights::fighting(),
+            GymScenario::Forage => RewardWeights::foraging(),
+            GymScenario::Match => RewardWeights {
+                damage_inflicted: 0.5,
+                food_collected: 1.0,
+                survival: 0.01,
+            },
+        }
+    }
+
+    fn max_seconds(&self) -> f32 {
+        match *self {
+            GymScenario::Battle => 60.0,
+            GymScenario::Forage => 120.0,
+            GymScenario::Match => 300.0,
+        }
+    }
+
+    // Whether the agent also wins by wiping out every enemy
+    fn has_enemies(&self) -> bool {
+        *self != GymScenario::Forage
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StepInfo {
+    pub tick: u64,
+    pub beetles_alive: usize,
+    pub enemies_alive: usize,
+    pub damage_inflicted: i32,
+    pub food_collected: i32,
+    pub done_reason: Option<&'static str>,
+}
+
+pub struct StepResult {
+    pub observation: Observation,
+    pub reward: f32,
+    pub done: bool,
+    pub info: StepInfo,
+}
+
+// A reinforcement learning environment around Game, in the usual reset/step
+// shape. The agent controls AGENT_TEAM_ID with the same commands and sees
+// the same observations as a bot, see bot_server.rs. Nothing here needs a
+// UI, so episodes run as fast as the trainer can step them.
+pub struct Gym {
+    game: Game,
+    // None until the first reset
+    scenario: Option<GymScenario>,
+    reward_weights: RewardWeights,
+    max_ticks: u64,
+    // damage dealt and food collected by each of the agent's beetles so
+    // far, so rewards only count what's new
+    seen: HashMap<Id, (i32, i32)>,
+    damage_inflicted: i32,
+    food_collected: i32,
+    done: bool,
+}
+
+impl Gym {
+    pub fn new() -> Gym {
+        Gym {
+            game: Game::new(),
+            scenario: None,
+            reward_weights: RewardWeights::fighting(),
+            max_ticks: 0,
+            seen: HashMap::new(),
+            damage_inflicted: 0,
+            food_collected: 0,
+            done: true,
+        }
+    }
+
+    // Overrides the scenario's reward weights until the next reset
+    pub fn set_reward_weights(&mut self, reward_weights: RewardWeights) {
+        self.reward_weights = reward_weights;
+    }
+
+    // Starts a new episode. The seed decides everything the episode starts
+    // with, but not what happens once it's running: wandering, critical hits,
+    // new food and the order beetles act in all change from run to run, so
+    // two episodes with the same seed start the same and then drift apart.
+    pub fn reset(&mut self, seed: u64, scenario_name: &str) -> Result<Observation, String> {
+
+        let scenario = match GymScenario::from_name(scenario_name) {
+            Some(scenario) => scenario,
+            None => return Err(format!("Unknown scenario {}", scenario_name)),
+        };
+
+        // XorShift can't start from all zeroes
+        let mut rng = XorShiftRng::from_seed([
+            seed as u32, (seed >> 32) as u32, 0x9e37_79b9, 0x7f4a_7c15]);
+
+        let game = Scenario::builtin(scenario.scenario_name()).unwrap()
+            .build_with_rng(&mut rng)?;
+
+        self.game = game;
+        self.scenario = Some(scenario);
+        self.reward_weights = scenario.reward_weights();
+        self.max_ticks = seconds_to_ticks(scenario.max_seconds()) as u64;
+        self.seen.clear();
+        self.damage_inflicted = 0;
+        self.food_collected = 0;
+        self.done = false;
+
+        Ok(observe(&self.game.field_state, AGENT_TEAM_ID))
+    }
+
+    // Hands out the actions, then runs the game for ticks ticks or until the
+    // episode ends, whichever comes first
+    pub fn step(&mut self, actions: Vec<BotCommand>, ticks: u32) -> StepResult {
+
+        let mut reward = 0.0;
+
+        if !self.done {
+            apply_commands(&mut self.game, AGENT_TEAM_ID, actions);
+
+            for _ in 0..ticks.max(1) {
+                self.game.tick();
+                reward += self.collect_reward();
+
+                if self.done_reason().is_some() {
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+
+        StepResult {
+            observation: observe(&self.game.field_state, AGENT_TEAM_ID),
+            reward,
+            done: self.done,
+            info: self.info(),
+        }
+    }
+
+    fn collect_reward(&mut self) -> f32 {
+        let mut damage_inflicted = 0;
+        let mut food_collected = 0;
+        let mut alive = 0;
+
+        for beetle in self.game.field_state.beetles.values() {
+            if beetle.team_id != AGENT_TEAM_ID {
+                continue;
+            }
+            alive += 1;
+
+            let seen = self.seen.entry(beetle.id).or_insert((0, 0));
+            damage_inflicted += beetle.damage_inflicted - seen.0;
+            food_collected += beetle.food_collected - seen.1;
+            *seen = (beetle.damage_inflicted, beetle.food_collected);
+        }
+
+        // nobody gets credit twice for beetles that have died
+        let beetles = &self.game.field_state.beetles;
+        self.seen.retain(|id, _| beetles.contains_key(id));
+
+        self.damage_inflicted += damage_inflicted;
+        self.food_collected += food_collected;
+
+        let ticks_per_second = seconds_to_ticks(1.0) as f32;
+
+        damage_inflicted as f32 * self.reward_weights.damage_inflicted +
+            food_collected as f32 * self.reward_weights.food_collected +
+            alive as f32 * self.reward_weights.survival / ticks_per_second
+    }
+
+    fn count_alive(&self) -> (usize, usize) {
+        let field_state = &self.game.field_state;
+
+        let beetles_alive = field_state.beetles.values()
+            .filter(|beetle| beetle.team_id == AGENT_TEAM_ID)
+            .count();
+        let enemies_alive = field_state.beetles.values()
+            .filter(|beetle| field_state.teams.are_hostile(AGENT_TEAM_ID, beetle.team_id))
+            .count();
+
+        (beetles_alive, enemies_alive)
+    }
+
+    fn done_reason(&self) -> Option<&'static str> {
+        let (beetles_alive, enemies_alive) = self.count_alive();
+
+        if beetles_alive == 0 {
+            Some("defeated")
+        }
+        else if self.scenario.map_or(false, |scenario| scenario.has_enemies()) &&
+                enemies_alive == 0 {
+            Some("victory")
+        }
+        else if self.game.field_state.tick >= self.max_ticks {
+            Some("time limit")
+        }
+        else {
+            None
+        }
+    }
+
+    fn info(&self) -> StepInfo {
+        let (beetles_alive, enemies_alive) = self.count_alive();
+
+        StepInfo {
+            tick: self.game.field_state.tick,
+            beetles_alive,
+            enemies_alive,
+            damage_inflicted: self.damage_inflicted,
+            food_collected: self.food_collected,
+            done_reason: if self.done { self.done_reason() } else { None },
+        }
+    }
+}
+
+fn gym_info(info: &StepInfo) -> GymInfo {
+    let mut gym_info = GymInfo::new();
+    gym_info.set_tick(info.tick);
+    gym_info.set_beetles_alive(info.beetles_alive as i32);
+    gym_info.set_enemies_alive(info.enemies_alive as i32);
+    gym_info.set_damage_inflicted(info.damage_inflicted);
+    gym_info.set_food_collected(info.food_collected);
+    gym_info.set_done_reason(info.done_reason.unwrap_or("").to_string());
+    gym_info
+}
+
+fn handle_request(gym: &mut Gym, mut request: GymRequest) -> GymResponse {
+    let mut response = GymResponse::new();
+
+    if request.has_reset() {
+        let reset = request.take_reset();
+
+        match gym.reset(reset.get_seed(), reset.get_scenario()) {
+            Ok(observation) => {
+                if reset.has_reward_weights() {
+                    let weights = reset.get_reward_weights();
+                    gym.set_reward_weights(reward_weights(weights));
+                }
+
+                response.set_observation(observation);
+                response.set_info(gym_info(&gym.info()));
+            },
+            Err(e) => response.set_error(e),
+        }
+    }
+    else if request.has_step() {
+        if gym.scenario.is_none() {
+            response.set_error("Reset before stepping".to_string());
+            return response;
+        }
+
+        let mut step = request.take_step();
+        let ticks = step.get_ticks().max(1) as u32;
+        let result = gym.step(step.take_actions().into_vec(), ticks);
+
+        response.set_observation(result.observation);
+        response.set_reward(result.reward);
+        response.set_done(result.done);
+        response.set_info(gym_info(&result.info));
+    }
+
+    response
+}
+
+fn reward_weights(weights: &GymRewardWeights) -> RewardWeights {
+    RewardWeights {
+        damage_inflicted: weights.get_damage_inflicted(),
+        food_collected: weights.get_food_collected(),
+        survival: weights.get_survival(),
+    }
+}
+
+// Answers requests from reader until it runs dry or the trainer sends Close
+pub fn serve<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> ProtobufResult<()> {
+    let mut gym = Gym::new();
+    let mut input = CodedInputStream::new(reader);
+
+    loop {
+        if input.eof()? {
+            return Ok(());
+        }
+
+        let request = parse_length_delimited_from::<GymRequest>(&mut input)?;
+        if request.has_close() {
+            return Ok(());
+        }
+
+        let response = handle_request(&mut gym, request);
+        response.write_length_delimited_to_writer(writer)?;
+        writer.flush()?;
+    }
+}
+
+// stdout carries the responses, so nothing else should print while this runs
+pub fn serve_stdio() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    if let Err(e) = serve(&mut stdin.lock(), &mut stdout.lock()) {
+        eprintln!("Gym error: {}", e);
+    }
+}
+
+// One trainer at a time, each starting with a fresh gym
+pub fn serve_socket(address: &str) {
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("Couldn't start the gym on {}: {}", address, e);
+            return;
+        },
+    };
+
+    println!("Gym listening on {}", address);
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Gym connection failed: {}", e);
+                continue;
+            },
+        };
+
+        let mut writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(e) => {
+                println!("Gym connection failed: {}", e);
+                continue;
+            },
+        };
+
+        if let Err(e) = serve(&mut stream, &mut writer) {
+            println!("Gym error: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use entities::Id;
+    use utils::seconds_to_ticks;
+    use super::{Gym, RewardWeights, AGENT_TEAM_ID};
+
+    fn battle() -> Gym {
+        let mut gym = Gym::new();
+        gym.reset(7, "battle").unwrap();
+        gym
+    }
+
+    fn team_ids(gym: &Gym, agent: bool) -> Vec<Id> {
+        let mut ids: Vec<Id> = gym.game.field_state.beetles.values()
+            .filter(|beetle| (beetle.team_id == AGENT_TEAM_ID) == agent)
+            .map(|beetle| beetle.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn rewards_only_what_is_new() {
+        let mut gym = battle();
+        gym.set_reward_weights(RewardWeights { damage_inflicted: 2.0, food_collected: 3.0, survival: 0.0 });
+        let agents = team_ids(&gym, true);
+
+        gym.game.field_state.beetles.get_mut(&agents[0]).unwrap().damage_inflicted = 5;
+        gym.game.field_state.beetles.get_mut(&agents[1]).unwrap().food_collected = 1;
+        assert_close(gym.collect_reward(), 5.0 * 2.0 + 3.0);
+
+        // nothing new happened
+        assert_close(gym.collect_reward(), 0.0);
+
+        gym.game.field_state.beetles.get_mut(&agents[0]).unwrap().damage_inflicted = 8;
+        assert_close(gym.collect_reward(), 3.0 * 2.0);
+
+        assert_eq!(gym.info().damage_inflicted, 8);
+        assert_eq!(gym.info().food_collected, 1);
+    }
+
+    #[test]
+    fn enemies_earn_the_agent_nothing() {
+        let mut gym = battle();
+        gym.set_reward_weights(RewardWeights { damage_inflicted: 1.0, food_collected: 1.0, survival: 0.0 });
+        let enemies = team_ids(&gym, false);
+
+        gym.game.field_state.beetles.get_mut(&enemies[0]).unwrap().damage_inflicted = 50;
+        assert_close(gym.collect_reward(), 0.0);
+        assert_eq!(gym.info().damage_inflicted, 0);
+    }
+
+    #[test]
+    fn survival_is_paid_per_beetle_per_second() {
+        let mut gym = battle();
+        gym.set_reward_weights(RewardWeights { damage_inflicted: 0.0, food_collected: 0.0, survival: 1.0 });
+        let agents = team_ids(&gym, true);
+        let ticks_per_second = seconds_to_ticks(1.0) as f32;
+
+        assert_close(gym.collect_reward(), agents.len() as f32 / ticks_per_second);
+
+        gym.game.field_state.beetles.remove(&agents[0]);
+        assert_close(gym.collect_reward(), (agents.len() - 1) as f32 / ticks_per_second);
+    }
+
+    #[test]
+    fn the_dead_are_forgotten() {
+        let mut gym = battle();
+        let agents = team_ids(&gym, true);
+
+        gym.game.field_state.beetles.get_mut(&agents[0]).unwrap().damage_inflicted = 5;
+        gym.collect_reward();
+        assert!(gym.seen.contains_key(&agents[0]));
+
+        gym.game.field_state.beetles.remove(&agents[0]);
+        gym.collect_reward();
+        assert!(!gym.seen.contains_key(&agents[0]));
+        // what it did before it died still counts towards the episode
+        assert_eq!(gym.info().damage_inflicted, 5);
+    }
+
+    #[test]
+    fn a_reset_starts_the_accounting_over() {
+        let mut gym = battle();
+        gym.set_reward_weights(RewardWeights { damage_inflicted: 0.0, food_collected: 0.0, survival: 0.0 });
+        let agents = team_ids(&gym, true);
+
+        gym.game.field_state.beetles.get_mut(&agents[0]).unwrap().damage_inflicted = 5;
+        gym.collect_reward();
+
+        gym.reset(7, "forage").unwrap();
+        assert_eq!(gym.reward_weights, RewardWeights::foraging());
+        assert!(gym.seen.is_empty());
+        assert_eq!(gym.info().damage_inflicted, 0);
+    }
+
+    #[test]
+    fn a_finished_episode_pays_nothing_more() {
+        let mut gym = battle();
+        gym.set_reward_weights(RewardWeights { damage_inflicted: 0.0, food_collected: 0.0, survival: 1.0 });
+
+        let enemies = team_ids(&gym, false);
+        for id in enemies {
+            gym.game.field_state.beetles.remove(&id);
+        }
+
+        let result = gym.step(Vec::new(), 10);
+        assert!(result.done);
+        assert_eq!(result.info.done_reason, Some("victory"));
+        // the episode ended on the first tick, so only that one is paid
+        assert_close(result.reward, team_ids(&gym, true).len() as f32 / seconds_to_ticks(1.0) as f32);
+
+        let result = gym.step(Vec::new(), 10);
+        assert!(result.done);
+        assert_close(result.reward, 0.0);
+    }
+
+    #[test]
+    fn unknown_scenarios_are_refused() {
+        let mut gym = Gym::new();
+        assert!(gym.reset(7, "picnic").is_err());
+        assert!(gym.scenario.is_none());
+    }
+}
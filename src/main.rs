@@ -17,14 +17,36 @@ mod gen;
 mod simulation;
 mod message_handler;
 mod entities;
+mod spatial_index;
+mod physics;
+mod world;
+mod pathfinding;
+mod flow_field;
+mod pheromone;
+mod formation;
+mod rules;
+mod combat;
+mod status_effect;
+mod regeneration;
+mod colony;
+mod metabolism;
+mod food_ecology;
+mod team;
+mod ai;
+mod bot_server;
+mod stub_bot;
+mod gym;
+mod scenario;
+mod game_match;
 mod experiment;
 
 use std::thread;
 use std::time::{Instant, Duration};
 
-use game::{Game};
 use message_handler::MessageHandler;
+use bot_server::{BotServer, BOT_ADDRESS};
 use experiment::run_experiment;
+use scenario::Scenario;
 
 //use rouille::Response;
 
@@ -33,26 +55,76 @@ fn main() {
 
     //start_web_server_thread();
 
-    let ui = ui::UI::new();
-    let mut game = Game::new();
+    let args: Vec<String> = std::env::args().collect();
+
+    // --gym runs a training environment over stdin/stdout instead of the
+    // game, and --gym-socket <address> does the same over TCP
+    if args.iter().any(|arg| arg == "--gym") {
+        gym::serve_stdio();
+        return;
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--gym-socket") {
+        match args.get(index + 1) {
+            Some(address) => gym::serve_socket(address),
+            None => println!("--gym-socket needs an address"),
+        }
+        return;
+    }
 
-    let max_speed =
-        utils::convert_value_for_sim_period(utils::MAX_SPEED_UNITS_PER_SECOND);
+    // --bots lets bots connect, and so does asking for a stub bot. The
+    // server has to be listening before the UI starts waiting for the
+    // browser.
+    let wants_bots = args.iter().any(|arg| arg == "--bots" || arg == "--stub-bot");
+    let mut bot_server = if wants_bots {
+        Some(BotServer::new(BOT_ADDRESS))
+    }
+    else {
+        None
+    };
+
+    // --stub-bot <team name> puts a stub bot in charge of a team, as many
+    // times as it's given
+    for (index, arg) in args.iter().enumerate() {
+        if arg == "--stub-bot" {
+            match args.get(index + 1) {
+                Some(team_name) => stub_bot::spawn(BOT_ADDRESS, team_name),
+                None => println!("--stub-bot needs a team name"),
+            }
+        }
+    }
 
-    let max_rotation =
-        utils::convert_value_for_sim_period(utils::ROTATION_RADIANS_PER_SECOND);
+    // --scenario <name or path> picks what the game starts with
+    let scenario_name = match args.iter().position(|arg| arg == "--scenario") {
+        Some(index) => args.get(index + 1).map(|name| name.as_str()).unwrap_or(""),
+        None => "sandbox",
+    };
+
+    let loaded = Scenario::find(scenario_name).and_then(|scenario| {
+        scenario.build().map(|game| (scenario, game))
+    });
+
+    let (scenario, mut game) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            println!("Couldn't load scenario {}: {}", scenario_name, e);
+            println!("Built in scenarios: {}", Scenario::builtin_names().join(", "));
+            return;
+        },
+    };
 
-    game.set_random_population(
-            utils::POPULATION_SIZE, max_speed, max_rotation);
+    let ui = ui::UI::new();
 
     //run_experiment(&ui);
 
     
-    let mut message_handler = MessageHandler::new();
+    let mut message_handler = MessageHandler::new(scenario);
 
     let mut done = false;
     while !done {
-        
+
+        if let Some(ref mut bot_server) = bot_server {
+            bot_server.update(&mut game);
+        }
         ui.update_game_state(game.tick());
 
         let messages = ui.get_all_messages();
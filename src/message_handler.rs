@@ -2,23 +2,40 @@ use std::thread;
 use std::time::{Duration};
 use utils;
 use simulation::GeneticAlgorithm;
-use game::{Game, FieldState};
-use gen::messages::UiMessage;
-use entities::{Beetles, BeetleBuilder};
+use game::{Game, Command};
+use gen::messages::{
+    UiMessage, BehaviourDescriptor as UiBehaviourDescriptor,
+    FormationShape as UiFormationShape, GenomeSource as UiGenomeSource,
+    Relation as UiRelation, Difficulty as UiDifficulty
+};
+use entities::{Beetle, Beetles, BeetleBuilder};
 use simulation::speed_ga::SpeedGA;
 use simulation::battle_ga::BattleGA;
 use simulation::food_ga::FoodGA;
+use simulation::map_elites::{MapElitesGA, BehaviourDescriptor};
 use simulation::Simulate;
 use simulation::fight_simulation::FightSimulation;
+use formation::FormationShape;
+use colony::{GenomeSource, ProductionOrder};
+use scenario::Scenario;
+use game_match::VictoryCondition;
+use team::Relation;
+use ai::{self, Difficulty};
+use utils::Color;
 use ui::UI;
-use cgmath::Vector2;
+use cgmath::{Point2, Vector2};
+use rand::{Rng, thread_rng};
 
 pub struct MessageHandler {
+    // what the game was last loaded from
+    scenario: Scenario,
 }
 
 impl MessageHandler {
-    pub fn new() -> MessageHandler {
-        MessageHandler {}
+    pub fn new(scenario: Scenario) -> MessageHandler {
+        MessageHandler {
+            scenario,
+        }
     }
 
     pub fn handle_message(
@@ -63,12 +80,53 @@ impl MessageHandler {
             game.selected_interact_command(
                 message.get_selected_interact_command().get_target_id());
         }
+        else if message.has_selected_attack_move_command() {
+            let position = Point2::new(
+                message.get_selected_attack_move_command().get_x(),
+                message.get_selected_attack_move_command().get_y());
+            game.selected_command(Command::AttackMove{ position });
+        }
+        else if message.has_selected_patrol_command() {
+            game.selected_patrol_command(
+                message.get_selected_patrol_command().get_x(),
+                message.get_selected_patrol_command().get_y());
+        }
+        else if message.has_selected_guard_command() {
+            let target_id = message.get_selected_guard_command().get_target_id();
+            game.selected_command(Command::Guard{ target_id });
+        }
+        else if message.has_selected_follow_command() {
+            let target_id = message.get_selected_follow_command().get_target_id();
+            game.selected_command(Command::Follow{ target_id });
+        }
+        else if message.has_selected_hold_position_command() {
+            game.selected_command(Command::HoldPosition);
+        }
+        else if message.has_selected_forage_command() {
+            game.selected_command(Command::Forage);
+        }
+        else if message.has_queue_move_command() {
+            let position = Point2::new(
+                message.get_queue_move_command().get_x(),
+                message.get_queue_move_command().get_y());
+            game.selected_queue_command(Command::Move{ position });
+        }
+        else if message.has_queue_interact_command() {
+            let target_id = message.get_queue_interact_command().get_target_id();
+            game.selected_queue_command(Command::Interact{ target_id });
+        }
+        else if message.has_queue_harvest_command() {
+            game.selected_queue_command(Command::HarvestClosestFood);
+        }
+        else if message.has_queue_return_to_base_command() {
+            game.selected_queue_command(Command::ReturnToBase);
+        }
         else if message.has_terminate() {
             done = true;
         }
         else if message.has_run_speed_simulation() {
 
-            let mut simulation = SpeedGA::new(game, &ui);
+            let mut simulation = SpeedGA::new(game, &ui, &self.scenario);
             simulation.run();
         }
         else if message.has_run_battle_simulation() {
@@ -85,7 +143,8 @@ impl MessageHandler {
             }
 
             {
-                let mut ga = BattleGA::new(population, &ui);
+                let scenario = Scenario::builtin("melee").unwrap();
+                let mut ga = BattleGA::new(population, &ui, scenario);
                 ga.run();
                 population = ga.get_population().clone();
             }
@@ -96,10 +155,16 @@ impl MessageHandler {
                     *beetle = new_beetle;
                     (*beetle).id = *id;
                     (*beetle).position = pos;
-                    (*beetle).team_id = 1;
                     (*beetle).direction = Vector2::new(1.0, 0.0);
                 }
             }
+
+            // the evolved armies go on teams of their own, so they can be
+            // fought against each other
+            let team_id = game.team_for("Fighters", Color { r: 213, g: 77, b: 77, a: 255 });
+            for id in ids.iter() {
+                game.assign_team(*id, team_id);
+            }
             //let mut ga = BattleGA::new(game.field_state.beetles.clone(), &ui);
             //ga.run();
 
@@ -118,7 +183,14 @@ impl MessageHandler {
             }
 
             {
-                let mut ga = FoodGA::new(population, &ui);
+                // scarce food replaces the single source that never runs out
+                let mut scenario = Scenario::builtin("foraging").unwrap();
+                if message.get_run_food_ga().get_scarce_food() {
+                    scenario.food_sources.clear();
+                    scenario.scarce_food = true;
+                }
+
+                let mut ga = FoodGA::new(population, &ui, scenario);
                 ga.run();
                 population = ga.get_population().clone();
             }
@@ -129,41 +201,223 @@ impl MessageHandler {
                     *beetle = new_beetle;
                     (*beetle).id = *id;
                     (*beetle).position = pos;
-                    (*beetle).team_id = 2;
                     (*beetle).direction = Vector2::new(-1.0, 0.0);
                 }
             }
+
+            let team_id = game.team_for("Foragers", Color { r: 144, g: 153, b: 212, a: 255 });
+            for id in ids.iter() {
+                game.assign_team(*id, team_id);
+            }
         }
         else if message.has_run_fight_simulation() {
 
-            let check_done_callback = |state: &FieldState| {
-                // whatever the first beetle's team is, make sure there are no
-                // enemies left.
-                if let Some(beetle) = state.beetles.iter().next() {
-                    let team_id = beetle.1.team_id;
+            let result = {
+                let mut sim = FightSimulation::new(
+                    &mut game, vec![VictoryCondition::LastTeamStanding]);
+                sim.set_tick_callback(|state| {
+                    ui.update_game_state(&state);
+                    //println!("{:?}", ui);
+                    thread::sleep(Duration::from_millis(utils::SIMULATION_PERIOD_MS));
+                });
+                sim.run();
+                sim.result().cloned()
+            };
 
-                    for other in state.beetles.values() {
-                        if other.team_id != team_id {
-                            return false;
-                        }
-                    }
+            if let Some(result) = result {
+                ui.fight_ended(&result, &game.field_state);
+            }
+        }
+        else if message.has_create_formation() {
+            let create_formation = message.get_create_formation();
 
-                    true
-                }
-                else {
-                    true
-                }
+            let shape = match create_formation.get_shape() {
+                UiFormationShape::BOX => FormationShape::Box,
+                UiFormationShape::LINE => FormationShape::Line,
+                UiFormationShape::COLUMN => FormationShape::Column,
+                UiFormationShape::WEDGE => FormationShape::Wedge,
+                UiFormationShape::CIRCLE => FormationShape::Circle,
             };
-            let mut sim = FightSimulation::new(&mut game, check_done_callback);
-            sim.set_tick_callback(|state| {
-                ui.update_game_state(&state);
-                //println!("{:?}", ui);
-                thread::sleep(Duration::from_millis(utils::SIMULATION_PERIOD_MS));
+
+            let facing = Vector2::new(
+                create_formation.get_facing_x(), create_formation.get_facing_y());
+            let facing = if facing.x != 0.0 || facing.y != 0.0 {
+                Some(facing)
+            }
+            else {
+                None
+            };
+
+            let spacing = if create_formation.get_spacing() > 0.0 {
+                Some(create_formation.get_spacing())
+            }
+            else {
+                None
+            };
+
+            game.create_formation(shape, facing, spacing);
+        }
+        else if message.has_set_path_debug() {
+            game.field_state.debug_paths =
+                message.get_set_path_debug().get_enabled();
+        }
+        else if message.has_set_pheromone_layer() {
+            game.field_state.show_pheromones =
+                message.get_set_pheromone_layer().get_enabled();
+        }
+        else if message.has_set_rules() {
+            let set_rules = message.get_set_rules();
+            game.field_state.rules.critical_hits = set_rules.get_critical_hits();
+            game.field_state.rules.flanking = set_rules.get_flanking();
+            game.field_state.rules.regeneration = set_rules.get_regeneration();
+            game.field_state.rules.base_healing = set_rules.get_base_healing();
+            game.field_state.rules.metabolism = set_rules.get_metabolism();
+        }
+        else if message.has_set_team_relation() {
+            let set_relation = message.get_set_team_relation();
+
+            let relation = match set_relation.get_relation() {
+                UiRelation::HOSTILE => Relation::Hostile,
+                UiRelation::NEUTRAL => Relation::Neutral,
+                UiRelation::ALLIED => Relation::Allied,
+            };
+
+            game.field_state.teams.set_relation(
+                set_relation.get_team_id(), set_relation.get_other_team_id(), relation);
+        }
+        else if message.has_set_home_base_team() {
+            let set_team = message.get_set_home_base_team();
+            game.set_home_base_team(set_team.get_home_base_id(), set_team.get_team_id());
+        }
+        else if message.has_start_ai_match() {
+            let start_match = message.get_start_ai_match();
+
+            let difficulty = match start_match.get_difficulty() {
+                UiDifficulty::EASY => Difficulty::Easy,
+                UiDifficulty::NORMAL => Difficulty::Normal,
+                UiDifficulty::HARD => Difficulty::Hard,
+            };
+
+            let max_speed =
+                utils::convert_value_for_sim_period(utils::MAX_SPEED_UNITS_PER_SECOND);
+            let max_rotation =
+                utils::convert_value_for_sim_period(utils::ROTATION_RADIANS_PER_SECOND);
+
+            // ids only need to be unique inside the GA, the game hands out
+            // new ones
+            let mut next_id = 0;
+            let mut id_generator = || {
+                next_id += 1;
+                next_id
+            };
+
+            let mut army = Game::generate_random_population(
+                start_match.get_army_size().max(1), max_speed, max_rotation,
+                &mut id_generator);
+
+            if start_match.get_evolve() {
+                let scenario = Scenario::builtin("melee").unwrap();
+                let mut ga = BattleGA::new(army, &ui, scenario);
+                ga.run();
+                army = ga.get_population().clone();
+            }
+
+            ai::setup_match(game, army, difficulty, &mut thread_rng());
+        }
+        else if message.has_set_match_paused() {
+            if message.get_set_match_paused().get_paused() {
+                game.field_state.game_match.pause();
+            }
+            else {
+                game.field_state.game_match.resume();
+            }
+        }
+        else if message.has_load_scenario() {
+            let name = message.get_load_scenario().get_name();
+
+            let loaded = Scenario::find(name).and_then(|scenario| {
+                scenario.build().map(|new_game| (scenario, new_game))
             });
-            sim.run();
+
+            match loaded {
+                Ok((scenario, new_game)) => {
+                    *game = new_game;
+                    self.scenario = scenario;
+                },
+                Err(e) => println!("Couldn't load scenario {}: {}", name, e),
+            }
         }
-        else if message.has_create_formation() {
-            game.create_formation();
+        else if message.has_set_base_production() {
+            let set_production = message.get_set_base_production();
+
+            let genome_source = match set_production.get_genome_source() {
+                UiGenomeSource::BREEDING_POOL => GenomeSource::BreedingPool,
+                UiGenomeSource::CROSSOVER => GenomeSource::Crossover,
+            };
+
+            let count = set_production.get_count();
+            let order = if count < 0 {
+                None
+            }
+            else {
+                Some(ProductionOrder {
+                    genome_source,
+                    remaining: if count == 0 { None } else { Some(count as u32) },
+                })
+            };
+
+            game.set_base_production(
+                set_production.get_home_base_id(), order, set_production.get_queue());
+        }
+        else if message.has_run_map_elites() {
+
+            let descriptor = match message.get_run_map_elites().get_descriptor() {
+                UiBehaviourDescriptor::SIZE_SPEED => {
+                    BehaviourDescriptor::SizeSpeed
+                },
+                UiBehaviourDescriptor::DAMAGE_DEALT_TAKEN => {
+                    BehaviourDescriptor::DamageDealtTaken
+                },
+            };
+
+            let mut population = Beetles::new();
+            let ids = game.field_state.selected_beetles.clone();
+
+            for beetle_id in &ids  {
+                if let Some(beetle) = game.field_state.beetles.get(&beetle_id) {
+                    population.insert(beetle.id, beetle.clone());
+                }
+            }
+
+            let mut elites: Vec<Beetle>;
+            {
+                let scenario = Scenario::builtin("melee").unwrap();
+                let mut ga = MapElitesGA::new(population, descriptor, &ui, scenario);
+                ga.run();
+                elites = ga.get_archive().elites().iter()
+                    .map(|elite| elite.beetle.clone())
+                    .collect();
+            }
+
+            // the archive is usually bigger than the selection, so the
+            // selected beetles get replaced with a sample of the designs
+            // spread across it
+            thread_rng().shuffle(&mut elites);
+
+            for (id, new_beetle) in ids.iter().zip(elites.into_iter()) {
+                if let Some(beetle) = game.field_state.beetles.get_mut(&id) {
+                    let pos = (*beetle).position;
+                    let team_id = (*beetle).team_id;
+                    let color = (*beetle).color;
+                    *beetle = new_beetle;
+                    (*beetle).id = *id;
+                    (*beetle).position = pos;
+                    (*beetle).team_id = team_id;
+                    (*beetle).color = color;
+                    (*beetle).health = (*beetle).max_health();
+                    (*beetle).direction = Vector2::new(1.0, 0.0);
+                }
+            }
         }
 
         return done;
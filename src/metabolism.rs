@@ -0,0 +1,112 @@
+use cgmath::InnerSpace;
+
+use entities::{Beetle, Beetles, HomeBases, HasFood};
+use rules::Rules;
+use team::Teams;
+use status_effect::{StatusEffect, StatusEffectKind};
+use utils::{Positioned, seconds_to_ticks};
+
+// energy spent every second just staying alive, for a beetle of no mass and
+// the slowest possible build. Heavier and faster beetles burn more on top.
+const BASAL_ENERGY_PER_SECOND: f32 = 0.4;
+// energy spent for every unit walked, scaled up by mass
+const MOVE_ENERGY_PER_UNIT: f32 = 0.004;
+const ATTACK_ENERGY_COST: f32 = 1.0;
+// beetles eat once their energy drops below this fraction of the most they
+// can hold
+const HUNGRY_FRACTION: f32 = 0.5;
+// energy in one unit of stored food. Carried pieces are worth their yield.
+const ENERGY_PER_FOOD: f32 = 25.0;
+// beetles this close to a home base can eat from what's stored there
+const EATING_RANGE_UNITS: f32 = 64.0;
+// fraction of max health lost every second once a beetle runs out of energy
+const STARVATION_DAMAGE_PER_SECOND: f32 = 0.05;
+
+// Bulk costs more to carry around, whether it comes from size or density
+fn mass_factor(beetle: &Beetle) -> f32 {
+    0.5 + beetle.mass()
+}
+
+pub fn basal_cost_per_tick(beetle: &Beetle) -> f32 {
+    let ticks_per_second = seconds_to_ticks(1.0) as f32;
+    BASAL_ENERGY_PER_SECOND * (mass_factor(beetle) + beetle.speed_ratio()) / ticks_per_second
+}
+
+pub fn movement_cost(beetle: &Beetle, distance: f32) -> f32 {
+    MOVE_ENERGY_PER_UNIT * mass_factor(beetle) * distance
+}
+
+pub fn attack_cost(beetle: &Beetle) -> f32 {
+    ATTACK_ENERGY_COST * mass_factor(beetle)
+}
+
+fn eat(beetle: &mut Beetle, home_bases: &mut HomeBases, teams: &Teams) {
+    if beetle.energy >= beetle.max_energy() * HUNGRY_FRACTION {
+        return;
+    }
+
+    // what it's carrying comes first, then whatever's stored at a friendly
+    // base nearby. Either way it eats one piece, and richer food is more
+    // filling.
+    let energy = if beetle.food_carrying > 0 {
+        beetle.food_carrying -= 1;
+        Some(ENERGY_PER_FOOD * beetle.carried_food_type.food_yield as f32)
+    }
+    else {
+        let position = beetle.position;
+        let team_id = beetle.team_id;
+        let home_base = home_bases.values_mut().find(|home_base| {
+            home_base.get_food_stored_amount() > 0 &&
+                teams.are_allied(team_id, home_base.get_team_id()) &&
+                (home_base.get_position() - position).magnitude() < EATING_RANGE_UNITS
+        });
+
+        match home_base {
+            Some(home_base) => {
+                home_base.remove_food(1);
+                Some(ENERGY_PER_FOOD)
+            },
+            None => None,
+        }
+    };
+
+    if let Some(energy) = energy {
+        beetle.energy = (beetle.energy + energy).min(beetle.max_energy());
+        beetle.num_eaten += 1;
+    }
+}
+
+// Charges every beetle for a tick of living and for however far it walked,
+// and feeds the hungry ones. Beetles that run out of energy start starving,
+// which does damage through status_effect::update.
+pub fn update(
+        beetles: &mut Beetles, home_bases: &mut HomeBases, teams: &Teams,
+        rules: &Rules) {
+    let ticks_per_second = seconds_to_ticks(1.0) as f32;
+
+    for beetle in beetles.values_mut() {
+        let distance_moved = beetle.distance_moved;
+        beetle.distance_moved = 0.0;
+
+        if !rules.metabolism {
+            beetle.remove_status_effect(StatusEffectKind::Starving);
+            continue;
+        }
+
+        let cost = basal_cost_per_tick(beetle) + movement_cost(beetle, distance_moved);
+        beetle.energy = (beetle.energy - cost).max(0.0);
+
+        eat(beetle, home_bases, teams);
+
+        if beetle.energy > 0.0 {
+            beetle.remove_status_effect(StatusEffectKind::Starving);
+        }
+        else if !beetle.has_status_effect(StatusEffectKind::Starving) {
+            let damage_per_tick =
+                beetle.max_health() as f32 * STARVATION_DAMAGE_PER_SECOND / ticks_per_second;
+
+            beetle.add_status_effect(StatusEffect::lasting(
+                StatusEffectKind::Starving, damage_per_tick));
+        }
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::cmp::Ordering;
+use std::f32;
+use cgmath::{Point2, InnerSpace};
+
+use entities::Beetle;
+use world::{World, closest_point_on_segment};
+
+const NAV_CELL_SIZE_UNITS: f32 = 16.0;
+// keep paths far enough from walls that an average beetle doesn't scrape
+// along them
+const NAV_CLEARANCE_UNITS: f32 = 12.0;
+const WAYPOINT_REACHED_UNITS: f32 = 8.0;
+// chasing a moving target only triggers a new plan once it's moved this far
+const REPLAN_GOAL_MOVED_UNITS: f32 = 2.0 * NAV_CELL_SIZE_UNITS;
+// if a waypoint takes longer than this to reach, assume the beetle is stuck
+// behind something and plan again
+const REPLAN_TICKS: u32 = 100;
+
+type Cell = (i32, i32);
+
+// Coarse grid over the world marking which cells can be walked through.
+// Derived from the walls, so it needs rebuilding whenever they change.
+#[derive(Debug)]
+pub struct NavGrid {
+    cell_size: f32,
+    width: i32,
+    height: i32,
+    blocked: Vec<bool>,
+    has_obstacles: bool,
+}
+
+impl NavGrid {
+    pub fn new(world: &World) -> NavGrid {
+
+        let cell_size = NAV_CELL_SIZE_UNITS;
+        let width = (world.width / cell_size).ceil() as i32;
+        let height = (world.height / cell_size).ceil() as i32;
+
+        let mut blocked = vec![false; (width * height) as usize];
+
+        if world.walls.len() > 0 {
+            for y in 0..height {
+                for x in 0..width {
+                    let center = Point2::new(
+                        (x as f32 + 0.5) * cell_size, (y as f32 + 0.5) * cell_size);
+
+                    blocked[(y * width + x) as usize] =
+                        world.walls.iter().any(|wall| {
+                            wall.contains(center) || wall.edges().iter().any(|&(a, b)| {
+                                let closest = closest_point_on_segment(center, a, b);
+                                (closest - center).magnitude() < NAV_CLEARANCE_UNITS
+                            })
+                        });
+                }
+            }
+        }
+
+        let has_obstacles = blocked.iter().any(|blocked| *blocked);
+
+        NavGrid {
+            cell_size,
+            width,
+            height,
+            blocked,
+            has_obstacles,
+        }
+    }
+
+    pub fn has_obstacles(&self) -> bool {
+        self.has_obstacles
+    }
+
+    // in cells
+    pub fn dimensions(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    pub fn cell_for(&self, position: Point2<f32>) -> Cell {
+        let x = (position.x / self.cell_size).floor() as i32;
+        let y = (position.y / self.cell_size).floor() as i32;
+        (x.max(0).min(self.width - 1), y.max(0).min(self.height - 1))
+    }
+
+    pub fn cell_center(&self, cell: Cell) -> Point2<f32> {
+        Point2::new(
+            (cell.0 as f32 + 0.5) * self.cell_size,
+            (cell.1 as f32 + 0.5) * self.cell_size)
+    }
+
+    pub fn in_bounds(&self, cell: Cell) -> bool {
+        cell.0 >= 0 && cell.0 < self.width && cell.1 >= 0 && cell.1 < self.height
+    }
+
+    pub fn is_blocked(&self, cell: Cell) -> bool {
+        !self.in_bounds(cell) || self.blocked[(cell.1 * self.width + cell.0) as usize]
+    }
+
+    // 8-connected, but diagonal moves can't cut the corner of a blocked cell
+    pub fn neighbours(&self, cell: Cell) -> Vec<(Cell, f32)> {
+        let mut neighbours = Vec::with_capacity(8);
+
+        for dx in -1..2 {
+            for dy in -1..2 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+
+                let next = (cell.0 + dx, cell.1 + dy);
+
+                if self.is_blocked(next) {
+                    continue;
+                }
+
+                if dx != 0 && dy != 0 {
+                    if self.is_blocked((cell.0 + dx, cell.1)) ||
+                            self.is_blocked((cell.0, cell.1 + dy)) {
+                        continue;
+                    }
+                    neighbours.push((next, f32::consts::SQRT_2));
+                }
+                else {
+                    neighbours.push((next, 1.0));
+                }
+            }
+        }
+
+        neighbours
+    }
+
+    // position itself if it's open, otherwise the middle of the closest
+    // open cell
+    pub fn nearest_open_position(&self, position: Point2<f32>) -> Point2<f32> {
+        let cell = self.cell_for(position);
+
+        if !self.is_blocked(cell) {
+            return position;
+        }
+
+        match self.nearest_open_cell(cell) {
+            Some(open_cell) => self.cell_center(open_cell),
+            None => position,
+        }
+    }
+
+    // Beetles get shoved into the padding around walls all the time, so
+    // searches start from the closest open cell instead.
+    pub fn nearest_open_cell(&self, cell: Cell) -> Option<Cell> {
+        if !self.is_blocked(cell) {
+            return Some(cell);
+        }
+
+        let mut visited = vec![false; self.blocked.len()];
+        let mut queue = VecDeque::new();
+        queue.push_back(cell);
+        visited[(cell.1 * self.width + cell.0) as usize] = true;
+
+        while let Some(current) = queue.pop_front() {
+            for dx in -1..2 {
+                for dy in -1..2 {
+                    let next = (current.0 + dx, current.1 + dy);
+
+                    if !self.in_bounds(next) {
+                        continue;
+                    }
+
+                    let index = (next.1 * self.width + next.0) as usize;
+                    if visited[index] {
+                        continue;
+                    }
+                    visited[index] = true;
+
+                    if !self.is_blocked(next) {
+                        return Some(next);
+                    }
+
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn line_of_sight(&self, a: Point2<f32>, b: Point2<f32>) -> bool {
+        let vector = b - a;
+        let distance = vector.magnitude();
+        let steps = (distance / (self.cell_size / 2.0)).ceil() as i32;
+
+        for i in 0..(steps + 1) {
+            let t = if steps > 0 { i as f32 / steps as f32 } else { 0.0 };
+            if self.is_blocked(self.cell_for(a + vector * t)) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Beetles get pushed into the padding around walls all the time, which
+    // would block every line out of there. Measures from the closest open
+    // cell instead.
+    pub fn line_of_sight_from(&self, position: Point2<f32>, b: Point2<f32>) -> bool {
+        let cell = self.cell_for(position);
+        if !self.is_blocked(cell) {
+            return self.line_of_sight(position, b);
+        }
+
+        match self.nearest_open_cell(cell) {
+            Some(open_cell) => self.line_of_sight(self.cell_center(open_cell), b),
+            None => false,
+        }
+    }
+
+    // A* over the grid. The returned waypoints don't include the start
+    // position and have been straightened out wherever there's a clear line
+    // between them.
+    pub fn find_path(
+            &self, start: Point2<f32>, goal: Point2<f32>) -> Option<Vec<Point2<f32>>> {
+
+        let start_cell = match self.nearest_open_cell(self.cell_for(start)) {
+            Some(cell) => cell,
+            None => return None,
+        };
+        let goal_cell = match self.nearest_open_cell(self.cell_for(goal)) {
+            Some(cell) => cell,
+            None => return None,
+        };
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<Cell, Cell> = HashMap::new();
+        let mut cost_so_far: HashMap<Cell, f32> = HashMap::new();
+
+        open.push(Node { cell: start_cell, priority: 0.0 });
+        cost_so_far.insert(start_cell, 0.0);
+
+        let mut found = false;
+
+        while let Some(Node { cell, .. }) = open.pop() {
+
+            if cell == goal_cell {
+                found = true;
+                break;
+            }
+
+            let current_cost = cost_so_far[&cell];
+
+            for (next, step_cost) in self.neighbours(cell) {
+                let new_cost = current_cost + step_cost;
+
+                let better = match cost_so_far.get(&next) {
+                    Some(&cost) => new_cost < cost,
+                    None => true,
+                };
+
+                if better {
+                    cost_so_far.insert(next, new_cost);
+                    came_from.insert(next, cell);
+                    open.push(Node {
+                        cell: next,
+                        priority: new_cost + octile_distance(next, goal_cell),
+                    });
+                }
+            }
+        }
+
+        if !found {
+            return None;
+        }
+
+        let mut cells = vec![goal_cell];
+        let mut current = goal_cell;
+        while let Some(&previous) = came_from.get(&current) {
+            cells.push(previous);
+            current = previous;
+        }
+        cells.reverse();
+
+        let mut waypoints: Vec<Point2<f32>> = cells.iter()
+            .map(|cell| self.cell_center(*cell))
+            .collect();
+
+        // end exactly on the goal if it's reachable rather than the middle
+        // of its cell
+        if goal_cell == self.cell_for(goal) {
+            if let Some(last) = waypoints.last_mut() {
+                *last = goal;
+            }
+        }
+
+        Some(self.smooth(start, waypoints))
+    }
+
+    // Skip every waypoint that can be seen past
+    fn smooth(&self, start: Point2<f32>, waypoints: Vec<Point2<f32>>) -> Vec<Point2<f32>> {
+        let mut smoothed = Vec::new();
+        let mut from = start;
+        let mut i = 0;
+
+        while i < waypoints.len() {
+            let mut furthest = i;
+            for j in (i + 1)..waypoints.len() {
+                if self.line_of_sight(from, waypoints[j]) {
+                    furthest = j;
+                }
+            }
+
+            smoothed.push(waypoints[furthest]);
+            from = waypoints[furthest];
+            i = furthest + 1;
+        }
+
+        smoothed
+    }
+}
+
+fn octile_distance(a: Cell, b: Cell) -> f32 {
+    let dx = (a.0 - b.0).abs() as f32;
+    let dy = (a.1 - b.1).abs() as f32;
+    dx.max(dy) + (f32::consts::SQRT_2 - 1.0) * dx.min(dy)
+}
+
+#[derive(PartialEq)]
+struct Node {
+    cell: Cell,
+    priority: f32,
+}
+
+impl Eq for Node {}
+
+// BinaryHeap is a max heap, so lower priorities need to compare as greater
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        other.priority.partial_cmp(&self.priority).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Works out where a beetle headed for destination should actually steer this
+// tick, planning a route around obstacles when there isn't a straight shot.
+// Paths are cached on the beetle and only replanned when the destination
+// moves, the way ahead gets blocked, or the beetle stops making progress.
+pub fn next_waypoint(
+        beetle: &mut Beetle, destination: Point2<f32>,
+        nav_grid: &NavGrid) -> Point2<f32> {
+
+    if !nav_grid.has_obstacles() ||
+            nav_grid.line_of_sight_from(beetle.position, destination) {
+        beetle.clear_path();
+        return destination;
+    }
+
+    let goal_moved = match beetle.path_goal {
+        Some(goal) => (goal - destination).magnitude() > REPLAN_GOAL_MOVED_UNITS,
+        None => true,
+    };
+
+    while beetle.path.len() > 0 &&
+            (beetle.path[0] - beetle.position).magnitude() < WAYPOINT_REACHED_UNITS {
+        beetle.path.remove(0);
+        beetle.ticks_since_waypoint = 0;
+    }
+
+    // an empty path is either a plan that's been followed to the end or one
+    // that couldn't be made. Either way it's left to REPLAN_TICKS rather than
+    // running A* again every tick.
+    let blocked = match beetle.path.first() {
+        Some(waypoint) => !nav_grid.line_of_sight_from(beetle.position, *waypoint),
+        None => false,
+    };
+
+    if goal_moved || blocked || beetle.ticks_since_waypoint > REPLAN_TICKS {
+        beetle.path = nav_grid.find_path(beetle.position, destination)
+            .unwrap_or(Vec::new());
+        beetle.path_goal = Some(destination);
+        beetle.ticks_since_waypoint = 0;
+    }
+
+    beetle.ticks_since_waypoint += 1;
+
+    match beetle.path.first() {
+        Some(waypoint) => *waypoint,
+        // nowhere to go, but heading straight there is better than standing
+        // still
+        None => destination,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point2;
+    use world::{World, Wall};
+    use super::NavGrid;
+
+    fn rectangle(x1: f32, y1: f32, x2: f32, y2: f32) -> Wall {
+        Wall {
+            points: vec![
+                Point2::new(x1, y1), Point2::new(x2, y1),
+                Point2::new(x2, y2), Point2::new(x1, y2),
+            ],
+        }
+    }
+
+    // 400 x 400 with a wall down the middle, leaving a gap at the bottom
+    fn world_with_wall() -> World {
+        let mut world = World::new(400.0, 400.0);
+        world.walls.push(rectangle(180.0, 0.0, 220.0, 320.0));
+        world
+    }
+
+    // no waypoint, including the start, can see through a wall to the next
+    fn assert_walkable(nav_grid: &NavGrid, start: Point2<f32>, path: &[Point2<f32>]) {
+        let mut from = start;
+        for waypoint in path {
+            assert!(nav_grid.line_of_sight_from(from, *waypoint),
+                "{:?} to {:?} goes through a wall", from, waypoint);
+            from = *waypoint;
+        }
+    }
+
+    #[test]
+    fn open_ground_is_a_straight_line() {
+        let nav_grid = NavGrid::new(&World::new(400.0, 400.0));
+        let goal = Point2::new(350.0, 300.0);
+
+        assert!(!nav_grid.has_obstacles());
+        assert_eq!(nav_grid.find_path(Point2::new(20.0, 20.0), goal), Some(vec![goal]));
+    }
+
+    #[test]
+    fn goes_around_walls() {
+        let nav_grid = NavGrid::new(&world_with_wall());
+        let start = Point2::new(100.0, 100.0);
+        let goal = Point2::new(300.0, 100.0);
+
+        assert!(!nav_grid.line_of_sight(start, goal));
+
+        let path = nav_grid.find_path(start, goal).unwrap();
+        assert_eq!(*path.last().unwrap(), goal);
+        // through the gap
+        assert!(path.iter().any(|waypoint| waypoint.y > 320.0));
+        assert_walkable(&nav_grid, start, &path);
+    }
+
+    #[test]
+    fn goal_in_a_wall_ends_next_to_it() {
+        let nav_grid = NavGrid::new(&world_with_wall());
+        let start = Point2::new(100.0, 100.0);
+        let goal = Point2::new(200.0, 100.0);
+
+        let path = nav_grid.find_path(start, goal).unwrap();
+        let end = *path.last().unwrap();
+        assert!(!nav_grid.is_blocked(nav_grid.cell_for(end)));
+        assert_walkable(&nav_grid, start, &path);
+    }
+
+    #[test]
+    fn sealed_off_goal_has_no_path() {
+        let mut world = World::new(400.0, 400.0);
+        // a box with a room inside it
+        world.walls.push(rectangle(250.0, 250.0, 400.0, 260.0));
+        world.walls.push(rectangle(250.0, 250.0, 260.0, 400.0));
+        let nav_grid = NavGrid::new(&world);
+
+        assert_eq!(nav_grid.find_path(Point2::new(50.0, 50.0), Point2::new(340.0, 340.0)), None);
+    }
+}
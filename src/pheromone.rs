@@ -0,0 +1,208 @@
+use cgmath::Point2;
+
+use world::World;
+use utils::{Heatmap, HeatmapCell};
+
+const PHEROMONE_CELL_SIZE_UNITS: f32 = 16.0;
+// fraction of the pheromone in each cell lost every tick
+const EVAPORATION_RATE: f32 = 0.004;
+// fraction of each cell's pheromone that spreads to its neighbours every tick
+const DIFFUSION_RATE: f32 = 0.1;
+// keeps busy trails from building up forever
+const MAX_CELL_STRENGTH: f32 = 20.0;
+// anything weaker than this isn't worth following and gets cleared out
+const MIN_STRENGTH: f32 = 0.001;
+// how much a beetle lays down right after picking up food. It fades the
+// further the beetle gets from the food, so trails are strongest at the food
+// end and following them uphill leads there.
+pub const TRAIL_START_STRENGTH: f32 = 1.0;
+pub const TRAIL_FADE_PER_TICK: f32 = 0.995;
+// each UI heatmap cell covers this many field cells along each side
+const UI_CELLS_PER_HEATMAP_CELL: i32 = 4;
+
+// Scent left on the ground by beetles bringing food home. Covers the whole
+// world in a coarse grid that evaporates and spreads out a little every tick.
+#[derive(Debug)]
+pub struct PheromoneField {
+    cell_size: f32,
+    width: i32,
+    height: i32,
+    values: Vec<f32>,
+    // reused by update so it doesn't allocate every tick
+    scratch: Vec<f32>,
+    // nothing to update while the whole field is empty
+    empty: bool,
+}
+
+impl PheromoneField {
+    pub fn new(world: &World) -> PheromoneField {
+        let cell_size = PHEROMONE_CELL_SIZE_UNITS;
+        let width = (world.width / cell_size).ceil() as i32;
+        let height = (world.height / cell_size).ceil() as i32;
+        let num_cells = (width * height) as usize;
+
+        PheromoneField {
+            cell_size,
+            width,
+            height,
+            values: vec![0.0; num_cells],
+            scratch: vec![0.0; num_cells],
+            empty: true,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for value in self.values.iter_mut() {
+            *value = 0.0;
+        }
+        self.empty = true;
+    }
+
+    pub fn deposit(&mut self, position: Point2<f32>, amount: f32) {
+        if amount < MIN_STRENGTH {
+            return;
+        }
+
+        let index = self.index(self.cell_for(position));
+        self.values[index] = (self.values[index] + amount).min(MAX_CELL_STRENGTH);
+        self.empty = false;
+    }
+
+    // Middle of the strongest cell within range of position, as long as it's
+    // stronger than where position already is
+    pub fn strongest_nearby(&self, position: Point2<f32>, range: f32) -> Option<Point2<f32>> {
+        if self.empty {
+            return None;
+        }
+
+        let (cx, cy) = self.cell_for(position);
+        let range_cells = (range / self.cell_size).ceil() as i32;
+
+        let mut best_cell = None;
+        let mut best_strength = self.values[self.index((cx, cy))].max(MIN_STRENGTH);
+
+        for y in (cy - range_cells)..(cy + range_cells + 1) {
+            for x in (cx - range_cells)..(cx + range_cells + 1) {
+                if !self.in_bounds((x, y)) {
+                    continue;
+                }
+
+                let strength = self.values[self.index((x, y))];
+                if strength > best_strength {
+                    best_strength = strength;
+                    best_cell = Some((x, y));
+                }
+            }
+        }
+
+        best_cell.map(|cell| self.cell_center(cell))
+    }
+
+    pub fn update(&mut self) {
+        if self.empty {
+            return;
+        }
+
+        let width = self.width;
+        let height = self.height;
+
+        // cells along the edges of the world keep the share that would have
+        // gone off the edge, so diffusion alone never loses any pheromone
+        for y in 0..height {
+            for x in 0..width {
+                let value = self.values[(y * width + x) as usize];
+
+                let mut neighbours_sum = 0.0;
+                for &(dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+                    let (nx, ny) = (x + dx, y + dy);
+                    neighbours_sum += if nx >= 0 && nx < width && ny >= 0 && ny < height {
+                        self.values[(ny * width + nx) as usize]
+                    }
+                    else {
+                        value
+                    };
+                }
+
+                let diffused = value * (1.0 - DIFFUSION_RATE) +
+                    neighbours_sum * (DIFFUSION_RATE / 4.0);
+
+                self.scratch[(y * width + x) as usize] = diffused * (1.0 - EVAPORATION_RATE);
+            }
+        }
+
+        ::std::mem::swap(&mut self.values, &mut self.scratch);
+
+        let mut empty = true;
+        for value in self.values.iter_mut() {
+            if *value < MIN_STRENGTH {
+                *value = 0.0;
+            }
+            else {
+                empty = false;
+            }
+        }
+        self.empty = empty;
+    }
+
+    // Averaged down to a much coarser grid for the UI
+    pub fn to_heatmap(&self) -> Heatmap {
+        let factor = UI_CELLS_PER_HEATMAP_CELL;
+        let width = (self.width + factor - 1) / factor;
+        let height = (self.height + factor - 1) / factor;
+
+        let mut cells = Vec::new();
+
+        if !self.empty {
+            for hy in 0..height {
+                for hx in 0..width {
+                    let mut sum = 0.0;
+                    let mut count = 0;
+
+                    for y in (hy * factor)..((hy + 1) * factor).min(self.height) {
+                        for x in (hx * factor)..((hx + 1) * factor).min(self.width) {
+                            sum += self.values[self.index((x, y))];
+                            count += 1;
+                        }
+                    }
+
+                    if sum > 0.0 {
+                        cells.push(HeatmapCell {
+                            x: hx as usize,
+                            y: hy as usize,
+                            value: sum / count as f32,
+                        });
+                    }
+                }
+            }
+        }
+
+        Heatmap {
+            name: "Pheromones".to_string(),
+            x_label: "x".to_string(),
+            y_label: "y".to_string(),
+            width: width as usize,
+            height: height as usize,
+            cells,
+        }
+    }
+
+    fn cell_for(&self, position: Point2<f32>) -> (i32, i32) {
+        let x = (position.x / self.cell_size).floor() as i32;
+        let y = (position.y / self.cell_size).floor() as i32;
+        (x.max(0).min(self.width - 1), y.max(0).min(self.height - 1))
+    }
+
+    fn cell_center(&self, cell: (i32, i32)) -> Point2<f32> {
+        Point2::new(
+            (cell.0 as f32 + 0.5) * self.cell_size,
+            (cell.1 as f32 + 0.5) * self.cell_size)
+    }
+
+    fn in_bounds(&self, cell: (i32, i32)) -> bool {
+        cell.0 >= 0 && cell.0 < self.width && cell.1 >= 0 && cell.1 < self.height
+    }
+
+    fn index(&self, cell: (i32, i32)) -> usize {
+        (cell.1 * self.width + cell.0) as usize
+    }
+}
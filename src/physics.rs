@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use cgmath::{Vector2, InnerSpace};
+
+use entities::{Id, Beetles};
+use beetle::MAX_COLLISION_RADIUS_UNITS;
+use spatial_index::SpatialGrid;
+
+// More passes settle big piles faster, but one or two per tick is plenty
+// since whatever's left over gets picked up next tick.
+const COLLISION_ITERATIONS: usize = 2;
+// allow for beetles having been pushed since the grid was built
+const QUERY_SLACK_UNITS: f32 = 5.0;
+
+// Pushes overlapping beetles apart. Each beetle is treated as a circle, and
+// heavier beetles get shoved less than light ones.
+pub fn resolve_collisions(beetles: &mut Beetles, grid: &SpatialGrid) {
+
+    for _ in 0..COLLISION_ITERATIONS {
+
+        let mut corrections: HashMap<Id, Vector2<f32>> = HashMap::new();
+
+        for beetle in beetles.values() {
+
+            let radius = beetle.collision_radius();
+            let query_radius =
+                radius + MAX_COLLISION_RADIUS_UNITS + QUERY_SLACK_UNITS;
+
+            for other_id in grid.query_radius(beetle.position, query_radius) {
+
+                // only handle each pair once
+                if other_id <= beetle.id {
+                    continue;
+                }
+
+                if let Some(other) = beetles.get(&other_id) {
+
+                    let offset = other.position - beetle.position;
+                    let distance = offset.magnitude();
+                    let overlap = radius + other.collision_radius() - distance;
+
+                    if overlap > 0.0 {
+
+                        // beetles stacked exactly on top of each other need
+                        // to be split up somehow
+                        let normal = if distance > 0.0001 {
+                            offset / distance
+                        }
+                        else {
+                            Vector2::new(1.0, 0.0)
+                        };
+
+                        let mass = beetle.mass();
+                        let other_mass = other.mass();
+                        let total_mass = mass + other_mass;
+
+                        *corrections.entry(beetle.id)
+                            .or_insert(Vector2::new(0.0, 0.0)) -=
+                                normal * overlap * (other_mass / total_mass);
+                        *corrections.entry(other.id)
+                            .or_insert(Vector2::new(0.0, 0.0)) +=
+                                normal * overlap * (mass / total_mass);
+                    }
+                }
+            }
+        }
+
+        if corrections.is_empty() {
+            break;
+        }
+
+        for (id, correction) in corrections {
+            if let Some(beetle) = beetles.get_mut(&id) {
+                beetle.position += correction;
+            }
+        }
+    }
+}
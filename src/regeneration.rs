@@ -0,0 +1,68 @@
+use cgmath::InnerSpace;
+
+use entities::{Beetles, HomeBases, HasFood};
+use rules::Rules;
+use team::Teams;
+use utils::{Positioned, seconds_to_ticks};
+
+// beetles that haven't been hurt for this long start healing on their own
+const REGENERATION_DELAY_SECONDS: f32 = 5.0;
+// fraction of max health regained every second
+const REGENERATION_PER_SECOND: f32 = 0.01;
+// beetles this close to a home base get patched up much faster, at the cost
+// of the food stored there
+const BASE_HEALING_RANGE_UNITS: f32 = 64.0;
+const BASE_HEALING_PER_SECOND: f32 = 0.2;
+const HEALTH_PER_FOOD: f32 = 10.0;
+
+// Beetles only heal at bases that belong to their team or its allies
+pub fn update(
+        beetles: &mut Beetles, home_bases: &mut HomeBases, teams: &Teams,
+        rules: &Rules) {
+    let ticks_per_second = seconds_to_ticks(1.0) as f32;
+    let regeneration_delay = seconds_to_ticks(REGENERATION_DELAY_SECONDS);
+
+    for beetle in beetles.values_mut() {
+        beetle.ticks_since_hurt = beetle.ticks_since_hurt.saturating_add(1);
+
+        let max_health = beetle.max_health();
+        if beetle.health >= max_health {
+            beetle.pending_healing = 0.0;
+            continue;
+        }
+
+        let position = beetle.position;
+        let team_id = beetle.team_id;
+        let home_base = if rules.base_healing {
+            home_bases.values_mut().find(|home_base| {
+                home_base.get_food_stored_amount() > 0 &&
+                    teams.are_allied(team_id, home_base.get_team_id()) &&
+                    (home_base.get_position() - position).magnitude() <
+                        BASE_HEALING_RANGE_UNITS
+            })
+        }
+        else {
+            None
+        };
+
+        // base healing comes in chunks worth one food each
+        if let Some(home_base) = home_base {
+            beetle.pending_healing +=
+                max_health as f32 * BASE_HEALING_PER_SECOND / ticks_per_second;
+
+            if beetle.pending_healing >= HEALTH_PER_FOOD {
+                home_base.remove_food(1);
+                beetle.heal(HEALTH_PER_FOOD as i32);
+                beetle.pending_healing -= HEALTH_PER_FOOD;
+            }
+        }
+        else if rules.regeneration && beetle.ticks_since_hurt >= regeneration_delay {
+            beetle.pending_healing +=
+                max_health as f32 * REGENERATION_PER_SECOND / ticks_per_second;
+
+            let healing = beetle.pending_healing.floor();
+            beetle.heal(healing as i32);
+            beetle.pending_healing -= healing;
+        }
+    }
+}
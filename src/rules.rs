@@ -0,0 +1,28 @@
+// Optional game mechanics that can be switched on and off for a game.
+// Scenarios only need to list the ones they change.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default = "Rules::new")]
+pub struct Rules {
+    // attacks occasionally do extra damage
+    pub critical_hits: bool,
+    // attacks landing on a beetle's side or rear do extra damage
+    pub flanking: bool,
+    // beetles slowly heal once they've been out of a fight for a while
+    pub regeneration: bool,
+    // beetles near a home base heal quickly using the food stored there
+    pub base_healing: bool,
+    // beetles burn energy to live, move and fight, and starve without food
+    pub metabolism: bool,
+}
+
+impl Rules {
+    pub fn new() -> Rules {
+        Rules {
+            critical_hits: false,
+            flanking: false,
+            regeneration: false,
+            base_healing: false,
+            metabolism: false,
+        }
+    }
+}
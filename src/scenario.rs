@@ -0,0 +1,479 @@
+use std::fs::File;
+use std::io::prelude::*;
+
+use cgmath::{Point2, Vector2, Rad, InnerSpace};
+use rand::{Rng, thread_rng};
+use serde_json;
+
+use game::{Game, Command};
+use entities::{Beetle, Beetles, BeetleBuilder, FoodSource, Id};
+use beetle_genome::BeetleGenome;
+use world::{World, Boundary, Wall, DEFAULT_WORLD_WIDTH, DEFAULT_WORLD_HEIGHT};
+use food_ecology::{FoodEcology, FoodType};
+use formation::{Formation, FormationShape, spacing_for};
+use team::Relation;
+use ai::{AiPlayer, Difficulty};
+use game_match::VictoryCondition;
+use rules::Rules;
+use utils::{
+    Color, Positioned, convert_value_for_sim_period,
+    MAX_SPEED_UNITS_PER_SECOND, ROTATION_RADIANS_PER_SECOND
+};
+
+// Scenarios that are built into the binary, by name
+const BUILTIN_SCENARIOS: [(&str, &str); 10] = [
+    ("sandbox", include_str!("../scenarios/sandbox.json")),
+    ("foraging", include_str!("../scenarios/foraging.json")),
+    ("melee", include_str!("../scenarios/melee.json")),
+    ("showdown", include_str!("../scenarios/showdown.json")),
+    ("food_race", include_str!("../scenarios/food_race.json")),
+    ("match", include_str!("../scenarios/match.json")),
+    ("arena", include_str!("../scenarios/arena.json")),
+    ("torus", include_str!("../scenarios/torus.json")),
+    ("gym_battle", include_str!("../scenarios/gym_battle.json")),
+    ("gym_forage", include_str!("../scenarios/gym_forage.json")),
+];
+
+// Everything needed to set up a game: the map, the food on it, the teams and
+// the beetles they start with. Loaded from JSON; anything left out gets a
+// sensible default.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_width")]
+    pub width: f32,
+    #[serde(default = "default_height")]
+    pub height: f32,
+    #[serde(default = "default_boundary")]
+    pub boundary: Boundary,
+    #[serde(default)]
+    pub walls: Vec<Wall>,
+    #[serde(default)]
+    pub food_sources: Vec<FoodSourceSpec>,
+    // FoodEcology::scarce, sized to the map. Overrides food_ecology.
+    #[serde(default)]
+    pub scarce_food: bool,
+    #[serde(default)]
+    pub food_ecology: Option<FoodEcology>,
+    #[serde(default)]
+    pub teams: Vec<TeamSpec>,
+    #[serde(default)]
+    pub relations: Vec<RelationSpec>,
+    #[serde(default = "Rules::new")]
+    pub rules: Rules,
+    // how a match on this map is won. With none it's a sandbox.
+    #[serde(default)]
+    pub victory_conditions: Vec<VictoryCondition>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FoodSourceSpec {
+    pub x: f32,
+    pub y: f32,
+    #[serde(default = "default_food_amount")]
+    pub amount: i32,
+    #[serde(default = "FoodType::new")]
+    pub food_type: FoodType,
+    #[serde(default)]
+    pub regrowth_per_second: f32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TeamSpec {
+    pub name: String,
+    // only used when the team doesn't exist yet
+    #[serde(default = "Color::new")]
+    pub color: Color,
+    #[serde(default)]
+    pub home_bases: Vec<HomeBaseSpec>,
+    #[serde(default)]
+    pub populations: Vec<PopulationSpec>,
+    // puts a computer player in charge of the team
+    #[serde(default)]
+    pub ai: Option<Difficulty>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct HomeBaseSpec {
+    pub x: f32,
+    pub y: f32,
+    // stored food to start with
+    #[serde(default)]
+    pub food: i32,
+}
+
+// A group of beetles that start out together
+#[derive(Deserialize, Debug, Clone)]
+pub struct PopulationSpec {
+    // beetles with random genomes
+    #[serde(default)]
+    pub random: i32,
+    // one beetle for each, gene values in BeetleGeneIndex order
+    #[serde(default)]
+    pub genomes: Vec<Vec<f32>>,
+    // a JSON file holding more genomes in the same format
+    #[serde(default)]
+    pub genome_file: Option<String>,
+    // the middle of the group
+    pub x: f32,
+    pub y: f32,
+    // lined up in a formation, or scattered if there isn't one
+    #[serde(default)]
+    pub formation: Option<FormationShape>,
+    #[serde(default = "default_facing")]
+    pub facing: Vector2<f32>,
+    // how far from the middle scattered beetles can be
+    #[serde(default = "default_spread")]
+    pub spread: f32,
+    #[serde(default = "default_command")]
+    pub command: StartingCommand,
+    // queued up for once the first command is done
+    #[serde(default)]
+    pub then: Vec<StartingCommand>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum StartingCommand {
+    Stop,
+    Forage,
+    HarvestClosestFood,
+    Wander,
+    HoldPosition,
+    AttackMove { x: f32, y: f32 },
+    // from wherever the beetle starts
+    Patrol { x: f32, y: f32 },
+}
+
+impl StartingCommand {
+    fn command(&self, position: Point2<f32>) -> Command {
+        match *self {
+            StartingCommand::Stop => Command::Stop,
+            StartingCommand::Forage => Command::Forage,
+            StartingCommand::HarvestClosestFood => Command::HarvestClosestFood,
+            StartingCommand::Wander => Command::Wander,
+            StartingCommand::HoldPosition => Command::HoldPosition,
+            StartingCommand::AttackMove { x, y } => {
+                Command::AttackMove { position: Point2::new(x, y) }
+            },
+            StartingCommand::Patrol { x, y } => {
+                Command::Patrol { from: position, to: Point2::new(x, y) }
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RelationSpec {
+    pub teams: [String; 2],
+    pub relation: Relation,
+}
+
+fn default_width() -> f32 { DEFAULT_WORLD_WIDTH }
+fn default_height() -> f32 { DEFAULT_WORLD_HEIGHT }
+fn default_boundary() -> Boundary { Boundary::Clamp }
+fn default_food_amount() -> i32 { 100 }
+fn default_facing() -> Vector2<f32> { Vector2::new(1.0, 0.0) }
+fn default_spread() -> f32 { 100.0 }
+fn default_command() -> StartingCommand { StartingCommand::Stop }
+
+impl Scenario {
+
+    pub fn builtin_names() -> Vec<&'static str> {
+        BUILTIN_SCENARIOS.iter().map(|&(name, _)| name).collect()
+    }
+
+    // The built in scenarios are checked by hand, so a broken one is a bug
+    pub fn builtin(name: &str) -> Option<Scenario> {
+        BUILTIN_SCENARIOS.iter()
+            .find(|&&(builtin_name, _)| builtin_name == name)
+            .map(|&(_, json)| {
+                Scenario::parse(json)
+                    .unwrap_or_else(|e| panic!("Bad built in scenario {}: {}", name, e))
+            })
+    }
+
+    pub fn load(path: &str) -> Result<Scenario, String> {
+        let json = read_file(path)?;
+        Scenario::parse(&json).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // A built in scenario if there's one called name_or_path, otherwise a
+    // file
+    pub fn find(name_or_path: &str) -> Result<Scenario, String> {
+        match Scenario::builtin(name_or_path) {
+            Some(scenario) => Ok(scenario),
+            None => Scenario::load(name_or_path),
+        }
+    }
+
+    fn parse(json: &str) -> Result<Scenario, String> {
+        serde_json::from_str(json).map_err(|e| format!("{}", e))
+    }
+
+    // A new game with everything in the scenario, and its match under way
+    pub fn build(&self) -> Result<Game, String> {
+        self.build_with_rng(&mut thread_rng())
+    }
+
+    // Same, but the random genomes, positions and food all come from rng
+    pub fn build_with_rng<R: Rng>(&self, rng: &mut R) -> Result<Game, String> {
+        let mut game = Game::new();
+        self.set_up_map_with_rng(&mut game, rng);
+        self.populate(&mut game, rng)?;
+
+        if self.victory_conditions.len() > 0 {
+            game.start_match(self.victory_conditions.clone());
+        }
+
+        Ok(game)
+    }
+
+    // The map, food, teams and home bases, for a game that doesn't have any
+    // yet. Beetles already in the game are left alone.
+    pub fn set_up_map(&self, game: &mut Game) {
+        self.set_up_map_with_rng(game, &mut thread_rng());
+    }
+
+    fn set_up_map_with_rng<R: Rng>(&self, game: &mut Game, rng: &mut R) {
+        let mut world = World::new(self.width, self.height);
+        world.boundary = self.boundary;
+        world.walls = self.walls.clone();
+        game.set_world(world);
+        game.field_state.rules = self.rules.clone();
+
+        self.reset_food_with_rng(game, rng);
+
+        for team in self.teams.iter() {
+            let team_id = game.team_for(&team.name, team.color);
+
+            for home_base in team.home_bases.iter() {
+                let home_base_id = game.add_home_base(home_base.x, home_base.y);
+                game.set_home_base_team(home_base_id, team_id);
+                game.add_home_base_food(home_base_id, home_base.food);
+            }
+        }
+
+        for relation in self.relations.iter() {
+            let team_id = self.find_team_id(game, &relation.teams[0]);
+            let other_team_id = self.find_team_id(game, &relation.teams[1]);
+
+            match (team_id, other_team_id) {
+                (Some(team_id), Some(other_team_id)) => {
+                    game.field_state.teams.set_relation(
+                        team_id, other_team_id, relation.relation);
+                },
+                _ => println!("Relation between unknown teams {:?}", relation.teams),
+            }
+        }
+
+        game.field_state.update_spatial_index();
+    }
+
+    // Puts the food back the way it was at the start
+    pub fn reset_food(&self, game: &mut Game) {
+        self.reset_food_with_rng(game, &mut thread_rng());
+    }
+
+    fn reset_food_with_rng<R: Rng>(&self, game: &mut Game, rng: &mut R) {
+        let food_ecology = if self.scarce_food {
+            FoodEcology::scarce(self.width, self.height)
+        }
+        else {
+            self.food_ecology.clone().unwrap_or_else(FoodEcology::new)
+        };
+        game.set_food_ecology(food_ecology, rng);
+
+        for spec in self.food_sources.iter() {
+            let mut food_source = FoodSource::with_type(
+                0, spec.food_type, spec.amount, spec.regrowth_per_second);
+            food_source.set_position(Point2::new(spec.x, spec.y));
+            game.insert_food_source(food_source);
+        }
+    }
+
+    // Adds every team's beetles and computer players
+    fn populate<R: Rng>(&self, game: &mut Game, rng: &mut R) -> Result<(), String> {
+        for team in self.teams.iter() {
+            let team_id = game.team_for(&team.name, team.color);
+
+            for population in team.populations.iter() {
+                let beetles = create_beetles(population, rng)?;
+                place(game, team_id, population, beetles, rng);
+            }
+
+            if let Some(difficulty) = team.ai {
+                game.ai_players.retain(|ai_player| ai_player.team_id != team_id);
+                game.ai_players.push(AiPlayer::new(team_id, difficulty));
+            }
+        }
+
+        Ok(())
+    }
+
+    // New beetles for all of a team's populations, ready to be evolved
+    // before they're placed
+    pub fn team_beetles<T: FnMut() -> Id>(
+            &self, team_name: &str, id_generator: &mut T) -> Result<Beetles, String> {
+
+        let team = self.team_spec(team_name)?;
+        let mut beetles = Beetles::new();
+
+        for population in team.populations.iter() {
+            for mut beetle in create_beetles(population, &mut thread_rng())? {
+                beetle.id = id_generator();
+                beetles.insert(beetle.id, beetle);
+            }
+        }
+
+        Ok(beetles)
+    }
+
+    // Adds beetles that came from somewhere else, laid out like the team's
+    // first population, and returns the team's id
+    pub fn place_team(
+            &self, game: &mut Game, team_name: &str,
+            beetles: Beetles) -> Result<Id, String> {
+
+        let team = self.team_spec(team_name)?;
+        let population = team.populations.first()
+            .ok_or(format!("Team {} has no populations", team_name))?;
+
+        let team_id = game.team_for(&team.name, team.color);
+        let beetles = beetles.into_iter().map(|(_, beetle)| beetle).collect();
+        place(game, team_id, population, beetles, &mut thread_rng());
+
+        Ok(team_id)
+    }
+
+    // Somewhere on the map at least margin from the edges, and not inside a
+    // wall if that can be helped
+    pub fn random_position(&self, margin: f32) -> Point2<f32> {
+        let mut rng = thread_rng();
+        let mut position = Point2::new(self.width / 2.0, self.height / 2.0);
+
+        for _ in 0..10 {
+            position = Point2::new(
+                rng.gen_range(margin, (self.width - margin).max(margin + 1.0)),
+                rng.gen_range(margin, (self.height - margin).max(margin + 1.0)));
+
+            if !self.walls.iter().any(|wall| wall.contains(position)) {
+                break;
+            }
+        }
+
+        position
+    }
+
+    fn team_spec(&self, team_name: &str) -> Result<&TeamSpec, String> {
+        self.teams.iter()
+            .find(|team| team.name == team_name)
+            .ok_or(format!("No team called {} in scenario {}", team_name, self.name))
+    }
+
+    fn find_team_id(&self, game: &Game, team_name: &str) -> Option<Id> {
+        game.field_state.teams.find_by_name(team_name).map(|team| team.id)
+    }
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    let mut contents = String::new();
+
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+
+    Ok(contents)
+}
+
+fn create_beetles<R: Rng>(
+        population: &PopulationSpec, rng: &mut R) -> Result<Vec<Beetle>, String> {
+
+    let mut genomes = Vec::new();
+
+    for values in population.genomes.iter() {
+        genomes.push(BeetleGenome::from_values(values));
+    }
+
+    if let Some(ref path) = population.genome_file {
+        let json = read_file(path)?;
+        let values: Vec<Vec<f32>> = serde_json::from_str(&json)
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        for values in values.iter() {
+            genomes.push(BeetleGenome::from_values(values));
+        }
+    }
+
+    for _ in 0..population.random {
+        let mut genome = BeetleGenome::new();
+        genome.set_random_genome_with_rng(rng);
+        genomes.push(genome);
+    }
+
+    let max_speed = convert_value_for_sim_period(MAX_SPEED_UNITS_PER_SECOND);
+    let max_rotation = convert_value_for_sim_period(ROTATION_RADIANS_PER_SECOND);
+
+    let beetles = genomes.into_iter().map(|genome| {
+        BeetleBuilder::new()
+            .max_speed_units_per_tick(max_speed)
+            .rotation_radians_per_tick(Rad(max_rotation))
+            .genome(genome)
+            .build()
+    }).collect();
+
+    Ok(beetles)
+}
+
+// Adds the beetles to the game on team_id, fresh and lined up the way the
+// population says
+fn place<R: Rng>(
+        game: &mut Game, team_id: Id, population: &PopulationSpec,
+        beetles: Vec<Beetle>, rng: &mut R) {
+
+
+    let facing = if population.facing.magnitude2() > 0.0 {
+        population.facing.normalize()
+    }
+    else {
+        default_facing()
+    };
+    let centre = Point2::new(population.x, population.y);
+
+    let positions: Vec<Point2<f32>> = match population.formation {
+        Some(shape) => {
+            let spacing = spacing_for(&beetles.iter().collect::<Vec<_>>());
+            let formation = Formation::new(shape, facing, spacing);
+            formation.slot_offsets(beetles.len()).iter()
+                .map(|offset| centre + offset)
+                .collect()
+        },
+        None => {
+            let spread = population.spread.max(0.0);
+            beetles.iter().map(|_| {
+                centre + Vector2::new(
+                    rng.gen_range(-spread, spread + 0.001),
+                    rng.gen_range(-spread, spread + 0.001))
+            }).collect()
+        },
+    };
+
+    for (mut beetle, position) in beetles.into_iter().zip(positions) {
+        beetle.position = position;
+        beetle.direction = facing;
+        beetle.angle = Vector2::new(1.0, 0.0).angle(facing);
+        beetle.health = beetle.max_health();
+        beetle.energy = beetle.max_energy();
+        beetle.damage_inflicted = 0;
+        beetle.kills = 0;
+        beetle.food_collected = 0;
+        beetle.food_carrying = 0;
+        beetle.set_command(population.command.command(position));
+        for command in population.then.iter() {
+            beetle.queue_command(command.command(position));
+        }
+
+        let id = game.add_beetle(beetle);
+        game.assign_team(id, team_id);
+    }
+}
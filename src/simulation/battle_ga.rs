@@ -1,29 +1,37 @@
 use std::f32;
-use simulation::Simulate;
 use simulation::GeneticAlgorithm;
-use simulation::fight_simulation::FightSimulation;
+use simulation::fight_simulation::run_free_for_all;
 use ui::UI;
-use game::{Game, FieldState, Command};
+use game::{Game, Command};
 use entities::{Beetle, Beetles};
 use std::thread;
 use std::time::{Duration};
 use utils::{SIMULATION_PERIOD_MS, Color};
-use rand::{Rng, thread_rng};
+use scenario::Scenario;
+
+// a kill is worth this much on top of the damage it took to get it
+const KILL_FITNESS_BONUS: f32 = 100.0;
+// beetles start at least this far from the edges of the map
+const BATTLE_MARGIN: f32 = 100.0;
 
 pub struct BattleGA<'a> {
     ui: &'a UI,
     game: Game,
+    // where the fights happen
+    scenario: Scenario,
 }
 
 impl<'a> BattleGA<'a> {
-    pub fn new(population: Beetles, ui: &'a UI) -> BattleGA<'a> {
+    pub fn new(population: Beetles, ui: &'a UI, scenario: Scenario) -> BattleGA<'a> {
 
         let mut game = Game::new();
+        scenario.set_up_map(&mut game);
         game.set_population(population);
 
         BattleGA {
             ui,
             game,
+            scenario,
         }
     }
 }
@@ -48,44 +56,26 @@ impl<'a> GeneticAlgorithm for BattleGA<'a> {
     }
 
     fn fitness(&self, beetle: &Beetle) -> f32 {
-        beetle.damage_inflicted as f32
+        beetle.damage_inflicted as f32 + beetle.kills as f32 * KILL_FITNESS_BONUS
     }
 
     fn run_generation(&mut self) {
 
-        let mut rng = thread_rng();
-
         let population_size = self.game.field_state.beetles.len();
 
         for beetle in self.game.field_state.beetles.values_mut() {
             beetle.health = beetle.max_health();
+            beetle.energy = beetle.max_energy();
             beetle.damage_inflicted = 0;
+            beetle.kills = 0;
             beetle.color = Color { r: 213, g: 77, b: 77, a: 255 };
             // put them all on different teams so it's a free for all
             beetle.team_id = beetle.id;
 
-            let rand_x: f32 = rng.gen_range(100.0, 600.0);
-            let rand_y: f32 = rng.gen_range(100.0, 600.0);
-            beetle.position.x = rand_x;
-            beetle.position.y = rand_y;
+            beetle.position = self.scenario.random_position(BATTLE_MARGIN);
         }
 
-        {
-            let check_done_callback = |state: &FieldState| {
-                state.beetles.len() < ((population_size / 2) + 10) as usize
-            };
-
-            let mut sim = FightSimulation::new(&mut self.game, check_done_callback);
-            // TODO: should be a way to remove this. Currently its only
-            // purpose is so the type checker knows what kind of closure to
-            // implement above.
-            //let ui = self.ui.clone();
-            sim.set_tick_callback(move |_state| {
-                //ui.update_game_state(&_state);
-                //thread::sleep(Duration::from_millis(SIMULATION_PERIOD_MS));
-            });
-            sim.run();
-        }
+        run_free_for_all(&mut self.game, population_size);
 
         while self.game.field_state.beetles.len() < population_size as usize {
 
@@ -1,22 +1,95 @@
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+
 use simulation::Simulate;
-use game::{Game, FieldState, State};
+use game::{Game, FieldState, Command, GameEvent};
+use game_match::{Match, VictoryCondition, MatchState};
+use entities::Id;
+use utils::seconds_to_ticks;
+
+// fights that go on longer than this are called off
+const DEFAULT_MAX_SECONDS: f32 = 300.0;
+// and so are ones where nobody has hurt anybody for this long
+const DEFAULT_STALEMATE_SECONDS: f32 = 30.0;
+// the GAs' free for alls are called off sooner
+const FREE_FOR_ALL_SECONDS: f32 = 120.0;
+const FREE_FOR_ALL_STALEMATE_SECONDS: f32 = 10.0;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum FightEndReason {
+    // one of the victory conditions was met
+    Victory,
+    // nobody did any damage for too long
+    Stalemate,
+    // ran out of ticks
+    TimeLimit,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FightResult {
+    pub end_reason: FightEndReason,
+    pub ticks: u64,
+    // empty unless there was a victory
+    pub winners: Vec<Id>,
+    // beetles left on each team, including the ones with none left
+    pub survivors: BTreeMap<Id, usize>,
+    // damage[attacker's team][victim's team]
+    pub damage: BTreeMap<Id, BTreeMap<Id, i32>>,
+}
+
+// The free for all the battle GAs thin out a population with. Everyone has
+// to be on their own team already. It's over once about half of them are
+// gone, or once nobody has found anybody to bite for a while.
+pub fn run_free_for_all(game: &mut Game, population_size: usize) {
+
+    let conditions = vec![
+        VictoryCondition::Survivors { count: (population_size / 2) + 9 }
+    ];
+
+    let mut sim = FightSimulation::new(game, conditions);
+    sim.set_max_ticks(seconds_to_ticks(FREE_FOR_ALL_SECONDS) as u64);
+    sim.set_stalemate_ticks(seconds_to_ticks(FREE_FOR_ALL_STALEMATE_SECONDS) as u64);
+    // nobody watches these
+    sim.set_tick_callback(|_state| {});
+    sim.run();
+}
 
-// Represents a single fight, without generations.
-pub struct FightSimulation<'a, T: Fn(&FieldState), U: Fn(&FieldState) -> bool> {
+// Represents a single fight, without generations. It's over when one of the
+// victory conditions is met, or it's given up on.
+pub struct FightSimulation<'a, T: Fn(&FieldState)> {
     game: &'a mut Game,
     tick_callback: Option<T>,
-    check_done_callback: U,
+    conditions: Vec<VictoryCondition>,
+    max_ticks: u64,
+    stalemate_ticks: u64,
+    result: Option<FightResult>,
 }
 
-impl<'a, T: Fn(&FieldState), U: Fn(&FieldState) -> bool> FightSimulation<'a, T, U> {
-    pub fn new(game: &'a mut Game, check_done_callback: U) -> FightSimulation<'a, T, U> {
+impl<'a, T: Fn(&FieldState)> FightSimulation<'a, T> {
+    pub fn new(game: &'a mut Game, conditions: Vec<VictoryCondition>) -> FightSimulation<'a, T> {
         FightSimulation {
             game,
             tick_callback: None,
-            check_done_callback: check_done_callback,
+            conditions,
+            max_ticks: seconds_to_ticks(DEFAULT_MAX_SECONDS) as u64,
+            stalemate_ticks: seconds_to_ticks(DEFAULT_STALEMATE_SECONDS) as u64,
+            result: None,
         }
     }
 
+    pub fn set_max_ticks(&mut self, max_ticks: u64) {
+        self.max_ticks = max_ticks;
+    }
+
+    pub fn set_stalemate_ticks(&mut self, stalemate_ticks: u64) {
+        self.stalemate_ticks = stalemate_ticks;
+    }
+
+    // How the fight turned out, once it's been run
+    pub fn result(&self) -> Option<&FightResult> {
+        self.result.as_ref()
+    }
+
     pub fn set_tick_callback(&mut self, tick_callback: T) {
         self.tick_callback = Some(tick_callback);
 
@@ -24,41 +97,117 @@ impl<'a, T: Fn(&FieldState), U: Fn(&FieldState) -> bool> FightSimulation<'a, T,
             cb(&self.game.field_state);
         }
     }
+
+    // Sends idle beetles after the closest enemy they can smell, or off to
+    // look for one
+    fn give_orders(&mut self) {
+        let ids: Vec<Id> = self.game.field_state.beetles.keys().cloned().collect();
+
+        for id in ids {
+            let command = match self.game.field_state.beetles.get(&id) {
+                Some(beetle) => {
+                    match self.game.find_closest_enemy(beetle) {
+                        Some(target_id) => Command::Interact{ target_id },
+                        // nobody close enough to smell, go and find someone
+                        None => Command::Wander,
+                    }
+                },
+                None => continue,
+            };
+
+            if let Some(beetle) = self.game.field_state.beetles.get_mut(&id) {
+                if beetle.current_command != command {
+                    beetle.set_command(command);
+                }
+            }
+        }
+    }
 }
 
-impl<'a, T: Fn(&FieldState), U: Fn(&FieldState) -> bool> Simulate<T> for FightSimulation<'a, T, U> {
+impl<'a, T: Fn(&FieldState)> Simulate<T> for FightSimulation<'a, T> {
 
     fn run(&mut self) {
 
-        // TODO: get rid of clone somehow
-        let beetles = self.game.field_state.beetles.clone();
+        // positions are often shuffled around right before a fight
+        self.game.field_state.update_spatial_index();
 
-        while !(self.check_done_callback)(&self.game.field_state) {
+        // the fight gets its own match, and whatever match the game was
+        // already running is put back afterwards
+        let previous_match = mem::replace(
+            &mut self.game.field_state.game_match, Match::new(Vec::new()));
+        self.game.start_match(self.conditions.clone());
 
-            for beetle in beetles.values() {
+        // dead beetles are gone by the time their damage is counted, so
+        // everyone's team is remembered
+        let mut team_ids: HashMap<Id, Id> = HashMap::new();
+        let mut survivors = BTreeMap::new();
+        let mut damage: BTreeMap<Id, BTreeMap<Id, i32>> = BTreeMap::new();
+        let mut ticks = 0;
+        let mut quiet_ticks = 0;
 
-                if beetle.current_state == State::Idle {
-                    if let Some(closest_beetle_id) = self.game.find_closest_enemy(&beetle) {
-                        //println!("{} attack {}", beetle.id, closest_beetle_id);
-                        self.game.select_beetle(beetle.id);
-                        self.game.selected_interact_command(closest_beetle_id);
-                        self.game.deselect_all_beetles();
-                    }
-                    else {
-                        println!("no enemies for {}", beetle.id);
+        let end_reason = loop {
+            if self.game.field_state.game_match.state() == MatchState::Ended {
+                break FightEndReason::Victory;
+            }
+            if ticks >= self.max_ticks {
+                break FightEndReason::TimeLimit;
+            }
+            if quiet_ticks >= self.stalemate_ticks {
+                break FightEndReason::Stalemate;
+            }
+
+            for beetle in self.game.field_state.beetles.values() {
+                team_ids.insert(beetle.id, beetle.team_id);
+                survivors.entry(beetle.team_id).or_insert(0);
+            }
+
+            self.give_orders();
+
+            self.game.tick();
+            ticks += 1;
+            quiet_ticks += 1;
+
+            for event in self.game.field_state.events.iter() {
+                if let GameEvent::BeetleDamaged{ id, attacker_id, damage: amount, .. } = *event {
+                    if let (Some(team_id), Some(attacker_team_id)) = (
+                            team_ids.get(&id), team_ids.get(&attacker_id)) {
+                        *damage.entry(*attacker_team_id).or_insert(BTreeMap::new())
+                            .entry(*team_id).or_insert(0) += amount;
                     }
-                }
-                else {
-                    println!("not idle");
+                    quiet_ticks = 0;
                 }
             }
 
-            self.game.tick();
             if let Some(tick_callback) = self.get_tick_callback() {
                 tick_callback(&self.game.field_state);
             }
+        };
+
+        for count in survivors.values_mut() {
+            *count = 0;
+        }
+        for beetle in self.game.field_state.beetles.values() {
+            *survivors.entry(beetle.team_id).or_insert(0) += 1;
         }
 
+        let winners = match end_reason {
+            FightEndReason::Victory => {
+                self.game.field_state.game_match.result()
+                    .map(|result| result.winners.clone())
+                    .unwrap_or(Vec::new())
+            },
+            _ => Vec::new(),
+        };
+
+        self.game.field_state.game_match = previous_match;
+
+        self.result = Some(FightResult {
+            end_reason,
+            ticks,
+            winners,
+            survivors,
+            damage,
+        });
     }
 
     fn get_tick_callback(&self) -> Option<&T> {
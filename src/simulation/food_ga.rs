@@ -6,14 +6,18 @@ use ui::UI;
 use game::{Game, Command};
 use utils::{SIMULATION_PERIOD_MS, Color};
 use entities::{Entity, Beetle, Beetles};
+use scenario::Scenario;
 
 pub struct FoodGA<'a> {
     ui: &'a UI,
     game: Game,
+    // the map and food every generation starts out with
+    scenario: Scenario,
 }
 
 impl<'a> FoodGA<'a> {
-    pub fn new(population: Beetles, ui: &'a UI) -> FoodGA<'a> {
+    pub fn new(
+            population: Beetles, ui: &'a UI, scenario: Scenario) -> FoodGA<'a> {
 
         let mut game = Game::new();
         game.set_population(population);
@@ -21,6 +25,7 @@ impl<'a> FoodGA<'a> {
         FoodGA {
             ui,
             game,
+            scenario,
         }
     }
 }
@@ -29,18 +34,22 @@ impl<'a> GeneticAlgorithm for FoodGA<'a> {
 
     fn setup(&mut self) {
 
-        self.game.add_home_base(128.0, 128.0);
+        self.scenario.set_up_map(&mut self.game);
 
-        let food_source_id = self.game.add_food_source(256.0, 256.0);
-
-        if let Some(food_source) = self.game.field_state.food_sources.get_mut(&food_source_id) {
-            food_source.increase_food(1_000_000);
-        }
+        // everyone forages for the team that owns the bases, otherwise they'd
+        // have nowhere to take the food. Offspring are copies, so they stay
+        // on it too.
+        let team_id = self.game.field_state.get_home_bases().values()
+            .next()
+            .map(|home_base| home_base.get_team_id());
 
         for beetle in self.game.field_state.beetles.values_mut() {
             let color = Color { r: 144, g: 153, b: 212, a: 255 };
             beetle.color = color;
-            beetle.set_command(Command::HarvestClosestFood);
+            if let Some(team_id) = team_id {
+                beetle.team_id = team_id;
+            }
+            beetle.set_command(Command::Forage);
         }
     }
 
@@ -56,8 +65,15 @@ impl<'a> GeneticAlgorithm for FoodGA<'a> {
 
         for beetle in self.game.field_state.beetles.values_mut() {
             beetle.food_collected = 0;
+            beetle.energy = beetle.max_energy();
         }
 
+        // every generation has to find the food for itself
+        self.game.field_state.pheromones.clear();
+
+        // and starts out with the same amount of it
+        self.scenario.reset_food(&mut self.game);
+
         //while self.game.field_state.get_food_sources().len() > 0 {
         for _ in 0..2000 {
             self.game.tick();
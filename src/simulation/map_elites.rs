@@ -0,0 +1,306 @@
+use std::io;
+use std::fs::File;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json;
+
+use simulation::GeneticAlgorithm;
+use simulation::fight_simulation::run_free_for_all;
+use ui::UI;
+use game::{Game, Command};
+use entities::{Beetle, Beetles};
+use utils::{Color, Heatmap, HeatmapCell};
+use scenario::Scenario;
+use rand::{Rng, thread_rng};
+
+// number of cells along each axis of the archive
+const ARCHIVE_RESOLUTION: usize = 16;
+// damage dealt is open ended, so anything above this lands in the last column
+const MAX_DAMAGE_DEALT: f32 = 1000.0;
+// each run's archive gets its own file, named after when it finished
+const ARCHIVE_EXPORT_PREFIX: &str = "map_elites_archive";
+// beetles start at least this far from the edges of the map
+const BATTLE_MARGIN: f32 = 100.0;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum BehaviourDescriptor {
+    SizeSpeed,
+    DamageDealtTaken,
+}
+
+impl BehaviourDescriptor {
+
+    // Maps a beetle to a point in behaviour space. Both values are 0.0-1.0.
+    pub fn describe(&self, beetle: &Beetle) -> (f32, f32) {
+        match *self {
+            BehaviourDescriptor::SizeSpeed => {
+                (beetle.size(), beetle.speed_ratio())
+            },
+            BehaviourDescriptor::DamageDealtTaken => {
+                let max_health = beetle.max_health() as f32;
+                let damage_taken = max_health - beetle.health as f32;
+
+                (beetle.damage_inflicted as f32 / MAX_DAMAGE_DEALT,
+                 damage_taken / max_health)
+            },
+        }
+    }
+
+    pub fn labels(&self) -> (&'static str, &'static str) {
+        match *self {
+            BehaviourDescriptor::SizeSpeed => ("Size", "Speed"),
+            BehaviourDescriptor::DamageDealtTaken => {
+                ("Damage Dealt", "Damage Taken")
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Elite {
+    pub beetle: Beetle,
+    pub fitness: f32,
+    pub behaviour: (f32, f32),
+}
+
+// Grid of the best beetle found so far for each region of behaviour space.
+#[derive(Serialize, Debug)]
+pub struct Archive {
+    descriptor: BehaviourDescriptor,
+    resolution: usize,
+    cells: Vec<Option<Elite>>,
+}
+
+impl Archive {
+    pub fn new(descriptor: BehaviourDescriptor, resolution: usize) -> Archive {
+        Archive {
+            descriptor,
+            resolution,
+            cells: vec![None; resolution * resolution],
+        }
+    }
+
+    fn cell_coordinates(&self, behaviour: (f32, f32)) -> (usize, usize) {
+        let max_index = (self.resolution - 1) as f32;
+        let x = (behaviour.0.max(0.0).min(1.0) * max_index).round();
+        let y = (behaviour.1.max(0.0).min(1.0) * max_index).round();
+        (x as usize, y as usize)
+    }
+
+    // Returns true if the beetle made it into the archive, either by filling
+    // an empty cell or beating the current elite.
+    pub fn insert(&mut self, beetle: &Beetle, fitness: f32) -> bool {
+        let behaviour = self.descriptor.describe(beetle);
+        let (x, y) = self.cell_coordinates(behaviour);
+        let index = y * self.resolution + x;
+
+        let replace = match self.cells[index] {
+            Some(ref elite) => fitness > elite.fitness,
+            None => true,
+        };
+
+        if replace {
+            self.cells[index] = Some(Elite {
+                beetle: beetle.clone(),
+                fitness,
+                behaviour,
+            });
+        }
+
+        replace
+    }
+
+    pub fn elites(&self) -> Vec<&Elite> {
+        self.cells.iter().filter_map(|cell| cell.as_ref()).collect()
+    }
+
+    pub fn random_elite(&self) -> Option<&Elite> {
+        let elites = self.elites();
+
+        if elites.len() > 0 {
+            let index = thread_rng().gen_range(0, elites.len());
+            Some(elites[index])
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn num_filled(&self) -> usize {
+        self.elites().len()
+    }
+
+    pub fn heatmap(&self) -> Heatmap {
+        let (x_label, y_label) = self.descriptor.labels();
+
+        let mut cells = Vec::new();
+        for (i, cell) in self.cells.iter().enumerate() {
+            if let Some(ref elite) = *cell {
+                cells.push(HeatmapCell {
+                    x: i % self.resolution,
+                    y: i / self.resolution,
+                    value: elite.fitness,
+                });
+            }
+        }
+
+        Heatmap {
+            name: "MAP-Elites Archive".to_string(),
+            x_label: x_label.to_string(),
+            y_label: y_label.to_string(),
+            width: self.resolution,
+            height: self.resolution,
+            cells,
+        }
+    }
+
+    pub fn export(&self, path: &str) -> io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+}
+
+// Quality-diversity search. Rather than converging on a single design, keeps
+// the fittest fighter for every cell of a behaviour grid, so the end result
+// is a whole map of different ways to win fights.
+pub struct MapElitesGA<'a> {
+    ui: &'a UI,
+    game: Game,
+    archive: Archive,
+    population_size: usize,
+    // where the fights happen
+    scenario: Scenario,
+}
+
+impl<'a> MapElitesGA<'a> {
+    pub fn new(
+            population: Beetles, descriptor: BehaviourDescriptor,
+            ui: &'a UI, scenario: Scenario) -> MapElitesGA<'a> {
+
+        let population_size = population.len();
+
+        let mut game = Game::new();
+        scenario.set_up_map(&mut game);
+        game.set_population(population);
+
+        MapElitesGA {
+            ui,
+            game,
+            archive: Archive::new(descriptor, ARCHIVE_RESOLUTION),
+            population_size,
+            scenario,
+        }
+    }
+
+    pub fn get_archive(&self) -> &Archive {
+        &self.archive
+    }
+
+    // Every offspring gets a new value for one gene. Unlike the standard
+    // mutation there's no point in evaluating unchanged copies of elites.
+    fn vary(&self, parent: &Beetle) -> Beetle {
+        let mut offspring = parent.clone();
+
+        let random_val = thread_rng().gen::<f32>();
+        let random_gene_index = offspring.genome.get_random_gene_index();
+        offspring.genome.set_gene_value(random_gene_index, random_val);
+
+        offspring
+    }
+
+    // Free for all, same as BattleGA. Only the beetles still alive when it's
+    // over are eligible for the archive.
+    fn evaluate_population(&mut self) {
+
+        for beetle in self.game.field_state.beetles.values_mut() {
+            beetle.health = beetle.max_health();
+            beetle.energy = beetle.max_energy();
+            beetle.damage_inflicted = 0;
+            beetle.color = Color { r: 213, g: 77, b: 77, a: 255 };
+            beetle.team_id = beetle.id;
+            beetle.set_command(Command::Stop);
+
+            beetle.position = self.scenario.random_position(BATTLE_MARGIN);
+        }
+
+        let population_size = self.population_size;
+
+        run_free_for_all(&mut self.game, population_size);
+
+        // the dead have already been taken off the map, this just makes sure
+        for beetle in self.game.field_state.beetles.values() {
+            if beetle.health <= 0 {
+                continue;
+            }
+            let fitness = self.fitness(beetle);
+            self.archive.insert(beetle, fitness);
+        }
+    }
+}
+
+impl<'a> GeneticAlgorithm for MapElitesGA<'a> {
+
+    fn cleanup(&mut self) {
+        for beetle in self.game.field_state.beetles.values_mut() {
+            beetle.set_command(Command::Stop);
+        }
+
+        let path = archive_export_path();
+
+        match self.archive.export(&path) {
+            Ok(()) => {
+                println!("Exported {} elites to {}",
+                    self.archive.num_filled(), path);
+            },
+            Err(e) => {
+                println!("archive export error: {}", e);
+            }
+        }
+    }
+
+    fn get_game(&self) -> &Game {
+        &self.game
+    }
+
+    fn get_ui(&self) -> &UI {
+        self.ui
+    }
+
+    fn fitness(&self, beetle: &Beetle) -> f32 {
+        beetle.damage_inflicted as f32
+    }
+
+    fn run_generation(&mut self) {
+
+        // the first generation evaluates the initial population, after that
+        // every generation is made of offspring of random elites
+        if self.archive.num_filled() > 0 {
+
+            let mut offspring = Vec::with_capacity(self.population_size);
+
+            while offspring.len() < self.population_size {
+                if let Some(elite) = self.archive.random_elite() {
+                    offspring.push(self.vary(&elite.beetle));
+                }
+            }
+
+            self.game.field_state.beetles.clear();
+
+            for beetle in offspring {
+                self.game.add_beetle(beetle);
+            }
+        }
+
+        self.evaluate_population();
+
+        self.ui.update_heatmap(&self.archive.heatmap());
+    }
+}
+
+fn archive_export_path() -> String {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    format!("{}_{}.json", ARCHIVE_EXPORT_PREFIX, timestamp)
+}
@@ -2,6 +2,7 @@ pub mod speed_ga;
 pub mod food_ga;
 pub mod battle_ga;
 pub mod fight_simulation;
+pub mod map_elites;
 
 use game::{Game};
 use ui::UI;
@@ -9,7 +10,7 @@ use entities::{Beetle, Beetles};
 use rand::{Rng, thread_rng};
 
 const NUM_GENERATIONS: i32 = 128;
-const MUTATION_RATE: f32 = 0.1;
+pub const MUTATION_RATE: f32 = 0.1;
 const SELECTION_BIAS: f32 = 0.8;
 
 pub trait Simulate<T> {
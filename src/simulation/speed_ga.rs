@@ -2,21 +2,25 @@ use simulation::GeneticAlgorithm;
 use ui::UI;
 use game::{Game, STARTING_ID};
 use entities::{Beetle, Beetles};
-use cgmath::{Point2};
-use rand::{Rng, thread_rng};
 use utils::{Color};
+use scenario::Scenario;
 
+// offspring are scattered anywhere at least this far from the edges
+const SPAWN_MARGIN: f32 = 25.0;
 
 pub struct SpeedGA<'a> {
     ui: &'a UI,
     game: &'a mut Game,
+    // the one the game was loaded from, for where offspring can go
+    scenario: &'a Scenario,
 }
 
 impl<'a> SpeedGA<'a> {
-    pub fn new(game: &'a mut Game, ui: &'a UI) -> SpeedGA<'a> {
+    pub fn new(game: &'a mut Game, ui: &'a UI, scenario: &'a Scenario) -> SpeedGA<'a> {
         SpeedGA {
             ui,
             game,
+            scenario,
         }
     }
 }
@@ -50,17 +54,19 @@ impl<'a> GeneticAlgorithm for SpeedGA<'a> {
             offspring1 = self.mutate(&parent1);
             offspring2 = self.mutate(&parent2);
 
+            // offspring stay on their parent's team, since this is the live
+            // game and strangers are hostile
             let color = Color { r: 144, g: 153, b: 212, a: 255 };
             offspring1.id = id;
-            offspring1.team_id = id;
             offspring1.color = color;
-            offspring1.position = random_position();
+            offspring1.energy = offspring1.max_energy();
+            offspring1.position = self.scenario.random_position(SPAWN_MARGIN);
             new_population.insert(id, offspring1);
             id += 1;
             offspring2.id = id;
-            offspring2.team_id = id;
             offspring2.color = color;
-            offspring2.position = random_position();
+            offspring2.energy = offspring2.max_energy();
+            offspring2.position = self.scenario.random_position(SPAWN_MARGIN);
             new_population.insert(id, offspring2);
             id += 1;
         }
@@ -72,12 +78,3 @@ impl<'a> GeneticAlgorithm for SpeedGA<'a> {
         beetle.speed()
     }
 }
-
-
-fn random_position() -> Point2<f32> {
-    let mut rng = thread_rng();
-    let rand_x: f32 = rng.gen_range(0.0, 500.0);
-    let rand_y: f32 = rng.gen_range(0.0, 500.0);
-
-    Point2::new(rand_x, rand_y)
-}
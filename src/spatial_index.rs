@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use cgmath::{Point2, InnerSpace};
+
+use entities::{Id, Beetles, FoodSources, HomeBases};
+use utils::Positioned;
+
+// Roughly the largest beetle plus its interaction range, so most queries
+// only need to look at the neighbouring cells.
+const CELL_SIZE_UNITS: f32 = 64.0;
+
+type Cell = (i32, i32);
+
+// Uniform grid for neighbour queries. Cells are only allocated where there's
+// something in them, so there's no need to know the size of the field
+// up front.
+#[derive(Debug)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<Cell, Vec<(Id, Point2<f32>)>>,
+    len: usize,
+    min_cell: Cell,
+    max_cell: Cell,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32) -> SpatialGrid {
+        SpatialGrid {
+            cell_size,
+            cells: HashMap::new(),
+            len: 0,
+            min_cell: (0, 0),
+            max_cell: (0, 0),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.len = 0;
+        self.min_cell = (0, 0);
+        self.max_cell = (0, 0);
+    }
+
+    pub fn insert(&mut self, id: Id, position: Point2<f32>) {
+        let cell = self.cell_for(position);
+
+        if self.len == 0 {
+            self.min_cell = cell;
+            self.max_cell = cell;
+        }
+        else {
+            self.min_cell = (self.min_cell.0.min(cell.0), self.min_cell.1.min(cell.1));
+            self.max_cell = (self.max_cell.0.max(cell.0), self.max_cell.1.max(cell.1));
+        }
+
+        self.cells.entry(cell).or_insert_with(Vec::new).push((id, position));
+        self.len += 1;
+    }
+
+    fn cell_for(&self, position: Point2<f32>) -> Cell {
+        ((position.x / self.cell_size).floor() as i32,
+         (position.y / self.cell_size).floor() as i32)
+    }
+
+    // The corners of the block of cells a circle overlaps
+    fn cells_within(&self, center: Point2<f32>, radius: f32) -> (Cell, Cell) {
+
+        let low = self.cell_for(Point2::new(center.x - radius, center.y - radius));
+        let high = self.cell_for(Point2::new(center.x + radius, center.y + radius));
+
+        // don't walk a huge empty area if the radius is much bigger than
+        // the populated part of the grid
+        ((low.0.max(self.min_cell.0), low.1.max(self.min_cell.1)),
+         (high.0.min(self.max_cell.0), high.1.min(self.max_cell.1)))
+    }
+
+    // All entities within radius of center, in no particular order.
+    pub fn query_radius(&self, center: Point2<f32>, radius: f32) -> Vec<Id> {
+
+        let mut found = Vec::new();
+
+        let (low, high) = self.cells_within(center, radius);
+
+        for x in low.0..(high.0 + 1) {
+            for y in low.1..(high.1 + 1) {
+                if let Some(entries) = self.cells.get(&(x, y)) {
+                    for &(id, position) in entries {
+                        if (position - center).magnitude() <= radius {
+                            found.push(id);
+                        }
+                    }
+                }
+            }
+        }
+
+        found
+    }
+
+    // Up to k entities accepted by filter, closest first. Searches outward
+    // one ring of cells at a time and stops as soon as nothing further out
+    // could beat what's already been found.
+    pub fn k_nearest<F: Fn(Id) -> bool>(
+            &self, center: Point2<f32>, k: usize, filter: F) -> Vec<Id> {
+
+        let mut found: Vec<(f32, Id)> = Vec::new();
+
+        if k == 0 || self.len == 0 {
+            return Vec::new();
+        }
+
+        let (cx, cy) = self.cell_for(center);
+        let max_ring = *[
+            cx - self.min_cell.0, self.max_cell.0 - cx,
+            cy - self.min_cell.1, self.max_cell.1 - cy,
+        ].iter().max().unwrap();
+
+        for ring in 0..(max_ring.max(0) + 1) {
+
+            for x in (cx - ring)..(cx + ring + 1) {
+                for y in (cy - ring)..(cy + ring + 1) {
+
+                    // only the outline of the square, the inside has already
+                    // been searched
+                    if (x - cx).abs() != ring && (y - cy).abs() != ring {
+                        continue;
+                    }
+
+                    if let Some(entries) = self.cells.get(&(x, y)) {
+                        for &(id, position) in entries {
+                            if filter(id) {
+                                found.push(((position - center).magnitude(), id));
+                            }
+                        }
+                    }
+                }
+            }
+
+            // everything within this distance of center has been seen
+            let covered = ring as f32 * self.cell_size;
+
+            if found.len() >= k {
+                sort_by_distance(&mut found);
+                if found[k - 1].0 <= covered {
+                    break;
+                }
+            }
+        }
+
+        sort_by_distance(&mut found);
+        found.truncate(k);
+        found.into_iter().map(|(_, id)| id).collect()
+    }
+
+    pub fn nearest<F: Fn(Id) -> bool>(
+            &self, center: Point2<f32>, filter: F) -> Option<Id> {
+        self.k_nearest(center, 1, filter).into_iter().next()
+    }
+
+    // Like nearest, but only looks at the cells within radius, so it's
+    // cheap when there's nothing nearby.
+    pub fn nearest_within<F: Fn(Id) -> bool>(
+            &self, center: Point2<f32>, radius: f32, filter: F) -> Option<Id> {
+
+        let mut closest: Option<(f32, Id)> = None;
+
+        if self.len == 0 {
+            return None;
+        }
+
+        let (low, high) = self.cells_within(center, radius);
+
+        for x in low.0..(high.0 + 1) {
+            for y in low.1..(high.1 + 1) {
+                if let Some(entries) = self.cells.get(&(x, y)) {
+                    for &(id, position) in entries {
+                        let distance = (position - center).magnitude();
+
+                        if distance > radius || !filter(id) {
+                            continue;
+                        }
+
+                        // ties go to the lower id, so the answer doesn't
+                        // depend on the order things were inserted in
+                        let closer = match closest {
+                            Some((best, best_id)) => {
+                                distance < best || (distance == best && id < best_id)
+                            },
+                            None => true,
+                        };
+
+                        if closer {
+                            closest = Some((distance, id));
+                        }
+                    }
+                }
+            }
+        }
+
+        closest.map(|(_, id)| id)
+    }
+}
+
+fn sort_by_distance(found: &mut Vec<(f32, Id)>) {
+    found.sort_by(|a, b| {
+        a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal)
+    });
+}
+
+// One grid per kind of entity. Rebuilt by Game::tick, so positions can be up
+// to a tick out of date and ids may refer to entities that have since been
+// removed. Callers should always look results up in the real collections.
+#[derive(Debug)]
+pub struct SpatialIndex {
+    pub beetles: SpatialGrid,
+    pub food_sources: SpatialGrid,
+    pub home_bases: SpatialGrid,
+}
+
+impl SpatialIndex {
+    pub fn new() -> SpatialIndex {
+        SpatialIndex {
+            beetles: SpatialGrid::new(CELL_SIZE_UNITS),
+            food_sources: SpatialGrid::new(CELL_SIZE_UNITS),
+            home_bases: SpatialGrid::new(CELL_SIZE_UNITS),
+        }
+    }
+
+    pub fn rebuild(
+            &mut self, beetles: &Beetles, food_sources: &FoodSources,
+            home_bases: &HomeBases) {
+
+        self.beetles.clear();
+        for beetle in beetles.values() {
+            self.beetles.insert(beetle.id, beetle.position);
+        }
+
+        self.food_sources.clear();
+        for (id, food_source) in food_sources.iter() {
+            self.food_sources.insert(*id, food_source.get_position());
+        }
+
+        self.home_bases.clear();
+        for (id, home_base) in home_bases.iter() {
+            self.home_bases.insert(*id, home_base.get_position());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point2;
+    use super::SpatialGrid;
+
+    // a row of points 10 units apart along y = 0, id i at x = i * 10
+    fn row(count: i32) -> SpatialGrid {
+        let mut grid = SpatialGrid::new(64.0);
+        for id in 0..count {
+            grid.insert(id, Point2::new(id as f32 * 10.0, 0.0));
+        }
+        grid
+    }
+
+    #[test]
+    fn k_nearest_is_closest_first() {
+        let grid = row(50);
+        let found = grid.k_nearest(Point2::new(203.0, 0.0), 4, |_| true);
+        assert_eq!(found, vec![20, 21, 19, 22]);
+    }
+
+    #[test]
+    fn k_nearest_searches_past_empty_rings() {
+        let mut grid = SpatialGrid::new(64.0);
+        grid.insert(1, Point2::new(0.0, 0.0));
+        grid.insert(2, Point2::new(1000.0, 1000.0));
+
+        let found = grid.k_nearest(Point2::new(900.0, 900.0), 2, |_| true);
+        assert_eq!(found, vec![2, 1]);
+    }
+
+    #[test]
+    fn k_nearest_skips_filtered_out() {
+        let grid = row(50);
+        let found = grid.k_nearest(Point2::new(200.0, 0.0), 3, |id| id % 2 == 1);
+        assert_eq!(found.len(), 3);
+        assert!(found.contains(&19) && found.contains(&21));
+        assert!(found[2] == 17 || found[2] == 23);
+    }
+
+    #[test]
+    fn k_nearest_returns_what_there_is() {
+        let grid = row(3);
+        assert_eq!(grid.k_nearest(Point2::new(0.0, 0.0), 10, |_| true).len(), 3);
+        assert!(SpatialGrid::new(64.0).k_nearest(Point2::new(0.0, 0.0), 1, |_| true).is_empty());
+    }
+
+    #[test]
+    fn nearest_within_stays_in_range() {
+        let grid = row(50);
+        assert_eq!(grid.nearest_within(Point2::new(203.0, 0.0), 5.0, |_| true), Some(20));
+        assert_eq!(grid.nearest_within(Point2::new(205.0, 50.0), 40.0, |_| true), None);
+        assert_eq!(grid.nearest_within(Point2::new(203.0, 0.0), 150.0, |id| id > 30), Some(31));
+        assert_eq!(grid.nearest_within(Point2::new(203.0, 0.0), 50.0, |id| id > 30 && id < 10), None);
+    }
+
+    #[test]
+    fn query_radius_finds_everything_in_range() {
+        let grid = row(50);
+        let mut found = grid.query_radius(Point2::new(200.0, 0.0), 25.0);
+        found.sort();
+        assert_eq!(found, vec![18, 19, 20, 21, 22]);
+    }
+}
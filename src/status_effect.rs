@@ -0,0 +1,345 @@
+use entities::{Id, Beetle, Beetles};
+use beetle_genome::BeetleGeneIndex as Gene;
+use utils::seconds_to_ticks;
+
+// how much a full load slows down the weakest possible beetle
+const MAX_OVERLOAD_SLOWDOWN: f32 = 0.5;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum StatusEffectKind {
+    // loses magnitude health every tick
+    Poisoned,
+    // can't move, turn or attack
+    Stunned,
+    // moves and turns magnitude slower, as a fraction of normal
+    Slowed,
+    // hits magnitude harder for every stack, as a fraction of normal
+    Enraged,
+    // weighed down by the food it's carrying. Moves magnitude slower, as a
+    // fraction of normal.
+    CarryingOverload,
+    // out of energy, and losing magnitude health every tick until it eats
+    Starving,
+}
+
+// What happens when a beetle gets an effect it already has
+enum Stacking {
+    // the stronger of the two wins, and lasts as long as whichever had
+    // longer left
+    Strongest,
+    // adds another stack, up to a limit, and starts the duration over
+    Stack(u32),
+    // the new one always takes over
+    Replace,
+}
+
+impl StatusEffectKind {
+    fn stacking(&self) -> Stacking {
+        match *self {
+            StatusEffectKind::Poisoned => Stacking::Strongest,
+            StatusEffectKind::Stunned => Stacking::Strongest,
+            StatusEffectKind::Slowed => Stacking::Strongest,
+            StatusEffectKind::Enraged => Stacking::Stack(3),
+            StatusEffectKind::CarryingOverload => Stacking::Replace,
+            StatusEffectKind::Starving => Stacking::Replace,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StatusEffect {
+    pub kind: StatusEffectKind,
+    pub magnitude: f32,
+    // None for effects that last until something takes them away
+    pub ticks_left: Option<u32>,
+    pub stacks: u32,
+    // whoever caused the effect, so any damage it does can be credited to them
+    pub source_id: Option<Id>,
+    // damage owed that doesn't add up to a whole point of health yet
+    pending_damage: f32,
+}
+
+impl StatusEffect {
+    pub fn new(kind: StatusEffectKind, magnitude: f32, seconds: f32) -> StatusEffect {
+        StatusEffect {
+            kind,
+            magnitude,
+            ticks_left: Some(seconds_to_ticks(seconds).max(1)),
+            stacks: 1,
+            source_id: None,
+            pending_damage: 0.0,
+        }
+    }
+
+    pub fn lasting(kind: StatusEffectKind, magnitude: f32) -> StatusEffect {
+        StatusEffect {
+            kind,
+            magnitude,
+            ticks_left: None,
+            stacks: 1,
+            source_id: None,
+            pending_damage: 0.0,
+        }
+    }
+
+    pub fn caused_by(mut self, source_id: Id) -> StatusEffect {
+        self.source_id = Some(source_id);
+        self
+    }
+}
+
+pub fn add(effects: &mut Vec<StatusEffect>, effect: StatusEffect) {
+    let existing = effects.iter().position(|current| current.kind == effect.kind);
+
+    let index = match existing {
+        Some(index) => index,
+        None => {
+            effects.push(effect);
+            return;
+        },
+    };
+
+    let current = &mut effects[index];
+
+    match effect.kind.stacking() {
+        Stacking::Strongest => {
+            let ticks_left = match (current.ticks_left, effect.ticks_left) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                _ => None,
+            };
+
+            if effect.magnitude > current.magnitude {
+                let pending_damage = current.pending_damage;
+                *current = effect;
+                current.pending_damage = pending_damage;
+            }
+            current.ticks_left = ticks_left;
+        },
+        Stacking::Stack(max_stacks) => {
+            current.stacks = (current.stacks + 1).min(max_stacks);
+            current.magnitude = current.magnitude.max(effect.magnitude);
+            current.ticks_left = effect.ticks_left;
+            current.source_id = effect.source_id;
+        },
+        Stacking::Replace => {
+            *current = effect;
+        },
+    }
+}
+
+pub fn remove(effects: &mut Vec<StatusEffect>, kind: StatusEffectKind) {
+    effects.retain(|effect| effect.kind != kind);
+}
+
+pub fn speed_multiplier(effects: &[StatusEffect]) -> f32 {
+    effects.iter().fold(1.0, |multiplier, effect| {
+        match effect.kind {
+            StatusEffectKind::Stunned => 0.0,
+            StatusEffectKind::Slowed |
+            StatusEffectKind::CarryingOverload => {
+                multiplier * (1.0 - effect.magnitude).max(0.0)
+            },
+            _ => multiplier,
+        }
+    })
+}
+
+pub fn turn_multiplier(effects: &[StatusEffect]) -> f32 {
+    effects.iter().fold(1.0, |multiplier, effect| {
+        match effect.kind {
+            StatusEffectKind::Stunned => 0.0,
+            StatusEffectKind::Slowed => multiplier * (1.0 - effect.magnitude).max(0.0),
+            _ => multiplier,
+        }
+    })
+}
+
+pub fn attack_multiplier(effects: &[StatusEffect]) -> f32 {
+    effects.iter().fold(1.0, |multiplier, effect| {
+        match effect.kind {
+            StatusEffectKind::Stunned => 0.0,
+            StatusEffectKind::Enraged => {
+                multiplier * (1.0 + effect.magnitude * effect.stacks as f32)
+            },
+            _ => multiplier,
+        }
+    })
+}
+
+// Weak beetles struggle under a full load, strong ones barely notice
+fn update_overload(beetle: &mut Beetle) {
+    if beetle.food_carrying > 0 {
+        let load = beetle.load();
+        let weakness = 1.0 - beetle.genome.get_gene(Gene::Strength);
+        let slowdown = load.min(1.0) * weakness * MAX_OVERLOAD_SLOWDOWN;
+
+        add(&mut beetle.status_effects, StatusEffect::lasting(
+            StatusEffectKind::CarryingOverload, slowdown));
+    }
+    else {
+        remove(&mut beetle.status_effects, StatusEffectKind::CarryingOverload);
+    }
+}
+
+// Counts down every beetle's effects and does a tick's worth of poison and
+// starvation damage. Returns the ids of the beetles that died along with
+// whoever poisoned them, and leaves it to the caller to remove them. Also
+// returns who was poisoned by whom and for how much.
+pub fn update(beetles: &mut Beetles) -> (Vec<(Id, Option<Id>)>, Vec<(Id, Id, i32)>) {
+    let mut dead = Vec::new();
+    let mut damage_inflicted = Vec::new();
+
+    for beetle in beetles.values_mut() {
+        update_overload(beetle);
+
+        let mut damage = 0;
+        let mut poisoner = None;
+
+        for effect in beetle.status_effects.iter_mut() {
+            if effect.kind == StatusEffectKind::Poisoned ||
+                    effect.kind == StatusEffectKind::Starving {
+                effect.pending_damage += effect.magnitude * effect.stacks as f32;
+
+                let effect_damage = effect.pending_damage.floor() as i32;
+                effect.pending_damage -= effect_damage as f32;
+
+                if let (Some(source_id), true) = (effect.source_id, effect_damage > 0) {
+                    damage_inflicted.push((beetle.id, source_id, effect_damage));
+                }
+
+                damage += effect_damage;
+                if effect.kind == StatusEffectKind::Poisoned {
+                    poisoner = effect.source_id;
+                }
+            }
+
+            if let Some(ref mut ticks_left) = effect.ticks_left {
+                *ticks_left = ticks_left.saturating_sub(1);
+            }
+        }
+
+        beetle.status_effects.retain(|effect| effect.ticks_left != Some(0));
+
+        if damage > 0 && beetle.take_damage(damage) {
+            dead.push((beetle.id, poisoner));
+        }
+    }
+
+    for &(_, source_id, damage) in damage_inflicted.iter() {
+        if let Some(source) = beetles.get_mut(&source_id) {
+            source.damage_inflicted += damage;
+        }
+    }
+
+    (dead, damage_inflicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use entities::{Beetle, Beetles};
+    use super::{StatusEffect, StatusEffectKind, add, remove, update,
+                speed_multiplier, attack_multiplier};
+
+    fn ticks(effect: &StatusEffect) -> u32 {
+        effect.ticks_left.unwrap()
+    }
+
+    #[test]
+    fn strongest_keeps_the_bigger_magnitude_and_longer_duration() {
+        let mut effects = Vec::new();
+        add(&mut effects, StatusEffect::new(StatusEffectKind::Slowed, 0.2, 5.0));
+        let long = ticks(&effects[0]);
+
+        add(&mut effects, StatusEffect::new(StatusEffectKind::Slowed, 0.5, 1.0));
+        assert_eq!(effects.len(), 1);
+        assert_eq!(effects[0].magnitude, 0.5);
+        assert_eq!(ticks(&effects[0]), long);
+
+        add(&mut effects, StatusEffect::new(StatusEffectKind::Slowed, 0.1, 1.0));
+        assert_eq!(effects[0].magnitude, 0.5);
+    }
+
+    #[test]
+    fn rage_stacks_up_to_its_limit() {
+        let mut effects = Vec::new();
+        for _ in 0..5 {
+            add(&mut effects, StatusEffect::new(StatusEffectKind::Enraged, 0.2, 5.0));
+        }
+        assert_eq!(effects.len(), 1);
+        assert_eq!(effects[0].stacks, 3);
+        assert!((attack_multiplier(&effects) - 1.6).abs() < 0.001);
+    }
+
+    #[test]
+    fn replace_always_takes_the_new_one() {
+        let mut effects = Vec::new();
+        add(&mut effects, StatusEffect::lasting(StatusEffectKind::CarryingOverload, 0.4));
+        add(&mut effects, StatusEffect::lasting(StatusEffectKind::CarryingOverload, 0.1));
+        assert_eq!(effects.len(), 1);
+        assert_eq!(effects[0].magnitude, 0.1);
+
+        remove(&mut effects, StatusEffectKind::CarryingOverload);
+        assert!(effects.is_empty());
+    }
+
+    #[test]
+    fn different_kinds_combine() {
+        let mut effects = Vec::new();
+        add(&mut effects, StatusEffect::new(StatusEffectKind::Slowed, 0.5, 5.0));
+        add(&mut effects, StatusEffect::lasting(StatusEffectKind::CarryingOverload, 0.5));
+        assert!((speed_multiplier(&effects) - 0.25).abs() < 0.001);
+
+        add(&mut effects, StatusEffect::new(StatusEffectKind::Stunned, 1.0, 1.0));
+        assert_eq!(speed_multiplier(&effects), 0.0);
+        assert_eq!(attack_multiplier(&effects), 0.0);
+    }
+
+    #[test]
+    fn poison_hurts_credits_the_poisoner_and_wears_off() {
+        let mut beetles: Beetles = HashMap::new();
+
+        let mut poisoner = Beetle::new();
+        poisoner.id = 1;
+        beetles.insert(1, poisoner);
+
+        let mut victim = Beetle::new();
+        victim.id = 2;
+        victim.health = 1000;
+        // 0.5 a tick for four ticks
+        victim.status_effects.push(StatusEffect::new(
+            StatusEffectKind::Poisoned, 0.5, 0.0).caused_by(1));
+        victim.status_effects[0].ticks_left = Some(4);
+        beetles.insert(2, victim);
+
+        let mut total = 0;
+        for _ in 0..4 {
+            let (dead, poisonings) = update(&mut beetles);
+            assert!(dead.is_empty());
+            for (id, source_id, damage) in poisonings {
+                assert_eq!((id, source_id), (2, 1));
+                total += damage;
+            }
+        }
+
+        assert_eq!(total, 2);
+        assert_eq!(beetles[&2].health, 998);
+        assert_eq!(beetles[&1].damage_inflicted, 2);
+        assert!(!beetles[&2].has_status_effect(StatusEffectKind::Poisoned));
+    }
+
+    #[test]
+    fn poison_can_kill() {
+        let mut beetles: Beetles = HashMap::new();
+
+        let mut victim = Beetle::new();
+        victim.id = 2;
+        victim.health = 3;
+        victim.status_effects.push(StatusEffect::new(
+            StatusEffectKind::Poisoned, 5.0, 1.0).caused_by(7));
+        beetles.insert(2, victim);
+
+        let (dead, _) = update(&mut beetles);
+        assert_eq!(dead, vec![(2, Some(7))]);
+    }
+}
@@ -0,0 +1,228 @@
+use std::thread;
+use std::collections::HashMap;
+use websocket::{ClientBuilder, OwnedMessage};
+use gen::bot::{
+    BotMessage, ClaimTeam, ServerMessage, Observation, BotCommands, BotCommand,
+    BotCommandType
+};
+use protobuf::{parse_from_bytes, RepeatedField, Message};
+
+use entities::Id;
+
+// What the stub last told each beetle to do
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Order {
+    Forage,
+    Attack(Id),
+}
+
+// A bot that's as simple as they come, for trying out the bot server. It
+// attacks the closest enemy any of its beetles can see and sends everyone
+// else foraging.
+pub struct StubBot {
+    orders: HashMap<Id, Order>,
+}
+
+impl StubBot {
+    pub fn new() -> StubBot {
+        StubBot {
+            orders: HashMap::new(),
+        }
+    }
+
+    // Only beetles whose orders have changed get a command, so nobody keeps
+    // starting over
+    pub fn decide(&mut self, observation: &Observation) -> BotCommands {
+
+        let mut commands = RepeatedField::new();
+
+        for beetle in observation.get_beetles() {
+            let distance = |x: f32, y: f32| {
+                (x - beetle.get_x()).powi(2) + (y - beetle.get_y()).powi(2)
+            };
+
+            let closest_enemy = observation.get_enemies().iter()
+                .min_by(|a, b| {
+                    distance(a.get_x(), a.get_y())
+                        .partial_cmp(&distance(b.get_x(), b.get_y()))
+                        .unwrap()
+                });
+
+            let order = match closest_enemy {
+                Some(enemy) => Order::Attack(enemy.get_id()),
+                None => Order::Forage,
+            };
+
+            if self.orders.get(&beetle.get_id()) == Some(&order) {
+                continue;
+            }
+            self.orders.insert(beetle.get_id(), order);
+
+            let mut command = BotCommand::new();
+            command.set_beetle_id(beetle.get_id());
+            match order {
+                Order::Forage => command.set_kind(BotCommandType::FORAGE),
+                Order::Attack(enemy_id) => {
+                    command.set_kind(BotCommandType::INTERACT);
+                    command.set_target_id(enemy_id);
+                },
+            }
+            commands.push(command);
+        }
+
+        let mut bot_commands = BotCommands::new();
+        bot_commands.set_tick(observation.get_tick());
+        bot_commands.set_commands(commands);
+        bot_commands
+    }
+}
+
+fn encode(message: BotMessage) -> Option<OwnedMessage> {
+    match message.write_to_bytes() {
+        Ok(encoded_message) => Some(OwnedMessage::Binary(encoded_message)),
+        Err(e) => {
+            println!("encode error: {}", e);
+            None
+        },
+    }
+}
+
+// Connects a stub bot to the bot server at address in the background and
+// has it claim team_name
+pub fn spawn(address: &str, team_name: &str) {
+
+    let url = format!("ws://{}", address);
+    let team_name = team_name.to_string();
+
+    thread::spawn(move || {
+        let client = ClientBuilder::new(&url)
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|builder| {
+                builder.add_protocol("battle-beetles-bot")
+                    .connect_insecure()
+                    .map_err(|e| format!("{:?}", e))
+            });
+
+        let mut client = match client {
+            Ok(client) => client,
+            Err(e) => {
+                println!("Stub bot couldn't connect to {}: {}", url, e);
+                return;
+            },
+        };
+
+        // the server only waits a few milliseconds for each reply
+        if let Err(e) = client.set_nodelay(true) {
+            println!("Stub bot {}: {:?}", team_name, e);
+        }
+
+        let mut claim_team = ClaimTeam::new();
+        claim_team.set_name(team_name.clone());
+        let mut message = BotMessage::new();
+        message.set_claim_team(claim_team);
+
+        if let Some(message) = encode(message) {
+            if client.send_message(&message).is_err() {
+                return;
+            }
+        }
+
+        let mut bot = StubBot::new();
+
+        loop {
+            let message = match client.recv_message() {
+                Ok(OwnedMessage::Binary(message)) => message,
+                Ok(OwnedMessage::Close(_)) | Err(_) => {
+                    println!("Stub bot {} lost its connection", team_name);
+                    return;
+                },
+                Ok(_) => continue,
+            };
+
+            let server_message = match parse_from_bytes::<ServerMessage>(&message) {
+                Ok(server_message) => server_message,
+                Err(_) => continue,
+            };
+
+            if server_message.has_error() {
+                println!("Stub bot {}: {}", team_name, server_message.get_error().get_message());
+            }
+            else if server_message.has_observation() {
+                let mut reply = BotMessage::new();
+                reply.set_commands(bot.decide(server_message.get_observation()));
+
+                if let Some(reply) = encode(reply) {
+                    if client.send_message(&reply).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+    use serde_json;
+
+    use bot_server::BotServer;
+    use game::{Game, Command};
+    use scenario::Scenario;
+    use super::spawn;
+
+    // The stub's beetles start out next to food with no enemies around, so
+    // once it's in charge they should all be foraging
+    const SCENARIO: &str = r#"{
+        "food_sources": [{ "x": 150, "y": 100, "amount": 1000000 }],
+        "teams": [
+            {
+                "name": "Stub",
+                "home_bases": [{ "x": 100, "y": 150 }],
+                "populations": [{ "random": 4, "x": 100, "y": 100, "spread": 20 }]
+            }
+        ]
+    }"#;
+
+    // somewhere on localhost nobody else is listening
+    fn free_address() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    fn all_foraging(game: &Game, team_id: i32) -> bool {
+        game.field_state.beetles.values()
+            .filter(|beetle| beetle.team_id == team_id)
+            .all(|beetle| beetle.current_command == Command::Forage)
+    }
+
+    #[test]
+    fn stub_bot_commands_its_team() {
+        let address = free_address();
+        let mut bot_server = BotServer::new(&address);
+
+        let scenario: Scenario = serde_json::from_str(SCENARIO).unwrap();
+        let mut game = scenario.build().unwrap();
+        let team_id = game.field_state.teams.find_by_name("Stub").unwrap().id;
+
+        assert!(game.field_state.beetles.len() > 0);
+        assert!(!all_foraging(&game, team_id));
+
+        spawn(&address, "Stub");
+
+        // connecting and claiming the team takes a few ticks
+        for _ in 0..200 {
+            bot_server.update(&mut game);
+            game.tick();
+
+            if all_foraging(&game, team_id) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(all_foraging(&game, team_id));
+    }
+}
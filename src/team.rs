@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+
+use entities::Id;
+use utils::Color;
+
+// whoever's playing through the browser. Beetles and home bases start out
+// on this team.
+pub const PLAYER_TEAM_ID: Id = 0;
+// score for every enemy a team's beetles kill. Delivered food scores one
+// point per unit stored.
+pub const KILL_SCORE: i32 = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Relation {
+    // share home bases and never fight
+    Allied,
+    // leave each other alone
+    Neutral,
+    Hostile,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Team {
+    pub id: Id,
+    pub name: String,
+    pub color: Color,
+    pub home_base_ids: Vec<Id>,
+    pub score: i32,
+    // teams that aren't in here are hostile
+    relations: BTreeMap<Id, Relation>,
+}
+
+impl Team {
+    pub fn new(id: Id, name: &str, color: Color) -> Team {
+        Team {
+            id,
+            name: name.to_string(),
+            color,
+            home_base_ids: Vec::new(),
+            score: 0,
+            relations: BTreeMap::new(),
+        }
+    }
+
+    pub fn relations(&self) -> &BTreeMap<Id, Relation> {
+        &self.relations
+    }
+}
+
+// Every team in a game. Beetles can have a team_id that was never
+// registered, in which case their team has no name or score and is hostile
+// to everyone else.
+#[derive(Serialize, Debug, Clone)]
+pub struct Teams {
+    teams: BTreeMap<Id, Team>,
+}
+
+impl Teams {
+    pub fn new() -> Teams {
+        Teams {
+            teams: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, team: Team) {
+        self.teams.insert(team.id, team);
+    }
+
+    pub fn get(&self, id: Id) -> Option<&Team> {
+        self.teams.get(&id)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<&Team> {
+        self.teams.values().find(|team| team.name == name)
+    }
+
+    pub fn iter<'a>(&'a self) -> ::std::collections::btree_map::Values<'a, Id, Team> {
+        self.teams.values()
+    }
+
+    pub fn relation(&self, team_id: Id, other_team_id: Id) -> Relation {
+        if team_id == other_team_id {
+            return Relation::Allied;
+        }
+
+        self.teams.get(&team_id)
+            .and_then(|team| team.relations.get(&other_team_id))
+            .cloned()
+            .unwrap_or(Relation::Hostile)
+    }
+
+    pub fn are_hostile(&self, team_id: Id, other_team_id: Id) -> bool {
+        self.relation(team_id, other_team_id) == Relation::Hostile
+    }
+
+    pub fn are_allied(&self, team_id: Id, other_team_id: Id) -> bool {
+        self.relation(team_id, other_team_id) == Relation::Allied
+    }
+
+    // Relations always go both ways
+    pub fn set_relation(&mut self, team_id: Id, other_team_id: Id, relation: Relation) {
+        if team_id == other_team_id {
+            return;
+        }
+
+        if let Some(team) = self.teams.get_mut(&team_id) {
+            team.relations.insert(other_team_id, relation);
+        }
+        if let Some(other_team) = self.teams.get_mut(&other_team_id) {
+            other_team.relations.insert(team_id, relation);
+        }
+    }
+
+    pub fn add_score(&mut self, team_id: Id, score: i32) {
+        if let Some(team) = self.teams.get_mut(&team_id) {
+            team.score += score;
+        }
+    }
+
+    // Moves a home base from whoever owned it to team_id
+    pub fn claim_home_base(&mut self, team_id: Id, home_base_id: Id) {
+        for team in self.teams.values_mut() {
+            team.home_base_ids.retain(|id| *id != home_base_id);
+        }
+
+        if let Some(team) = self.teams.get_mut(&team_id) {
+            team.home_base_ids.push(home_base_id);
+        }
+    }
+}
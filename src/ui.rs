@@ -6,14 +6,26 @@ use websocket::{OwnedMessage};
 use websocket::sync::Server;
 use gen::messages::{
     UiMessage, UiUpdate, UiBeetle, UiGameState, UiChartsIncremental, Color,
-    UiFoodSource, UiHomeBase
+    UiFoodSource, UiHomeBase, UiHeatmap, UiHeatmapCell, UiWorld, UiWall,
+    UiPoint, Boundary as UiBoundary, UiDebugPaths, UiBeetlePath, UiCommand,
+    CommandType, UiStatusEffect, StatusEffectType, UiEvents, UiEvent, BeetleDied,
+    UiProductionOrder, GenomeSource as UiGenomeSource, UiTeam, UiTeamRelation,
+    Relation as UiRelation, MatchEnded, UiTeamResult, FightEnded, UiFightTeam
 };
 use protobuf::{parse_from_bytes, RepeatedField, Message};
 
-use entities::{Entity, Beetles};
-use utils::Positioned;
+use cgmath::Point2;
+use entities::{Id, Entity, Beetle, Beetles};
+use utils::{Positioned, Heatmap};
 use game;
+use game::{Command, GameEvent};
+use world::Boundary;
 use beetle_genome::BeetleGeneIndex as Gene;
+use status_effect::StatusEffectKind;
+use colony::GenomeSource;
+use simulation::fight_simulation::FightResult;
+use team::Relation;
+use utils::{SIMULATION_PERIOD_MS, MS_PER_SECOND};
 //use FieldState;
 
 pub struct UI {
@@ -125,13 +137,40 @@ impl UI {
             new_beetle.set_body_length(beetle.body_length());
             new_beetle.set_food_carrying(beetle.food_carrying);
 
-            let mut color = Color::new();
-            color.set_r(beetle.color.r as i32);
-            color.set_g(beetle.color.g as i32);
-            color.set_b(beetle.color.b as i32);
-            color.set_a(beetle.color.a as i32);
+            let mut commands = RepeatedField::new();
+            if beetle.current_command != Command::Stop {
+                commands.push(ui_command(data, beetle, &beetle.current_command));
+            }
+            for command in beetle.command_queue.iter() {
+                commands.push(ui_command(data, beetle, command));
+            }
+            new_beetle.set_commands(commands);
+
+            let mut status_effects = RepeatedField::new();
+            for effect in beetle.status_effects.iter() {
+                let mut new_effect = UiStatusEffect::new();
+
+                new_effect.set_kind(match effect.kind {
+                    StatusEffectKind::Poisoned => StatusEffectType::POISONED,
+                    StatusEffectKind::Stunned => StatusEffectType::STUNNED,
+                    StatusEffectKind::Slowed => StatusEffectType::SLOWED,
+                    StatusEffectKind::Enraged => StatusEffectType::ENRAGED,
+                    StatusEffectKind::CarryingOverload => {
+                        StatusEffectType::CARRYING_OVERLOAD
+                    },
+                    StatusEffectKind::Starving => StatusEffectType::STARVING,
+                });
+                if let Some(ticks_left) = effect.ticks_left {
+                    new_effect.set_seconds_left(
+                        (ticks_left as u64 * SIMULATION_PERIOD_MS) as f32 / MS_PER_SECOND);
+                }
+                new_effect.set_stacks(effect.stacks as i32);
+
+                status_effects.push(new_effect);
+            }
+            new_beetle.set_status_effects(status_effects);
 
-            new_beetle.set_color(color);
+            new_beetle.set_color(ui_color(beetle.color));
 
             beetles.push(new_beetle);
         }
@@ -143,6 +182,8 @@ impl UI {
 
             new_food_source.set_id(food_source.get_id());
             new_food_source.set_amount(food_source.amount());
+            new_food_source.set_food_yield(food_source.food_type().food_yield);
+            new_food_source.set_carry_cost(food_source.food_type().carry_cost);
             new_food_source.set_x(food_source.get_position().x);
             new_food_source.set_y(food_source.get_position().y);
 
@@ -158,11 +199,79 @@ impl UI {
             new_home_base.set_x(home_base.get_position().x);
             new_home_base.set_y(home_base.get_position().y);
             new_home_base.set_food_stored_amount(home_base.get_food_stored_amount());
+            new_home_base.set_team_id(home_base.get_team_id());
+            new_home_base.set_hatch_progress(home_base.production.progress());
+
+            let mut production = RepeatedField::new();
+            for order in home_base.production.queue.iter() {
+                let mut ui_order = UiProductionOrder::new();
+                ui_order.set_genome_source(match order.genome_source {
+                    GenomeSource::BreedingPool => UiGenomeSource::BREEDING_POOL,
+                    GenomeSource::Crossover => UiGenomeSource::CROSSOVER,
+                });
+                ui_order.set_remaining(order.remaining.unwrap_or(0) as i32);
+                production.push(ui_order);
+            }
+            new_home_base.set_production(production);
 
             home_bases.push(new_home_base);
         }
         ui_game_state.set_home_bases(home_bases);
 
+        let mut teams = RepeatedField::new();
+        for team in data.teams.iter() {
+            let mut new_team = UiTeam::new();
+
+            new_team.set_id(team.id);
+            new_team.set_name(team.name.clone());
+            new_team.set_color(ui_color(team.color));
+            new_team.set_score(team.score);
+            new_team.set_home_base_ids(team.home_base_ids.clone());
+
+            let mut relations = RepeatedField::new();
+            for (team_id, relation) in team.relations().iter() {
+                let mut new_relation = UiTeamRelation::new();
+                new_relation.set_team_id(*team_id);
+                new_relation.set_relation(match *relation {
+                    Relation::Hostile => UiRelation::HOSTILE,
+                    Relation::Neutral => UiRelation::NEUTRAL,
+                    Relation::Allied => UiRelation::ALLIED,
+                });
+                relations.push(new_relation);
+            }
+            new_team.set_relations(relations);
+
+            teams.push(new_team);
+        }
+        ui_game_state.set_teams(teams);
+
+        let mut world = UiWorld::new();
+        world.set_width(data.world.width);
+        world.set_height(data.world.height);
+        world.set_boundary(match data.world.boundary {
+            Boundary::Clamp => UiBoundary::CLAMP,
+            Boundary::Bounce => UiBoundary::BOUNCE,
+            Boundary::Wrap => UiBoundary::WRAP,
+        });
+
+        let mut walls = RepeatedField::new();
+        for wall in data.world.walls.iter() {
+            let mut new_wall = UiWall::new();
+
+            let mut points = RepeatedField::new();
+            for point in wall.points.iter() {
+                let mut new_point = UiPoint::new();
+                new_point.set_x(point.x);
+                new_point.set_y(point.y);
+                points.push(new_point);
+            }
+            new_wall.set_points(points);
+
+            walls.push(new_wall);
+        }
+        world.set_walls(walls);
+        ui_game_state.set_world(world);
+
         ui_update.set_game_state(ui_game_state);
 
         match ui_update.write_to_bytes() {
@@ -176,6 +285,196 @@ impl UI {
                 println!("encode error: {}", e);
             }
         }
+
+        if data.debug_paths {
+            self.update_debug_paths(data);
+        }
+
+        if data.show_pheromones {
+            self.update_pheromones(data);
+        }
+
+        if data.events.len() > 0 {
+            self.update_events(data);
+        }
+
+        if data.game_match.just_ended(data) {
+            self.match_ended(data);
+        }
+    }
+
+    fn match_ended(&self, data: &game::FieldState) {
+
+        let result = match data.game_match.result() {
+            Some(result) => result,
+            None => return,
+        };
+
+        let mut teams = RepeatedField::new();
+
+        for team_result in result.teams.iter() {
+            let mut new_team = UiTeamResult::new();
+            new_team.set_team_id(team_result.team_id);
+            new_team.set_name(data.teams.get(team_result.team_id)
+                .map(|team| team.name.clone())
+                .unwrap_or(format!("Team {}", team_result.team_id)));
+            new_team.set_won(team_result.won);
+            new_team.set_beetles_alive(team_result.beetles_alive as i32);
+            new_team.set_food_stored(team_result.food_stored);
+            new_team.set_home_bases(team_result.home_bases as i32);
+            new_team.set_score(team_result.score);
+            teams.push(new_team);
+        }
+
+        let mut message = MatchEnded::new();
+        message.set_condition(format!("{:?}", result.condition));
+        message.set_ticks(result.ticks);
+        message.set_teams(teams);
+
+        let mut ui_update = UiUpdate::new();
+        ui_update.set_match_ended(message);
+
+        match ui_update.write_to_bytes() {
+            Ok(encoded_message) => {
+                self.tx_sender.send(OwnedMessage::Binary(encoded_message)).unwrap();
+            },
+            Err(e) => {
+                println!("encode error: {}", e);
+            }
+        }
+    }
+
+    pub fn fight_ended(&self, result: &FightResult, data: &game::FieldState) {
+
+        let mut teams = RepeatedField::new();
+
+        for (&team_id, &survivors) in result.survivors.iter() {
+            let damage_dealt: i32 = result.damage.get(&team_id)
+                .map(|victims| victims.iter()
+                    .filter(|&(&victim_id, _)| victim_id != team_id)
+                    .map(|(_, &damage)| damage).sum())
+                .unwrap_or(0);
+            let damage_taken: i32 = result.damage.iter()
+                .filter(|&(&attacker_id, _)| attacker_id != team_id)
+                .filter_map(|(_, victims)| victims.get(&team_id))
+                .sum();
+
+            let mut new_team = UiFightTeam::new();
+            new_team.set_team_id(team_id);
+            new_team.set_name(data.teams.get(team_id)
+                .map(|team| team.name.clone())
+                .unwrap_or(format!("Team {}", team_id)));
+            new_team.set_won(result.winners.contains(&team_id));
+            new_team.set_survivors(survivors as i32);
+            new_team.set_damage_dealt(damage_dealt);
+            new_team.set_damage_taken(damage_taken);
+            teams.push(new_team);
+        }
+
+        let mut message = FightEnded::new();
+        message.set_end_reason(format!("{:?}", result.end_reason));
+        message.set_ticks(result.ticks);
+        message.set_teams(teams);
+
+        let mut ui_update = UiUpdate::new();
+        ui_update.set_fight_ended(message);
+
+        match ui_update.write_to_bytes() {
+            Ok(encoded_message) => {
+                self.tx_sender.send(OwnedMessage::Binary(encoded_message)).unwrap();
+            },
+            Err(e) => {
+                println!("encode error: {}", e);
+            }
+        }
+    }
+
+    fn update_events(&self, data: &game::FieldState) {
+
+        let mut events = RepeatedField::new();
+
+        for event in data.events.iter() {
+            let mut new_event = UiEvent::new();
+
+            match *event {
+                GameEvent::BeetleDied{ id, killer_id, position, tick } => {
+                    let mut beetle_died = BeetleDied::new();
+                    beetle_died.set_id(id);
+                    beetle_died.set_killer_id(killer_id.unwrap_or(0));
+                    beetle_died.set_tick(tick);
+
+                    let mut point = UiPoint::new();
+                    point.set_x(position.x);
+                    point.set_y(position.y);
+                    beetle_died.set_position(point);
+
+                    new_event.set_beetle_died(beetle_died);
+                },
+                // too many of these to be worth sending
+                GameEvent::BeetleDamaged{ .. } => continue,
+            }
+
+            events.push(new_event);
+        }
+
+        if events.len() == 0 {
+            return;
+        }
+
+        let mut message = UiEvents::new();
+        message.set_events(events);
+
+        let mut ui_update = UiUpdate::new();
+        ui_update.set_events(message);
+
+        match ui_update.write_to_bytes() {
+            Ok(encoded_message) => {
+                self.tx_sender.send(OwnedMessage::Binary(encoded_message)).unwrap();
+            },
+            Err(e) => {
+                println!("encode error: {}", e);
+            }
+        }
+    }
+
+    fn update_debug_paths(&self, data: &game::FieldState) {
+
+        let mut paths = RepeatedField::new();
+
+        for beetle in data.beetles.values() {
+            if beetle.path.len() == 0 {
+                continue;
+            }
+
+            let mut path = UiBeetlePath::new();
+            path.set_beetle_id(beetle.id);
+
+            let mut points = RepeatedField::new();
+            for point in ::std::iter::once(&beetle.position).chain(beetle.path.iter()) {
+                let mut new_point = UiPoint::new();
+                new_point.set_x(point.x);
+                new_point.set_y(point.y);
+                points.push(new_point);
+            }
+            path.set_points(points);
+
+            paths.push(path);
+        }
+
+        let mut message = UiDebugPaths::new();
+        message.set_paths(paths);
+
+        let mut ui_update = UiUpdate::new();
+        ui_update.set_debug_paths(message);
+
+        match ui_update.write_to_bytes() {
+            Ok(encoded_message) => {
+                self.tx_sender.send(OwnedMessage::Binary(encoded_message)).unwrap();
+            },
+            Err(e) => {
+                println!("encode error: {}", e);
+            }
+        }
     }
 
     pub fn update_charts_incremental(&self, beetles: &Beetles) {
@@ -197,6 +496,7 @@ impl UI {
         let mut mandible_sharpness_sum = 0.0;
         let mut body_width_sum = 0.0;
         let mut body_length_sum = 0.0;
+        let mut smell_sensitivity_sum = 0.0;
 
         for beetle in beetles.values() {
 
@@ -220,6 +520,8 @@ impl UI {
                 beetle.genome.get_gene(Gene::BodyWidth);
             body_length_sum += 
                 beetle.genome.get_gene(Gene::BodyLength);
+            smell_sensitivity_sum +=
+                beetle.genome.get_gene(Gene::SmellSensitivity);
         }
 
         let mut message = UiChartsIncremental::new();
@@ -237,6 +539,7 @@ impl UI {
         message.set_avg_mandible_sharpness(mandible_sharpness_sum / len);
         message.set_avg_body_width(body_width_sum / len);
         message.set_avg_body_length(body_length_sum / len);
+        message.set_avg_smell_sensitivity(smell_sensitivity_sum / len);
 
 
         let mut ui_update = UiUpdate::new();
@@ -252,6 +555,36 @@ impl UI {
         }
     }
 
+    fn update_pheromones(&self, data: &game::FieldState) {
+
+        let mut ui_update = UiUpdate::new();
+        ui_update.set_pheromones(ui_heatmap(&data.pheromones.to_heatmap()));
+
+        match ui_update.write_to_bytes() {
+            Ok(encoded_message) => {
+                self.tx_sender.send(OwnedMessage::Binary(encoded_message)).unwrap();
+            },
+            Err(e) => {
+                println!("encode error: {}", e);
+            }
+        }
+    }
+
+    pub fn update_heatmap(&self, heatmap: &Heatmap) {
+
+        let mut ui_update = UiUpdate::new();
+        ui_update.set_heatmap(ui_heatmap(heatmap));
+
+        match ui_update.write_to_bytes() {
+            Ok(encoded_message) => {
+                self.tx_sender.send(OwnedMessage::Binary(encoded_message)).unwrap();
+            },
+            Err(e) => {
+                println!("encode error: {}", e);
+            }
+        }
+    }
+
     pub fn shutdown(&self) {
         self.tx_sender.send(OwnedMessage::Close(None)).unwrap();
     }
@@ -266,3 +599,129 @@ impl UI {
         return messages;
     }
 }
+
+fn ui_heatmap(heatmap: &Heatmap) -> UiHeatmap {
+
+    let mut message = UiHeatmap::new();
+
+    message.set_name(heatmap.name.clone());
+    message.set_x_label(heatmap.x_label.clone());
+    message.set_y_label(heatmap.y_label.clone());
+    message.set_width(heatmap.width as i32);
+    message.set_height(heatmap.height as i32);
+
+    let mut cells = RepeatedField::new();
+    for cell in heatmap.cells.iter() {
+        let mut new_cell = UiHeatmapCell::new();
+
+        new_cell.set_x(cell.x as i32);
+        new_cell.set_y(cell.y as i32);
+        new_cell.set_value(cell.value);
+
+        cells.push(new_cell);
+    }
+    message.set_cells(cells);
+
+    message
+}
+
+fn ui_command(data: &game::FieldState, beetle: &Beetle, command: &Command) -> UiCommand {
+
+    let mut ui_command = UiCommand::new();
+
+    let position = match *command {
+        Command::Move{ position } => {
+            ui_command.set_kind(CommandType::MOVE);
+            Some(position)
+        },
+        Command::GroupMove{ position, .. } => {
+            ui_command.set_kind(CommandType::GROUP_MOVE);
+            Some(position)
+        },
+        Command::HoldFormation{ group_id } => {
+            ui_command.set_kind(CommandType::HOLD_FORMATION);
+            data.formation_groups.get(&group_id)
+                .and_then(|group| group.slot_position(beetle.id, &data.nav_grid))
+        },
+        Command::Interact{ target_id } => {
+            ui_command.set_kind(CommandType::INTERACT);
+            ui_command.set_target_id(target_id);
+            target_position(data, target_id)
+        },
+        Command::AttackMove{ position } => {
+            ui_command.set_kind(CommandType::ATTACK_MOVE);
+            Some(position)
+        },
+        Command::Patrol{ to, .. } => {
+            ui_command.set_kind(CommandType::PATROL);
+            Some(to)
+        },
+        Command::Guard{ target_id } => {
+            ui_command.set_kind(CommandType::GUARD);
+            ui_command.set_target_id(target_id);
+            target_position(data, target_id)
+        },
+        Command::Follow{ target_id } => {
+            ui_command.set_kind(CommandType::FOLLOW);
+            ui_command.set_target_id(target_id);
+            target_position(data, target_id)
+        },
+        Command::HoldPosition => {
+            ui_command.set_kind(CommandType::HOLD_POSITION);
+            None
+        },
+        Command::Wander => {
+            ui_command.set_kind(CommandType::WANDER);
+            None
+        },
+        Command::HarvestClosestFood => {
+            ui_command.set_kind(CommandType::HARVEST_CLOSEST_FOOD);
+            None
+        },
+        Command::Forage => {
+            ui_command.set_kind(CommandType::FORAGE);
+            None
+        },
+        Command::ReturnToBase => {
+            ui_command.set_kind(CommandType::RETURN_TO_BASE);
+            None
+        },
+        Command::Stop => {
+            ui_command.set_kind(CommandType::STOP);
+            None
+        },
+    };
+
+    if let Some(position) = position {
+        let mut point = UiPoint::new();
+        point.set_x(position.x);
+        point.set_y(position.y);
+        ui_command.set_position(point);
+    }
+
+    ui_command
+}
+
+fn target_position(data: &game::FieldState, target_id: Id) -> Option<Point2<f32>> {
+    if let Some(target) = data.beetles.get(&target_id) {
+        Some(target.position)
+    }
+    else if let Some(food_source) = data.get_food_sources().get(&target_id) {
+        Some(food_source.get_position())
+    }
+    else if let Some(home_base) = data.get_home_bases().get(&target_id) {
+        Some(home_base.get_position())
+    }
+    else {
+        None
+    }
+}
+
+fn ui_color(color: ::utils::Color) -> Color {
+    let mut ui_color = Color::new();
+    ui_color.set_r(color.r as i32);
+    ui_color.set_g(color.g as i32);
+    ui_color.set_b(color.b as i32);
+    ui_color.set_a(color.a as i32);
+    ui_color
+}
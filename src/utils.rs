@@ -5,8 +5,6 @@ pub const MAX_SPEED_UNITS_PER_SECOND: f32 = 200.0;
 pub const MIN_SPEED_UNITS_PER_SECOND: f32 = 10.0;
 pub const ROTATION_RADIANS_PER_SECOND: f32 = 3.14159;
 pub const MS_PER_SECOND: f32 = 1000.0;
-//pub const POPULATION_SIZE: i32 = 20;
-pub const POPULATION_SIZE: i32 = 128;
 
 pub trait Positioned {
     fn get_position(&self) -> Point2<f32>;
@@ -17,7 +15,11 @@ pub fn convert_value_for_sim_period(value: f32) -> f32 {
     return value * ((SIMULATION_PERIOD_MS as f32) / MS_PER_SECOND);
 }
 
-#[derive(Serialize, Debug, Clone, Copy)]
+pub fn seconds_to_ticks(seconds: f32) -> u32 {
+    ((seconds * MS_PER_SECOND) / (SIMULATION_PERIOD_MS as f32)).round() as u32
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -31,6 +33,25 @@ impl Color {
     }
 }
 
+// Grid of values to be rendered as a heatmap by the UI. Empty cells are
+// simply left out.
+#[derive(Serialize, Debug, Clone)]
+pub struct Heatmap {
+    pub name: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<HeatmapCell>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HeatmapCell {
+    pub x: usize,
+    pub y: usize,
+    pub value: f32,
+}
+
 
 
 //pub fn mean(values: &Vec<f32>) -> f32 {
@@ -0,0 +1,182 @@
+use cgmath::{Point2, Vector2, InnerSpace};
+
+use entities::Beetle;
+
+pub const DEFAULT_WORLD_WIDTH: f32 = 1024.0;
+pub const DEFAULT_WORLD_HEIGHT: f32 = 768.0;
+
+// What happens to a beetle that reaches the edge of the map
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Boundary {
+    Clamp,
+    Bounce,
+    Wrap,
+}
+
+// Static obstacle. The points describe a closed polygon; the last point
+// connects back to the first.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Wall {
+    pub points: Vec<Point2<f32>>,
+}
+
+impl Wall {
+    pub fn edges(&self) -> Vec<(Point2<f32>, Point2<f32>)> {
+        let mut edges = Vec::with_capacity(self.points.len());
+
+        for i in 0..self.points.len() {
+            let next = (i + 1) % self.points.len();
+            edges.push((self.points[i], self.points[next]));
+        }
+
+        edges
+    }
+
+    pub fn contains(&self, point: Point2<f32>) -> bool {
+
+        // even-odd rule
+        let mut inside = false;
+
+        for (a, b) in self.edges() {
+            if (a.y > point.y) != (b.y > point.y) {
+                let x_crossing = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                if point.x < x_crossing {
+                    inside = !inside;
+                }
+            }
+        }
+
+        inside
+    }
+
+    pub fn closest_point(&self, point: Point2<f32>) -> Option<Point2<f32>> {
+
+        let mut closest = None;
+        let mut closest_distance = ::std::f32::MAX;
+
+        for (a, b) in self.edges() {
+            let candidate = closest_point_on_segment(point, a, b);
+            let distance = (candidate - point).magnitude();
+
+            if distance < closest_distance {
+                closest_distance = distance;
+                closest = Some(candidate);
+            }
+        }
+
+        closest
+    }
+}
+
+pub fn closest_point_on_segment(
+        point: Point2<f32>, a: Point2<f32>, b: Point2<f32>) -> Point2<f32> {
+
+    let segment = b - a;
+    let length_squared = segment.magnitude2();
+
+    if length_squared == 0.0 {
+        return a;
+    }
+
+    let t = ((point - a).dot(segment) / length_squared).max(0.0).min(1.0);
+
+    a + segment * t
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct World {
+    pub width: f32,
+    pub height: f32,
+    pub boundary: Boundary,
+    pub walls: Vec<Wall>,
+}
+
+impl World {
+    pub fn new(width: f32, height: f32) -> World {
+        World {
+            width,
+            height,
+            boundary: Boundary::Clamp,
+            walls: Vec::new(),
+        }
+    }
+
+    // Keeps a beetle on the map and out of the walls. Called after all the
+    // movement for a tick has happened.
+    pub fn constrain_beetle(&self, beetle: &mut Beetle) {
+
+        let radius = beetle.collision_radius();
+
+        for wall in self.walls.iter() {
+            self.push_out_of_wall(beetle, wall, radius);
+        }
+
+        match self.boundary {
+            Boundary::Clamp => {
+                beetle.position.x = clamp(beetle.position.x, radius, self.width - radius);
+                beetle.position.y = clamp(beetle.position.y, radius, self.height - radius);
+            },
+            Boundary::Bounce => {
+                if beetle.position.x < radius {
+                    beetle.direction.x = beetle.direction.x.abs();
+                }
+                else if beetle.position.x > self.width - radius {
+                    beetle.direction.x = -beetle.direction.x.abs();
+                }
+
+                if beetle.position.y < radius {
+                    beetle.direction.y = beetle.direction.y.abs();
+                }
+                else if beetle.position.y > self.height - radius {
+                    beetle.direction.y = -beetle.direction.y.abs();
+                }
+
+                beetle.position.x = clamp(beetle.position.x, radius, self.width - radius);
+                beetle.position.y = clamp(beetle.position.y, radius, self.height - radius);
+                beetle.angle = Vector2::new(1.0, 0.0).angle(beetle.direction);
+            },
+            Boundary::Wrap => {
+                beetle.position.x = wrap(beetle.position.x, self.width);
+                beetle.position.y = wrap(beetle.position.y, self.height);
+            },
+        }
+    }
+
+    fn push_out_of_wall(&self, beetle: &mut Beetle, wall: &Wall, radius: f32) {
+
+        if let Some(closest) = wall.closest_point(beetle.position) {
+
+            let offset = beetle.position - closest;
+            let distance = offset.magnitude();
+
+            if wall.contains(beetle.position) {
+                // pop back out through the nearest edge
+                if distance > 0.0 {
+                    beetle.position = closest - (offset / distance) * radius;
+                }
+                else {
+                    beetle.position = closest;
+                }
+            }
+            else if distance < radius && distance > 0.0 {
+                beetle.position = closest + (offset / distance) * radius;
+            }
+        }
+    }
+}
+
+fn clamp(value: f32, low: f32, high: f32) -> f32 {
+    value.max(low).min(high)
+}
+
+fn wrap(value: f32, size: f32) -> f32 {
+    if value < 0.0 {
+        value + size
+    }
+    else if value >= size {
+        value - size
+    }
+    else {
+        value
+    }
+}
@@ -198,6 +198,64 @@ class LegendChart extends D3Chart {
   }
 }
 
+class HeatmapChart extends D3Chart {
+  constructor({
+    title,
+    domElementId,
+  }) {
+
+    super({ title, domElementId })
+
+    // background
+    this.svg.append('rect')
+        .attr('fill', '#ededed')
+        .attr('width', this.width)
+        .attr('height', this.height)
+
+    this.g = this.svg.append('g')
+        .attr('class', 'heatmap-chart')
+
+    this.xLabel = this.svg.append('text')
+        .attr('class', 'chart__axis-label')
+        .attr('x', this.centerX)
+        .attr('y', this.height - 4)
+        .attr('text-anchor', 'middle')
+
+    this.yLabel = this.svg.append('text')
+        .attr('class', 'chart__axis-label')
+        .attr('transform', translate(14, this.centerY) + ' rotate(-90)')
+        .attr('text-anchor', 'middle')
+  }
+
+  update({ width, height, cells, xLabel, yLabel }) {
+
+    const cellWidth = this.width / width
+    const cellHeight = this.height / height
+
+    const maxValue = d3.max(cells, (d) => d.value) || 1
+    const colorScale = d3.scaleSequential(d3.interpolateYlOrRd)
+      .domain([0, maxValue])
+
+    const cellUpdate = this.g.selectAll('.heatmap-chart__cell')
+      .data(cells)
+
+    cellUpdate.enter()
+      .append('rect')
+        .attr('class', 'heatmap-chart__cell')
+      .merge(cellUpdate)
+        .attr('x', (d) => d.x * cellWidth)
+        // higher values at the top
+        .attr('y', (d) => this.height - ((d.y + 1) * cellHeight))
+        .attr('width', cellWidth)
+        .attr('height', cellHeight)
+        .attr('fill', (d) => colorScale(d.value))
+
+    cellUpdate.exit().remove()
+
+    this.xLabel.text(xLabel)
+    this.yLabel.text(yLabel)
+  }
+}
 
 class TwoJsChart extends Chart {
   constructor({
@@ -492,6 +550,7 @@ module.exports = {
   ScatterPlot,
   BarChart,
   LegendChart,
+  HeatmapChart,
   COLORS,
   GRAPH_COLORS,
 };
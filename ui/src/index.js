@@ -5,16 +5,58 @@ import * as Charts from './charts';
 import * as d3 from 'd3';
 
 const canvas = document.getElementById('canvas');
+const scoreboard = document.getElementById('scoreboard');
+const matchResult = document.getElementById('match-result');
 const rightPanel = document.getElementById('right-panel');
 const stopButton = document.getElementById('stop-button');
 const addBeetleButton = document.getElementById('add-beetle-button');
 //const speedSimButton = document.getElementById('speed-sim-button');
 const battleSimButton = document.getElementById('battle-sim-button');
 const foodGAButton = document.getElementById('food-ga-button');
+const scarceFoodCheckbox = document.getElementById('scarce-food-checkbox');
 const fightSimButton = document.getElementById('fight-sim-button');
+const difficultySelect = document.getElementById('difficulty-select');
+const armySizeInput = document.getElementById('army-size-input');
+const evolveArmyCheckbox = document.getElementById('evolve-army-checkbox');
+const aiMatchButton = document.getElementById('ai-match-button');
+const scenarioSelect = document.getElementById('scenario-select');
+const loadScenarioButton = document.getElementById('load-scenario-button');
+const pauseMatchButton = document.getElementById('pause-match-button');
 const createFormationButton = document.getElementById('create-formation-button');
+const formationShapeSelect = document.getElementById('formation-shape-select');
+const formationFacingSelect = document.getElementById('formation-facing-select');
+const formationSpacingInput = document.getElementById('formation-spacing-input');
+const descriptorSelect = document.getElementById('descriptor-select');
+const mapElitesButton = document.getElementById('map-elites-button');
+const debugPathsCheckbox = document.getElementById('debug-paths-checkbox');
+const pheromonesCheckbox = document.getElementById('pheromones-checkbox');
+const forageButton = document.getElementById('forage-button');
+const criticalHitsCheckbox = document.getElementById('critical-hits-checkbox');
+const flankingCheckbox = document.getElementById('flanking-checkbox');
+const regenerationCheckbox = document.getElementById('regeneration-checkbox');
+const baseHealingCheckbox = document.getElementById('base-healing-checkbox');
+const metabolismCheckbox = document.getElementById('metabolism-checkbox');
+const genomeSourceSelect = document.getElementById('genome-source-select');
+const hatchCountInput = document.getElementById('hatch-count-input');
+const hatchButton = document.getElementById('hatch-button');
+const queueHatchButton = document.getElementById('queue-hatch-button');
+const stopHatchingButton = document.getElementById('stop-hatching-button');
+const queueHarvestButton = document.getElementById('queue-harvest-button');
+const orderSelect = document.getElementById('order-select');
+const holdPositionButton = document.getElementById('hold-position-button');
+const queueReturnButton = document.getElementById('queue-return-button');
 const DEGREES_PER_RADIAN = 57.2958;
 
+// shown above beetles with each status effect, ordered by StatusEffectType
+const STATUS_EFFECT_ICONS = {
+  [messages.StatusEffectType.POISONED]: { symbol: '\u2620', color: '#5fa83a' },
+  [messages.StatusEffectType.STUNNED]: { symbol: '\u2726', color: '#f2e14c' },
+  [messages.StatusEffectType.SLOWED]: { symbol: '\u2248', color: '#6ab0de' },
+  [messages.StatusEffectType.ENRAGED]: { symbol: '!', color: '#d93a2b' },
+  [messages.StatusEffectType.CARRYING_OVERLOAD]: { symbol: '\u25bc', color: '#efc85d' },
+  [messages.StatusEffectType.STARVING]: { symbol: '\u2205', color: '#b07a4f' },
+};
+
 const beetleDim = {
   width: 20,
   length: 20,
@@ -46,10 +88,30 @@ const canvasRect = canvas.getBoundingClientRect();
 
 renderBackground();
 
+gameContainer.append('g')
+    .attr('class', 'world')
+
+gameContainer.append('g')
+    .attr('class', 'pheromones')
+
+gameContainer.append('g')
+    .attr('class', 'waypoints')
+
 gameContainer.append('g')
     .attr('class', 'beetles')
 
+gameContainer.append('g')
+    .attr('class', 'debug-paths')
+
+gameContainer.append('g')
+    .attr('class', 'deaths')
+
+// pheromone cells are sized to cover this
+let worldSize = { width: 0, height: 0 };
+
 let shiftKeyDown = false;
+// left clicking a base picks which one the hatch buttons apply to
+let selectedBaseId = null;
 window.onkeyup = function(e) {
   shiftKeyDown = false;
 };
@@ -88,6 +150,7 @@ const varNames = [
   "Avg Mandible Sharpness",
   "Avg Body Width",
   "Avg Body Length",
+  "Avg Smell Sensitivity",
 ]
 
 const genotypeChart = new Charts.ScatterPlot({
@@ -117,6 +180,11 @@ const legendChart = new Charts.LegendChart({
   variableNames: varNames,
 });
 
+const archiveChart = new Charts.HeatmapChart({
+  title: "Archive",
+  domElementId: 'archive-heatmap-chart',
+});
+
 //phenotypeChart.reset();
 genotypeChart.reset();
 
@@ -146,6 +214,24 @@ socket.onmessage = (event) => {
   else if (uiUpdate.hasChartsIncremental()) {
     handleChartsIncremental(uiUpdate.getChartsIncremental());
   }
+  else if (uiUpdate.hasHeatmap()) {
+    handleHeatmap(uiUpdate.getHeatmap());
+  }
+  else if (uiUpdate.hasDebugPaths()) {
+    renderDebugPaths(uiUpdate.getDebugPaths().getPathsList());
+  }
+  else if (uiUpdate.hasPheromones()) {
+    renderPheromones(uiUpdate.getPheromones());
+  }
+  else if (uiUpdate.hasEvents()) {
+    handleEvents(uiUpdate.getEvents().getEventsList());
+  }
+  else if (uiUpdate.hasMatchEnded()) {
+    renderMatchResult(uiUpdate.getMatchEnded());
+  }
+  else if (uiUpdate.hasFightEnded()) {
+    renderFightResult(uiUpdate.getFightEnded());
+  }
 }
 
 socket.onopen = (event) => {
@@ -180,7 +266,7 @@ battleSimButton.onclick = (e) => {
 foodGAButton.onclick = (e) => {
   //phenotypeChart.reset();
   genotypeChart.reset();
-  messageService.runFoodGA();
+  messageService.runFoodGA({ scarceFood: scarceFoodCheckbox.checked });
 }
 
 fightSimButton.onclick = (e) => {
@@ -189,8 +275,163 @@ fightSimButton.onclick = (e) => {
   messageService.runFightSimulation();
 }
 
+aiMatchButton.onclick = (e) => {
+  genotypeChart.reset();
+  messageService.startAiMatch({
+    difficulty: Number(difficultySelect.value),
+    armySize: Number(armySizeInput.value),
+    evolve: evolveArmyCheckbox.checked,
+  });
+}
+
+loadScenarioButton.onclick = (e) => {
+  genotypeChart.reset();
+  matchResult.textContent = '';
+  matchPaused = false;
+  pauseMatchButton.value = 'Pause Match';
+  messageService.loadScenario({ name: scenarioSelect.value });
+}
+
+let matchPaused = false;
+
+pauseMatchButton.onclick = (e) => {
+  matchPaused = !matchPaused;
+  pauseMatchButton.value = matchPaused ? 'Resume Match' : 'Pause Match';
+  messageService.setMatchPaused({ paused: matchPaused });
+}
+
+// y increases downward. A zero vector lets the server pick.
+const FORMATION_FACINGS = {
+  auto: { x: 0, y: 0 },
+  up: { x: 0, y: -1 },
+  right: { x: 1, y: 0 },
+  down: { x: 0, y: 1 },
+  left: { x: -1, y: 0 },
+};
+
 createFormationButton.onclick = (e) => {
-  messageService.createFormation();
+  const facing = FORMATION_FACINGS[formationFacingSelect.value];
+
+  messageService.createFormation({
+    shape: parseInt(formationShapeSelect.value),
+    facingX: facing.x,
+    facingY: facing.y,
+    spacing: parseFloat(formationSpacingInput.value) || 0,
+  });
+}
+
+debugPathsCheckbox.onchange = (e) => {
+  messageService.setPathDebug({ enabled: debugPathsCheckbox.checked });
+
+  if (!debugPathsCheckbox.checked) {
+    renderDebugPaths([]);
+  }
+}
+
+pheromonesCheckbox.onchange = (e) => {
+  messageService.setPheromoneLayer({ enabled: pheromonesCheckbox.checked });
+
+  if (!pheromonesCheckbox.checked) {
+    gameContainer.select('.pheromones').selectAll('.pheromone').remove();
+  }
+}
+
+function updateRules() {
+  messageService.setRules({
+    criticalHits: criticalHitsCheckbox.checked,
+    flanking: flankingCheckbox.checked,
+    regeneration: regenerationCheckbox.checked,
+    baseHealing: baseHealingCheckbox.checked,
+    metabolism: metabolismCheckbox.checked,
+  });
+}
+
+criticalHitsCheckbox.onchange = updateRules;
+flankingCheckbox.onchange = updateRules;
+regenerationCheckbox.onchange = updateRules;
+baseHealingCheckbox.onchange = updateRules;
+metabolismCheckbox.onchange = updateRules;
+
+function setBaseProduction({ count, queue }) {
+  if (selectedBaseId === null) {
+    console.log("Click a home base first");
+    return;
+  }
+
+  messageService.setBaseProduction({
+    homeBaseId: selectedBaseId,
+    genomeSource: Number(genomeSourceSelect.value),
+    count,
+    queue,
+  });
+}
+
+hatchButton.onclick = (e) => {
+  setBaseProduction({ count: Number(hatchCountInput.value), queue: false });
+}
+
+queueHatchButton.onclick = (e) => {
+  setBaseProduction({ count: Number(hatchCountInput.value), queue: true });
+}
+
+stopHatchingButton.onclick = (e) => {
+  setBaseProduction({ count: -1, queue: false });
+}
+
+forageButton.onclick = (e) => {
+  messageService.selectedForageCommand();
+}
+
+queueHarvestButton.onclick = (e) => {
+  messageService.queueHarvestCommand();
+}
+
+queueReturnButton.onclick = (e) => {
+  messageService.queueReturnToBaseCommand();
+}
+
+holdPositionButton.onclick = (e) => {
+  messageService.selectedHoldPositionCommand();
+}
+
+// Right clicking an entity. Holding shift adds to the end of the selected
+// beetles' orders instead of replacing them.
+function interactCommand(targetId) {
+  if (orderSelect.value === 'guard') {
+    messageService.selectedGuardCommand({ targetId })
+  }
+  else if (orderSelect.value === 'follow') {
+    messageService.selectedFollowCommand({ targetId })
+  }
+  else if (shiftKeyDown) {
+    messageService.queueInteractCommand({ targetId })
+  }
+  else {
+    messageService.selectedInteractCommand({ targetId })
+  }
+}
+
+// Right clicking the ground
+function moveCommand(position) {
+  if (orderSelect.value === 'attack-move') {
+    messageService.selectedAttackMoveCommand(position);
+  }
+  else if (orderSelect.value === 'patrol') {
+    messageService.selectedPatrolCommand(position);
+  }
+  else if (shiftKeyDown) {
+    messageService.queueMoveCommand(position);
+  }
+  else {
+    messageService.selectedMoveCommand(position);
+  }
+}
+
+mapElitesButton.onclick = (e) => {
+  genotypeChart.reset();
+  messageService.runMapElites({
+    descriptor: parseInt(descriptorSelect.value),
+  });
 }
 
 function renderBackground() {
@@ -200,11 +441,13 @@ function renderBackground() {
     .attr('class', 'background')
     .on('contextmenu', (d) => {
       d3.event.preventDefault();
-      messageService.selectedMoveCommand({
+      const position = {
         // accounts for where the canvas is on the page
         x: d3.event.clientX - canvasRect.left,
         y: d3.event.clientY - canvasRect.top,
-      });
+      };
+
+      moveCommand(position);
     })
     .on('mousedown', (d) => {
       dragging = true;
@@ -236,9 +479,63 @@ function renderBackground() {
     .attr('fill',   '#c98c5a')
 }
 
+function renderWorld(world) {
+  worldSize = { width: world.getWidth(), height: world.getHeight() };
+
+  const worldContainer = gameContainer.select('.world')
+
+  const boundsUpdate = worldContainer.selectAll('.world__bounds')
+    .data([world])
+
+  boundsUpdate.enter()
+    .append('rect')
+      .attr('class', 'world__bounds')
+      .attr('fill', 'none')
+      .attr('stroke', '#4a2c12')
+      .attr('stroke-width', 4)
+      .attr('pointer-events', 'none')
+    .merge(boundsUpdate)
+      .attr('width', (d) => d.getWidth())
+      .attr('height', (d) => d.getHeight())
+      // wrapping edges are drawn dashed since beetles can pass through them
+      .attr('stroke-dasharray', (d) => {
+        return d.getBoundary() === messages.Boundary.WRAP ? '12,8' : null;
+      })
+
+  const wallUpdate = worldContainer.selectAll('.wall')
+    .data(world.getWallsList())
+
+  wallUpdate.enter()
+    .append('polygon')
+      .attr('class', 'wall')
+      .attr('fill', '#5b3a1d')
+      .attr('stroke', '#3a2410')
+      .attr('stroke-width', 2)
+      .attr('pointer-events', 'none')
+    .merge(wallUpdate)
+      .attr('points', (d) => {
+        return d.getPointsList().map((p) => p.getX()+','+p.getY()).join(' ');
+      })
+
+  wallUpdate.exit().remove();
+}
+
+const GENOME_SOURCE_LABELS = ['Pool', 'Cross'];
+
+function productionText(base) {
+  return base.getProductionList()
+    .map((order) => {
+      const label = GENOME_SOURCE_LABELS[order.getGenomeSource()];
+      const remaining = order.getRemaining();
+      return label + ' x' + (remaining === 0 ? '\u221e' : remaining);
+    })
+    .join(', ');
+}
+
 function renderHomeBases(bases) {
   const baseWidth = 128;
   const baseHeight = baseWidth;
+  const progressHeight = 8;
 
   const baseUpdate = gameContainer.selectAll('.base')
     .data(bases)
@@ -246,9 +543,12 @@ function renderHomeBases(bases) {
   const baseEnter = baseUpdate.enter()
     .append('g')
       .attr('class', 'base')
+      .on('click', (d) => {
+        selectedBaseId = d.getId();
+      })
       .on('contextmenu', (d) => {
         d3.event.preventDefault();
-        messageService.selectedInteractCommand({ targetId: d.getId() })
+        interactCommand(d.getId());
       })
 
   const mainArea = baseEnter
@@ -265,6 +565,23 @@ function renderHomeBases(bases) {
       .attr('fill', '#eeeeee')
       .attr('font-family', 'Helvetica')
 
+  baseEnter
+    .append('text')
+      .attr('class', 'base__production')
+      .attr('text-anchor', 'middle')
+      .attr('y', baseHeight / 2 - progressHeight - 10)
+      .attr('font-size', 12)
+      .attr('fill', '#eeeeee')
+      .attr('font-family', 'Helvetica')
+
+  baseEnter
+    .append('rect')
+      .attr('class', 'base__hatch-progress')
+      .attr('x', -(baseWidth / 2))
+      .attr('y', baseHeight / 2 - progressHeight)
+      .attr('height', progressHeight)
+      .attr('fill', '#e8c170')
+
   baseUpdate
       .attr('transform', (d) => {
         return 'translate('+d.getX()+', '+d.getY()+')';
@@ -272,6 +589,16 @@ function renderHomeBases(bases) {
     .select('.base__text')
       .text((d) => d.getFoodStoredAmount())
 
+  baseUpdate.select('.base__production')
+      .text(productionText)
+
+  baseUpdate.select('.base__hatch-progress')
+      .attr('width', (d) => baseWidth * Math.min(d.getHatchProgress(), 1))
+
+  baseUpdate.select('.base__main-area')
+      .attr('stroke', (d) => d.getId() === selectedBaseId ? '#eeeeee' : 'none')
+      .attr('stroke-width', 4)
+
   mainArea 
       .attr('x', -(baseWidth / 2))
       .attr('y', -(baseHeight / 2))
@@ -282,6 +609,11 @@ function renderHomeBases(bases) {
   baseUpdate.exit().remove();
 }
 
+const FOOD_YIELD_COLORS = d3.scaleLinear()
+  .domain([1, 8])
+  .range(['#efc85d', '#b5651d'])
+  .clamp(true);
+
 function renderFoodSources(foods) {
   const width = 64;
   const height = width;
@@ -294,7 +626,7 @@ function renderFoodSources(foods) {
       .attr('class', 'food')
       .on('contextmenu', (d) =>{
         d3.event.preventDefault();
-        messageService.selectedInteractCommand({ targetId: d.getId() })
+        interactCommand(d.getId());
       })
 
   const mainArea = enter
@@ -322,7 +654,11 @@ function renderFoodSources(foods) {
       .attr('y', -(height / 2))
       .attr('width', width)
       .attr('height', height)
-      .attr('fill', '#efc85d')
+      .attr('fill', (d) => FOOD_YIELD_COLORS(d.getFoodYield()))
+
+  // richer food is darker
+  update.select('.food__main-area')
+      .attr('fill', (d) => FOOD_YIELD_COLORS(d.getFoodYield()))
 
   update.exit().remove();
 }
@@ -349,7 +685,7 @@ function renderBeetles(data) {
       })
       .on('contextmenu', (d) => {
         d3.event.preventDefault();
-        messageService.selectedInteractCommand({ targetId: d.getId() })
+        interactCommand(d.getId());
       })
 
   const head = beetleEnter
@@ -369,6 +705,11 @@ function renderBeetles(data) {
       .attr('stroke', 'lightgreen')
       .attr('visibility', 'hidden')
 
+  beetleEnter
+    .append('g')
+      .attr('class', 'beetle__effects')
+      .attr('pointer-events', 'none')
+
   //beetleEnter
   //  .append('text')
   //    .attr('class', 'beetle__text')
@@ -423,6 +764,8 @@ function renderBeetles(data) {
       .attr('visibility', (d) => d.getSelected() ? 'visible' : 'hidden')
       .attr('transform', (d) => 'rotate('+(-d.getAngle() * DEGREES_PER_RADIAN)+')')
 
+  renderStatusEffects(beetleUpdate.select('.beetle__effects'));
+
   //textUpdate
   //    .attr('transform', (d) => 'rotate('+(-d.getAngle() * DEGREES_PER_RADIAN)+')')
   //    .attr('x', (d) => d.getBodyLength() / 2)
@@ -432,6 +775,115 @@ function renderBeetles(data) {
   beetleUpdate.exit().remove();
 }
 
+// A row of icons above each beetle, kept upright whichever way it's facing
+function renderStatusEffects(effectsUpdate) {
+  const iconSpacing = 12;
+
+  effectsUpdate
+      .attr('transform', (d) => {
+        return 'rotate('+(-d.getAngle() * DEGREES_PER_RADIAN)+') translate(0, -30)';
+      })
+
+  const iconUpdate = effectsUpdate.selectAll('.beetle__effect')
+    .data((d) => d.getStatusEffectsList())
+
+  iconUpdate.enter()
+    .append('text')
+      .attr('class', 'beetle__effect')
+      .attr('text-anchor', 'middle')
+      .attr('font-size', 12)
+      .attr('font-weight', 'bold')
+      .attr('font-family', 'Helvetica')
+    .merge(iconUpdate)
+      .attr('x', (d, i, nodes) => (i - (nodes.length - 1) / 2) * iconSpacing)
+      .attr('fill', (d) => STATUS_EFFECT_ICONS[d.getKind()].color)
+      .text((d) => {
+        const symbol = STATUS_EFFECT_ICONS[d.getKind()].symbol;
+        return d.getStacks() > 1 ? symbol + d.getStacks() : symbol;
+      })
+
+  iconUpdate.exit().remove();
+}
+
+function renderDebugPaths(paths) {
+  const update = gameContainer.select('.debug-paths')
+    .selectAll('.debug-path')
+    .data(paths)
+
+  update.enter()
+    .append('polyline')
+      .attr('class', 'debug-path')
+      .attr('fill', 'none')
+      .attr('stroke', '#1c1c1c')
+      .attr('stroke-width', 1)
+      .attr('stroke-dasharray', '4,4')
+      .attr('pointer-events', 'none')
+    .merge(update)
+      .attr('points', (d) => {
+        return d.getPointsList().map((p) => p.getX()+','+p.getY()).join(' ');
+      })
+
+  update.exit().remove();
+}
+
+function renderPheromones(heatmap) {
+  const cellWidth = worldSize.width / heatmap.getWidth();
+  const cellHeight = worldSize.height / heatmap.getHeight();
+  const cells = heatmap.getCellsList();
+  const maxValue = d3.max(cells, (d) => d.getValue());
+
+  const update = gameContainer.select('.pheromones')
+    .selectAll('.pheromone')
+    .data(cells)
+
+  update.enter()
+    .append('rect')
+      .attr('class', 'pheromone')
+      .attr('fill', '#3f8f3f')
+      .attr('pointer-events', 'none')
+    .merge(update)
+      .attr('x', (d) => d.getX() * cellWidth)
+      .attr('y', (d) => d.getY() * cellHeight)
+      .attr('width', cellWidth)
+      .attr('height', cellHeight)
+      .attr('fill-opacity', (d) => 0.6 * d.getValue() / maxValue)
+
+  update.exit().remove();
+}
+
+// Lines from each selected beetle through everywhere its orders will take it
+function renderWaypoints(beetles) {
+  const selected = beetles.filter((b) => {
+    return b.getSelected() &&
+      b.getCommandsList().some((c) => c.hasPosition());
+  });
+
+  const update = gameContainer.select('.waypoints')
+    .selectAll('.waypoint-line')
+    .data(selected)
+
+  update.enter()
+    .append('polyline')
+      .attr('class', 'waypoint-line')
+      .attr('fill', 'none')
+      .attr('stroke', 'lightgreen')
+      .attr('stroke-width', 1)
+      .attr('pointer-events', 'none')
+    .merge(update)
+      .attr('points', (d) => {
+        const points = [d.getX()+','+d.getY()];
+        for (const command of d.getCommandsList()) {
+          if (command.hasPosition()) {
+            const p = command.getPosition();
+            points.push(p.getX()+','+p.getY());
+          }
+        }
+        return points.join(' ');
+      })
+
+  update.exit().remove();
+}
+
 function calcHeadRadius(bodyWidth) {
   return bodyWidth / 3;
 }
@@ -441,9 +893,164 @@ function handleStateUpdate(gameState) {
   const bases = gameState.getHomeBasesList();
   const foods = gameState.getFoodSourcesList();
 
+  if (gameState.hasWorld()) {
+    renderWorld(gameState.getWorld());
+  }
   renderHomeBases(bases);
   renderFoodSources(foods);
+  renderWaypoints(beetles);
   renderBeetles(beetles);
+  renderTeams(gameState.getTeamsList());
+}
+
+const PLAYER_TEAM_ID = 0;
+// must match SIMULATION_PERIOD_MS in utils.rs
+const SIMULATION_PERIOD_MS = 20;
+const RELATION_LABELS = {
+  [messages.Relation.HOSTILE]: 'Hostile',
+  [messages.Relation.NEUTRAL]: 'Neutral',
+  [messages.Relation.ALLIED]: 'Allied',
+};
+
+function relationToPlayer(team) {
+  const relation = team.getRelationsList()
+    .find((relation) => relation.getTeamId() === PLAYER_TEAM_ID);
+  return relation ? relation.getRelation() : messages.Relation.HOSTILE;
+}
+
+function teamColor(team) {
+  const color = team.getColor();
+  return 'rgba('+color.getR()+','+color.getG()+','+color.getB()+','+color.getA()+')';
+}
+
+// One row per team. The player can change how every other team feels about
+// them, and hand over whichever base they last clicked.
+function renderTeams(teams) {
+  const update = d3.select(scoreboard).selectAll('.scoreboard__team')
+    .data(teams, (d) => d.getId())
+
+  const enter = update.enter()
+    .append('tr')
+      .attr('class', 'scoreboard__team')
+
+  enter.append('td')
+    .append('span')
+      .attr('class', 'scoreboard__swatch')
+  enter.append('td')
+      .attr('class', 'scoreboard__name')
+  enter.append('td')
+      .attr('class', 'scoreboard__score')
+
+  enter.append('td')
+    .filter((d) => d.getId() !== PLAYER_TEAM_ID)
+    .append('select')
+      .attr('class', 'scoreboard__relation')
+      .on('change', function(d) {
+        messageService.setTeamRelation({
+          teamId: PLAYER_TEAM_ID,
+          otherTeamId: d.getId(),
+          relation: Number(this.value),
+        });
+      })
+    .selectAll('option')
+    .data(Object.keys(RELATION_LABELS))
+    .enter()
+    .append('option')
+      .attr('value', (d) => d)
+      .text((d) => RELATION_LABELS[d])
+
+  enter.append('td')
+    .append('input')
+      .attr('type', 'button')
+      .attr('value', 'Give Base')
+      .on('click', (d) => {
+        if (selectedBaseId === null) {
+          console.log("Click a home base first");
+          return;
+        }
+        messageService.setHomeBaseTeam({ homeBaseId: selectedBaseId, teamId: d.getId() });
+      })
+
+  const merged = enter.merge(update)
+
+  merged.select('.scoreboard__swatch')
+      .style('background-color', teamColor)
+  merged.select('.scoreboard__name')
+      .text((d) => d.getName() + ' (' + d.getHomeBaseIdsList().length + ' bases)')
+  merged.select('.scoreboard__score')
+      .text((d) => d.getScore())
+  merged.select('.scoreboard__relation')
+      .property('value', relationToPlayer)
+
+  update.exit().remove();
+}
+
+// Who won, and how everyone finished up
+function renderMatchResult(matchEnded) {
+  const seconds = (matchEnded.getTicks() * SIMULATION_PERIOD_MS / 1000).toFixed(1);
+  const teams = matchEnded.getTeamsList();
+  const winners = teams.filter((team) => team.getWon()).map((team) => team.getName());
+
+  const lines = [
+    'Match over after ' + seconds + 's (' + matchEnded.getCondition() + '): ' +
+      (winners.length > 0 ? winners.join(', ') + ' won' : 'nobody won'),
+  ];
+
+  for (const team of teams) {
+    lines.push(team.getName() + ': ' + team.getBeetlesAlive() + ' beetles, ' +
+      team.getFoodStored() + ' food, ' + team.getHomeBases() + ' bases, score ' +
+      team.getScore());
+  }
+
+  matchResult.textContent = lines.join('\n');
+}
+
+// Why a fight simulation stopped, and what it cost everyone
+function renderFightResult(fightEnded) {
+  const seconds = (fightEnded.getTicks() * SIMULATION_PERIOD_MS / 1000).toFixed(1);
+  const teams = fightEnded.getTeamsList();
+  const winners = teams.filter((team) => team.getWon()).map((team) => team.getName());
+
+  const lines = [
+    'Fight over after ' + seconds + 's (' + fightEnded.getEndReason() + '): ' +
+      (winners.length > 0 ? winners.join(', ') + ' won' : 'nobody won'),
+  ];
+
+  for (const team of teams) {
+    lines.push(team.getName() + ': ' + team.getSurvivors() + ' beetles, ' +
+      team.getDamageDealt() + ' damage dealt, ' + team.getDamageTaken() + ' taken');
+  }
+
+  matchResult.textContent = lines.join('\n');
+}
+
+function handleEvents(events) {
+  for (const event of events) {
+    if (event.hasBeetleDied()) {
+      renderDeath(event.getBeetleDied());
+    }
+  }
+}
+
+// A ring that spreads out and fades where the beetle was
+function renderDeath(beetleDied) {
+  const position = beetleDied.getPosition();
+
+  gameContainer.select('.deaths')
+    .append('circle')
+      .attr('class', 'death')
+      .attr('cx', position.getX())
+      .attr('cy', position.getY())
+      .attr('r', 5)
+      .attr('fill', 'none')
+      .attr('stroke', '#8b1a1a')
+      .attr('stroke-width', 3)
+      .attr('pointer-events', 'none')
+    .transition()
+      .duration(600)
+      .attr('r', 30)
+      .attr('stroke-opacity', 0)
+      .remove()
 }
 
 function handleChartsIncremental(msg) {
@@ -467,6 +1074,7 @@ function handleChartsIncremental(msg) {
     msg.getAvgMandibleSharpness(),
     msg.getAvgBodyWidth(),
     msg.getAvgBodyLength(),
+    msg.getAvgSmellSensitivity(),
   ]
 
   genotypeChart.addPoints({
@@ -476,6 +1084,20 @@ function handleChartsIncremental(msg) {
   geneBarChart.update({ data: geneVals })
 }
 
+function handleHeatmap(msg) {
+  archiveChart.update({
+    width: msg.getWidth(),
+    height: msg.getHeight(),
+    xLabel: msg.getXLabel(),
+    yLabel: msg.getYLabel(),
+    cells: msg.getCellsList().map((cell) => ({
+      x: cell.getX(),
+      y: cell.getY(),
+      value: cell.getValue(),
+    })),
+  })
+}
+
 function createSelecticle() {
   const selecticle = two.makeRectangle(0, 0, 50, 50);
   selecticle.stroke = 'black';
@@ -54,6 +54,85 @@ export class MessageService {
     this.socket.send(uiMessage.serializeBinary());
   }
 
+  selectedAttackMoveCommand({ x, y }) {
+    const message = new messages.SelectedAttackMoveCommand();
+    message.setX(x);
+    message.setY(y);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSelectedAttackMoveCommand(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  selectedPatrolCommand({ x, y }) {
+    const message = new messages.SelectedPatrolCommand();
+    message.setX(x);
+    message.setY(y);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSelectedPatrolCommand(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  selectedGuardCommand({ targetId }) {
+    const message = new messages.SelectedGuardCommand();
+    message.setTargetId(targetId);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSelectedGuardCommand(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  selectedFollowCommand({ targetId }) {
+    const message = new messages.SelectedFollowCommand();
+    message.setTargetId(targetId);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSelectedFollowCommand(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  selectedHoldPositionCommand() {
+    const message = new messages.SelectedHoldPositionCommand();
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSelectedHoldPositionCommand(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  selectedForageCommand() {
+    const message = new messages.SelectedForageCommand();
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSelectedForageCommand(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  queueMoveCommand({ x, y }) {
+    const message = new messages.QueueMoveCommand();
+    message.setX(x);
+    message.setY(y);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setQueueMoveCommand(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  queueInteractCommand({ targetId }) {
+    const message = new messages.QueueInteractCommand();
+    message.setTargetId(targetId);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setQueueInteractCommand(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  queueHarvestCommand() {
+    const message = new messages.QueueHarvestCommand();
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setQueueHarvestCommand(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  queueReturnToBaseCommand() {
+    const message = new messages.QueueReturnToBaseCommand();
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setQueueReturnToBaseCommand(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
   deselectAllBeetles() {
     const message = new messages.DeselectAllBeetles();
     const uiMessage = new messages.UiMessage();
@@ -82,13 +161,40 @@ export class MessageService {
     this.socket.send(uiMessage.serializeBinary());
   }
 
-  runFoodGA() {
+  runFoodGA({ scarceFood }) {
     const message = new messages.RunFoodGA();
+    message.setScarceFood(scarceFood);
     const uiMessage = new messages.UiMessage();
     uiMessage.setRunFoodGa(message);
     this.socket.send(uiMessage.serializeBinary());
   }
 
+  startAiMatch({ difficulty, armySize, evolve }) {
+    const message = new messages.StartAiMatch();
+    message.setDifficulty(difficulty);
+    message.setArmySize(armySize);
+    message.setEvolve(evolve);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setStartAiMatch(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  loadScenario({ name }) {
+    const message = new messages.LoadScenario();
+    message.setName(name);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setLoadScenario(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  setMatchPaused({ paused }) {
+    const message = new messages.SetMatchPaused();
+    message.setPaused(paused);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSetMatchPaused(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
   runFightSimulation() {
     const message = new messages.RunFightSimulation();
     const uiMessage = new messages.UiMessage();
@@ -96,10 +202,80 @@ export class MessageService {
     this.socket.send(uiMessage.serializeBinary());
   }
 
-  createFormation() {
+  createFormation({ shape, facingX, facingY, spacing }) {
     const message = new messages.CreateFormation();
+    message.setShape(shape);
+    message.setFacingX(facingX);
+    message.setFacingY(facingY);
+    message.setSpacing(spacing);
     const uiMessage = new messages.UiMessage();
     uiMessage.setCreateFormation(message);
     this.socket.send(uiMessage.serializeBinary());
   }
+
+  runMapElites({ descriptor }) {
+    const message = new messages.RunMapElites();
+    message.setDescriptor(descriptor);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setRunMapElites(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  setPathDebug({ enabled }) {
+    const message = new messages.SetPathDebug();
+    message.setEnabled(enabled);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSetPathDebug(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  setRules({ criticalHits, flanking, regeneration, baseHealing, metabolism }) {
+    const message = new messages.SetRules();
+    message.setCriticalHits(criticalHits);
+    message.setFlanking(flanking);
+    message.setRegeneration(regeneration);
+    message.setBaseHealing(baseHealing);
+    message.setMetabolism(metabolism);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSetRules(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  setBaseProduction({ homeBaseId, genomeSource, count, queue }) {
+    const message = new messages.SetBaseProduction();
+    message.setHomeBaseId(homeBaseId);
+    message.setGenomeSource(genomeSource);
+    message.setCount(count);
+    message.setQueue(queue);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSetBaseProduction(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  setTeamRelation({ teamId, otherTeamId, relation }) {
+    const message = new messages.SetTeamRelation();
+    message.setTeamId(teamId);
+    message.setOtherTeamId(otherTeamId);
+    message.setRelation(relation);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSetTeamRelation(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  setHomeBaseTeam({ homeBaseId, teamId }) {
+    const message = new messages.SetHomeBaseTeam();
+    message.setHomeBaseId(homeBaseId);
+    message.setTeamId(teamId);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSetHomeBaseTeam(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
+
+  setPheromoneLayer({ enabled }) {
+    const message = new messages.SetPheromoneLayer();
+    message.setEnabled(enabled);
+    const uiMessage = new messages.UiMessage();
+    uiMessage.setSetPheromoneLayer(message);
+    this.socket.send(uiMessage.serializeBinary());
+  }
 }